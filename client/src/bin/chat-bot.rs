@@ -0,0 +1,101 @@
+//! Runs one of the bundled bots as an ordinary client.
+//!
+//! `chat-bot <echo|dice|reminder> [--server <addr>] [--name <name>]
+//! [--daily HH:MM <text>]...` joins the room and runs until interrupted.
+//! `--daily` (reminder bot only) posts `<text>` to the room every day at that
+//! local time, e.g. `--daily 09:45 "standup in 15 minutes"`.
+
+use std::io;
+
+use chrono::{Local, NaiveTime};
+use client::{
+    bind_for,
+    bot::{DiceBot, EchoBot, ReminderBot, run_bot},
+    resolve_server_addr,
+};
+
+const BOTS: [&str; 3] = ["echo", "dice", "reminder"];
+const USAGE: &str = "usage: chat-bot <echo|dice|reminder> [--server <addr>] [--name <name>] \
+                     [--daily HH:MM <text>]...";
+
+fn invalid(reason: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, reason.into())
+}
+
+fn value(flag: &str, value: Option<String>) -> io::Result<String> {
+    value.ok_or_else(|| invalid(format!("{flag} needs a value")))
+}
+
+fn daily_time(value: &str) -> io::Result<NaiveTime> {
+    value
+        .split_once(':')
+        .and_then(|(h, m)| NaiveTime::from_hms_opt(h.parse().ok()?, m.parse().ok()?, 0))
+        .ok_or_else(|| invalid(format!("--daily: invalid time {value:?}, expected HH:MM")))
+}
+
+struct Options {
+    kind: String,
+    server: String,
+    name: Option<String>,
+    daily: Vec<(NaiveTime, String)>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> io::Result<Options> {
+    let kind = args.next().ok_or_else(|| invalid("which bot?"))?;
+    if !BOTS.contains(&kind.as_str()) {
+        return Err(invalid(format!("unknown bot {kind:?}")));
+    }
+    let mut options = Options {
+        kind,
+        server: "127.0.0.1".to_string(),
+        name: None,
+        daily: Vec::new(),
+    };
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--server" => options.server = value(&flag, args.next())?,
+            "--name" => options.name = Some(value(&flag, args.next())?),
+            "--daily" => {
+                let at = daily_time(&value(&flag, args.next())?)?;
+                options.daily.push((at, value(&flag, args.next())?));
+            }
+            _ => return Err(invalid(format!("unknown argument {flag}"))),
+        }
+    }
+    if !options.daily.is_empty() && options.kind != "reminder" {
+        return Err(invalid("--daily needs the reminder bot"));
+    }
+    Ok(options)
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {e}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    let name = options.name.as_deref().unwrap_or(&options.kind);
+    let server = resolve_server_addr(&options.server).await?;
+    // 端末クライアントと同居できるようにポートは OS に任せる
+    let sock = bind_for(server, 0).await?;
+    eprintln!("{name} joining {server}…");
+
+    match options.kind.as_str() {
+        "echo" => run_bot(sock, server, name, EchoBot).await,
+        "dice" => run_bot(sock, server, name, DiceBot::new()).await,
+        "reminder" => {
+            let now = Local::now().naive_local();
+            let bot = options
+                .daily
+                .into_iter()
+                .fold(ReminderBot::new(), |bot, (at, text)| {
+                    bot.daily(now, at, text)
+                });
+            run_bot(sock, server, name, bot).await
+        }
+        _ => unreachable!("bot names are checked while parsing"),
+    }
+}
@@ -0,0 +1,627 @@
+//! Bots that take part in a room as ordinary clients.
+//!
+//! A [`Bot`] reacts to chat and direct messages from other users, to
+//! `!commands`, and to timers it has set. It never talks to the socket itself:
+//! everything it wants to send goes into an [`Outbox`], which [`run_bot`]
+//! flushes after each call. That keeps bots testable without a server.
+//!
+//! [`run_bot`] joins with a heartbeat, so the room is not disturbed,
+//! acknowledges what it receives and sends another heartbeat every
+//! [`KEEPALIVE_INTERVAL`] so the server does not time the bot out.
+//!
+//! Three bots ship with the client: [`EchoBot`] (`!echo`), [`DiceBot`]
+//! (`!roll`) and [`ReminderBot`] (`!remind`, `!every`, `!reminders`,
+//! `!cancel`), which can also post fixed daily reminders such as standups.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    fmt::Write as _,
+    io,
+    net::SocketAddr,
+    time::Duration,
+};
+
+use chrono::{Local, NaiveDateTime, NaiveTime, TimeDelta};
+use protocol::{MessageKind, MessageProtocol};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use tokio::{
+    net::UdpSocket,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+use crate::BUFFER_SIZE;
+
+/// Marks a chat line as a command, e.g. `!roll 2d6`.
+pub const COMMAND_PREFIX: char = '!';
+/// How often a bot proves to the server it is still there.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A `!name args` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command<'a> {
+    pub name: &'a str,
+    /// Everything after the name, trimmed.
+    pub args: &'a str,
+}
+
+impl<'a> Command<'a> {
+    pub fn parse(body: &'a str) -> Option<Self> {
+        let line = body.trim().strip_prefix(COMMAND_PREFIX)?;
+        let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        (!name.is_empty()).then(|| Self {
+            name,
+            args: args.trim(),
+        })
+    }
+}
+
+/// Messages and timers a bot wants to send, collected during one call.
+#[derive(Debug)]
+pub struct Outbox {
+    name: String,
+    messages: Vec<MessageProtocol>,
+    timers: Vec<(Duration, u64)>,
+}
+
+impl Outbox {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            messages: Vec::new(),
+            timers: Vec::new(),
+        }
+    }
+
+    /// The bot's user name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Post to the room.
+    pub fn say(&mut self, body: impl Into<String>) {
+        self.messages
+            .push(MessageProtocol::new(self.name.as_str(), body));
+    }
+
+    /// Send a direct message to `to`.
+    pub fn whisper(&mut self, to: &str, body: impl Into<String>) {
+        self.messages
+            .push(MessageProtocol::direct(self.name.as_str(), to, body));
+    }
+
+    /// Answer `message`: privately if it was a direct message, otherwise as
+    /// a reply to it in the room.
+    pub fn reply(&mut self, message: &MessageProtocol, body: impl Into<String>) {
+        let answer = match message.kind {
+            MessageKind::Direct => {
+                MessageProtocol::direct(self.name.as_str(), message.user_name.as_str(), body)
+            }
+            _ => MessageProtocol::reply(self.name.as_str(), message.id, body),
+        };
+        self.messages.push(answer);
+    }
+
+    /// Call [`Bot::on_timer`] with `token` once `after` has passed.
+    pub fn set_timer(&mut self, after: Duration, token: u64) {
+        self.timers.push((after, token));
+    }
+
+    pub fn messages(&self) -> &[MessageProtocol] {
+        &self.messages
+    }
+
+    pub fn timers(&self) -> &[(Duration, u64)] {
+        &self.timers
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty() && self.timers.is_empty()
+    }
+
+    fn take(&mut self) -> (Vec<MessageProtocol>, Vec<(Duration, u64)>) {
+        (
+            std::mem::take(&mut self.messages),
+            std::mem::take(&mut self.timers),
+        )
+    }
+}
+
+/// Behaviour of a bot; every method defaults to doing nothing.
+pub trait Bot: Send {
+    /// Called once, right after joining.
+    fn on_start(&mut self, _out: &mut Outbox) {}
+
+    /// A chat or direct message from another user that is not a command.
+    fn on_message(&mut self, _message: &MessageProtocol, _out: &mut Outbox) {}
+
+    /// A command from another user; `message` is the frame it arrived in.
+    fn on_command(&mut self, _command: Command<'_>, _message: &MessageProtocol, _out: &mut Outbox) {
+    }
+
+    /// A timer set with [`Outbox::set_timer`] fired.
+    fn on_timer(&mut self, _token: u64, _out: &mut Outbox) {}
+}
+
+/// Hand one received frame to `bot`.
+///
+/// Only stamped chat and direct messages from other users reach the bot, so
+/// it never answers its own messages or their echoes.
+pub fn dispatch<B: Bot + ?Sized>(bot: &mut B, message: &MessageProtocol, out: &mut Outbox) {
+    if !matches!(message.kind, MessageKind::Chat | MessageKind::Direct)
+        || message.id == 0
+        || message.user_name == out.name
+    {
+        return;
+    }
+    match Command::parse(&message.body) {
+        Some(command) => bot.on_command(command, message, out),
+        None => bot.on_message(message, out),
+    }
+}
+
+/// Join the room at `server` as `name` and run `bot` until the socket fails.
+pub async fn run_bot<B: Bot>(
+    sock: UdpSocket,
+    server: SocketAddr,
+    name: &str,
+    mut bot: B,
+) -> io::Result<()> {
+    let mut out = Outbox::new(name);
+    // ハートビートで参加すれば部屋には何も流れない
+    send(&sock, server, &MessageProtocol::heartbeat(name)).await?;
+    bot.on_start(&mut out);
+
+    let mut timers: BinaryHeap<Reverse<(Instant, u64)>> = BinaryHeap::new();
+    // 参加のハートビートを送ったばかりなので、最初の送信は一周期後
+    let mut keepalive = time::interval_at(Instant::now() + KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL);
+    keepalive.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut buf = [0u8; BUFFER_SIZE];
+    loop {
+        let (messages, set) = out.take();
+        for message in messages {
+            send(&sock, server, &message).await?;
+        }
+        let now = Instant::now();
+        // 時計の範囲を超える待ち時間で止まらないよう、張れないタイマーは捨てる
+        timers.extend(set.into_iter().filter_map(|(after, token)| {
+            let at = now.checked_add(after);
+            if at.is_none() {
+                eprintln!(
+                    "not setting timer {token}: {} is too far away",
+                    format_duration(after)
+                );
+            }
+            at.map(|at| Reverse((at, token)))
+        }));
+
+        let next_timer = timers.peek().map(|Reverse((at, _))| *at);
+        tokio::select! {
+            received = sock.recv_from(&mut buf) => {
+                let (len, _) = received?;
+                // 解析できないデータグラムは読み捨てる
+                let Ok(message) = MessageProtocol::deserialize(&buf[..len]) else {
+                    continue;
+                };
+                if message.kind == MessageKind::Chat && message.id != 0 && message.user_name != name {
+                    send(&sock, server, &MessageProtocol::ack(name, message.id)).await?;
+                }
+                dispatch(&mut bot, &message, &mut out);
+            }
+            _ = time::sleep_until(next_timer.unwrap_or(now)), if next_timer.is_some() => {
+                if let Some(Reverse((_, token))) = timers.pop() {
+                    bot.on_timer(token, &mut out);
+                }
+            }
+            // ハートビートは活動として数えられ、タイムアウト後なら参加し直す
+            _ = keepalive.tick() => {
+                send(&sock, server, &MessageProtocol::heartbeat(name)).await?;
+            }
+        }
+    }
+}
+
+// 大きすぎて符号化できない発言は、ボットを止めずに捨てる
+async fn send(sock: &UdpSocket, server: SocketAddr, message: &MessageProtocol) -> io::Result<()> {
+    match message.serialize() {
+        Ok(frame) => sock.send_to(&frame, server).await.map(|_| ()),
+        Err(e) => {
+            eprintln!("not sending {message}: {e}");
+            Ok(())
+        }
+    }
+}
+
+/// Parse `90s`, `15m`, `2h`, `1d` or combinations such as `1h30m`.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in input.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return None,
+        };
+        let value: u64 = std::mem::take(&mut digits).parse().ok()?;
+        total = total.checked_add(value.checked_mul(unit)?)?;
+    }
+    (digits.is_empty() && total > 0).then(|| Duration::from_secs(total))
+}
+
+/// Render a duration the way [`parse_duration`] reads it, e.g. `1h30m`.
+pub fn format_duration(duration: Duration) -> String {
+    let mut secs = duration.as_secs();
+    let mut text = String::new();
+    for (unit, size) in [('d', 86_400), ('h', 3_600), ('m', 60), ('s', 1)] {
+        if secs >= size {
+            let _ = write!(text, "{}{unit}", secs / size);
+            secs %= size;
+        }
+    }
+    if text.is_empty() {
+        text.push_str("0s");
+    }
+    text
+}
+
+/// Time from `now` until the next `at` on the local clock (a full day if
+/// `at` is exactly now).
+pub fn until_daily(now: NaiveDateTime, at: NaiveTime) -> Duration {
+    let mut next = now.date().and_time(at);
+    if next <= now {
+        next += TimeDelta::days(1);
+    }
+    (next - now).to_std().unwrap_or_default()
+}
+
+/// Repeats `!echo <text>` back to the room, and direct messages to their sender.
+#[derive(Debug, Default)]
+pub struct EchoBot;
+
+impl Bot for EchoBot {
+    fn on_message(&mut self, message: &MessageProtocol, out: &mut Outbox) {
+        if message.kind == MessageKind::Direct {
+            out.reply(message, message.body.as_str());
+        }
+    }
+
+    fn on_command(&mut self, command: Command<'_>, message: &MessageProtocol, out: &mut Outbox) {
+        if command.name == "echo" && !command.args.is_empty() {
+            out.reply(message, command.args);
+        }
+    }
+}
+
+/// Dice a single `!roll` may throw.
+pub const MAX_DICE: u32 = 100;
+/// Faces of the largest die.
+pub const MAX_SIDES: u32 = 1000;
+/// Largest modifier, either way, a roll may add.
+pub const MAX_MODIFIER: i64 = 1_000_000;
+
+/// Answers `!roll [N]d<sides>[+K|-K]` (default `1d6`) with each die and the total.
+pub struct DiceBot {
+    rng: StdRng,
+}
+
+impl DiceBot {
+    pub fn new() -> Self {
+        Self {
+            rng: StdRng::from_os_rng(),
+        }
+    }
+
+    /// A bot whose rolls are the same on every run.
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn roll(&mut self, spec: &str) -> Result<String, String> {
+        let Dice {
+            count,
+            sides,
+            modifier,
+        } = Dice::parse(spec).ok_or_else(|| {
+            format!("usage: !roll [N]d<sides>[+K], up to {MAX_DICE}d{MAX_SIDES}{MAX_MODIFIER:+}")
+        })?;
+        let rolls: Vec<i64> = (0..count)
+            .map(|_| i64::from(self.rng.random_range(1..=sides)))
+            .collect();
+        let total = rolls.iter().sum::<i64>() + modifier;
+        let mut shown: Vec<String> = rolls.iter().map(i64::to_string).collect();
+        if modifier != 0 {
+            shown.push(format!("({modifier:+})"));
+        }
+        Ok(format!("{} = {total}", shown.join(" + ")))
+    }
+}
+
+impl Default for DiceBot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bot for DiceBot {
+    fn on_command(&mut self, command: Command<'_>, message: &MessageProtocol, out: &mut Outbox) {
+        if command.name != "roll" {
+            return;
+        }
+        let spec = match command.args {
+            "" => "1d6",
+            args => args,
+        };
+        let answer = match self.roll(spec) {
+            Ok(result) => format!("{} rolled {spec}: {result}", message.user_name),
+            Err(usage) => usage,
+        };
+        out.reply(message, answer);
+    }
+}
+
+// `2d6+1` の各部分
+struct Dice {
+    count: u32,
+    sides: u32,
+    modifier: i64,
+}
+
+impl Dice {
+    fn parse(spec: &str) -> Option<Self> {
+        let spec = spec.trim().to_ascii_lowercase();
+        let (count, rest) = spec.split_once('d')?;
+        let count = match count {
+            "" => 1,
+            count => count.parse().ok()?,
+        };
+        let (sides, modifier) = match rest.find(['+', '-']) {
+            Some(at) => (&rest[..at], rest[at..].parse().ok()?),
+            None => (rest, 0),
+        };
+        let sides = sides.parse().ok()?;
+        ((1..=MAX_DICE).contains(&count)
+            && (2..=MAX_SIDES).contains(&sides)
+            && (-MAX_MODIFIER..=MAX_MODIFIER).contains(&modifier))
+        .then_some(Self {
+            count,
+            sides,
+            modifier,
+        })
+    }
+}
+
+/// Shortest interval `!every` accepts.
+pub const MIN_REPEAT: Duration = Duration::from_secs(60);
+/// Longest delay `!remind` and `!every` accept.
+pub const MAX_REMINDER_DELAY: Duration = Duration::from_secs(365 * 86_400);
+/// Reminders one bot keeps at once.
+pub const MAX_REMINDERS: usize = 100;
+
+/// One pending reminder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reminder {
+    pub body: String,
+    /// Who asked for it; `None` for reminders configured at start-up.
+    pub owner: Option<String>,
+    /// Sent privately to the owner instead of the room.
+    pub private: bool,
+    /// Repeat interval; `None` fires once.
+    pub every: Option<Duration>,
+    /// Local time a daily reminder fires at; such reminders have no `every`.
+    pub daily: Option<NaiveTime>,
+    // 起動時に設定したものの初回までの時間
+    first: Option<Duration>,
+}
+
+/// Posts reminders after a delay, once or repeatedly.
+///
+/// `!remind 15m <text>` reminds the sender once; `!every 1d <text>` posts
+/// `<text>` to the room at that interval. Requests made by direct message are
+/// answered privately. `!reminders` lists them and `!cancel <id>` removes
+/// one of your own. Reminders added with [`ReminderBot::daily`] or
+/// [`ReminderBot::repeat`] before the bot starts cannot be cancelled from chat.
+#[derive(Debug)]
+pub struct ReminderBot {
+    reminders: BTreeMap<u64, Reminder>,
+    next_id: u64,
+    // 毎日のリマインダーの次回を測る時計
+    clock: fn() -> NaiveDateTime,
+}
+
+impl Default for ReminderBot {
+    fn default() -> Self {
+        Self {
+            reminders: BTreeMap::new(),
+            next_id: 0,
+            clock: || Local::now().naive_local(),
+        }
+    }
+}
+
+impl ReminderBot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the local time from `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: fn() -> NaiveDateTime) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Post `body` to the room after `first`, then every `every`.
+    pub fn repeat(mut self, first: Duration, every: Duration, body: impl Into<String>) -> Self {
+        self.add(Reminder {
+            body: body.into(),
+            owner: None,
+            private: false,
+            every: Some(every),
+            daily: None,
+            first: Some(first),
+        });
+        self
+    }
+
+    /// Post `body` to the room every day at `at` local time, e.g. a standup call.
+    ///
+    /// The wait is measured from the clock again after each post, so the
+    /// reminder stays at `at` when the clock changes, e.g. for daylight saving.
+    pub fn daily(mut self, now: NaiveDateTime, at: NaiveTime, body: impl Into<String>) -> Self {
+        self.add(Reminder {
+            body: body.into(),
+            owner: None,
+            private: false,
+            every: None,
+            daily: Some(at),
+            first: Some(until_daily(now, at)),
+        });
+        self
+    }
+
+    pub fn reminders(&self) -> &BTreeMap<u64, Reminder> {
+        &self.reminders
+    }
+
+    fn add(&mut self, reminder: Reminder) -> u64 {
+        self.next_id += 1;
+        self.reminders.insert(self.next_id, reminder);
+        self.next_id
+    }
+
+    fn schedule(
+        &mut self,
+        command: Command<'_>,
+        message: &MessageProtocol,
+        out: &mut Outbox,
+    ) -> Result<String, String> {
+        let usage = || {
+            format!(
+                "usage: !{} <duration, e.g. 15m or 1h30m, up to {}> <text>",
+                command.name,
+                format_duration(MAX_REMINDER_DELAY)
+            )
+        };
+        let (delay, text) = command
+            .args
+            .split_once(char::is_whitespace)
+            .ok_or_else(usage)?;
+        let delay = parse_duration(delay)
+            .filter(|delay| *delay <= MAX_REMINDER_DELAY)
+            .ok_or_else(usage)?;
+        let every = (command.name == "every").then_some(delay);
+        if every.is_some_and(|every| every < MIN_REPEAT) {
+            return Err(format!(
+                "reminders repeat at most every {}",
+                format_duration(MIN_REPEAT)
+            ));
+        }
+        if self.reminders.len() >= MAX_REMINDERS {
+            return Err(format!("already keeping {MAX_REMINDERS} reminders"));
+        }
+        let id = self.add(Reminder {
+            body: text.trim().to_string(),
+            owner: Some(message.user_name.clone()),
+            private: message.kind == MessageKind::Direct,
+            every,
+            daily: None,
+            first: None,
+        });
+        out.set_timer(delay, id);
+        Ok(match every {
+            Some(every) => format!("#{id} every {}", format_duration(every)),
+            None => format!("#{id} in {}", format_duration(delay)),
+        })
+    }
+
+    fn list(&self, user_name: &str) -> String {
+        let visible: Vec<String> = self
+            .reminders
+            .iter()
+            .filter(|(_, r)| !r.private || r.owner.as_deref() == Some(user_name))
+            .map(|(id, r)| {
+                let when = match (r.daily, r.every) {
+                    (Some(at), _) => format!("daily at {}", at.format("%H:%M")),
+                    (None, Some(every)) => format!("every {}", format_duration(every)),
+                    (None, None) => "once".to_string(),
+                };
+                let owner = r.owner.as_deref().unwrap_or("config");
+                format!("#{id} {when} ({owner}): {}", r.body)
+            })
+            .collect();
+        if visible.is_empty() {
+            "no reminders".to_string()
+        } else {
+            visible.join("; ")
+        }
+    }
+
+    fn cancel(&mut self, args: &str, user_name: &str) -> Result<String, String> {
+        let id: u64 = args
+            .trim_start_matches('#')
+            .parse()
+            .map_err(|_| "usage: !cancel <id>".to_string())?;
+        match self.reminders.get(&id) {
+            Some(r) if r.owner.as_deref() == Some(user_name) => {
+                self.reminders.remove(&id);
+                Ok(format!("cancelled #{id}"))
+            }
+            Some(r) if !r.private => Err(format!("#{id} is not yours")),
+            _ => Err(format!("no reminder #{id}")),
+        }
+    }
+}
+
+impl Bot for ReminderBot {
+    fn on_start(&mut self, out: &mut Outbox) {
+        for (id, reminder) in &mut self.reminders {
+            if let Some(first) = reminder.first.take() {
+                out.set_timer(first, *id);
+            }
+        }
+    }
+
+    fn on_command(&mut self, command: Command<'_>, message: &MessageProtocol, out: &mut Outbox) {
+        let answer = match command.name {
+            "remind" | "every" => self.schedule(command, message, out).unwrap_or_else(|e| e),
+            "reminders" => self.list(&message.user_name),
+            "cancel" => self
+                .cancel(command.args, &message.user_name)
+                .unwrap_or_else(|e| e),
+            _ => return,
+        };
+        out.reply(message, answer);
+    }
+
+    fn on_timer(&mut self, token: u64, out: &mut Outbox) {
+        // 取り消されたリマインダーのタイマーは何もしない
+        let Some(reminder) = self.reminders.get(&token) else {
+            return;
+        };
+        match (&reminder.owner, reminder.private) {
+            (Some(owner), true) => out.whisper(owner, reminder.body.as_str()),
+            (Some(owner), false) if reminder.every.is_none() => {
+                out.say(format!("@{owner} {}", reminder.body))
+            }
+            _ => out.say(reminder.body.as_str()),
+        }
+        // 毎日のものは 24 時間後ではなく、いまの時計から次の時刻までを測る
+        let next = match reminder.daily {
+            Some(at) => Some(until_daily((self.clock)(), at)),
+            None => reminder.every,
+        };
+        match next {
+            Some(after) => out.set_timer(after, token),
+            None => {
+                self.reminders.remove(&token);
+            }
+        }
+    }
+}
@@ -1,14 +1,78 @@
-use std::io;
-use tokio::net::UdpSocket;
+use chrono::{Local, TimeZone};
+use protocol::MessageProtocol;
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+use tokio::net::{UdpSocket, lookup_host};
+
+pub mod bot;
+pub mod tui;
 
 /// Default ports & buffer sizes for the demo client.
 pub const SERVER_PORT: u16 = 9001;
 pub const CLIENT_PORT: u16 = 9050;
-pub const BUFFER_SIZE: usize = 1024;
+pub const BUFFER_SIZE: usize = protocol::MAX_BUFFER_SIZE;
+
+/// Render a message as `[HH:MM:SS] <user>: body` using the server timestamp.
+///
+/// Messages the server has not stamped are rendered without the time.
+pub fn format_message(message: &MessageProtocol) -> String {
+    match Local
+        .timestamp_millis_opt(message.timestamp_ms as i64)
+        .single()
+    {
+        Some(time) if message.timestamp_ms != 0 => {
+            format!("[{}] {}", time.format("%H:%M:%S"), message)
+        }
+        _ => message.to_string(),
+    }
+}
+
+/// Resolve a user-supplied server address.
+///
+/// Accepts IPv4 / IPv6 literals (bare or bracketed), host names, and any of
+/// those with an explicit `:port`. Without a port, `SERVER_PORT` is used.
+pub async fn resolve_server_addr(input: &str) -> io::Result<SocketAddr> {
+    let input = input.trim();
+    if let Ok(addr) = input.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let host = input
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(input);
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, SERVER_PORT));
+    }
+
+    // `host:port` の形式（IPv6 リテラル以外はコロンを1つしか含まない）
+    let resolved = match input.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') && port.parse::<u16>().is_ok() => {
+            lookup_host(input).await?.next()
+        }
+        _ => lookup_host((input, SERVER_PORT)).await?.next(),
+    };
+    resolved.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no address found for {input}"),
+        )
+    })
+}
+
+/// Bind a UDP socket on `port` in the same address family as `server`.
+pub async fn bind_for(server: SocketAddr, port: u16) -> io::Result<UdpSocket> {
+    let local: IpAddr = match server {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    UdpSocket::bind((local, port)).await
+}
 
 /// Ask the user for destination address and message payload,
 /// then bind a UDP socket on `CLIENT_PORT`.
-pub async fn set_up_client() -> io::Result<(UdpSocket, String, String)> {
+pub async fn set_up_client() -> io::Result<(UdpSocket, String, SocketAddr)> {
     println!("\nType the server's address to connect to: ");
     let mut server_address = String::new();
     io::stdin()
@@ -23,23 +87,23 @@ pub async fn set_up_client() -> io::Result<(UdpSocket, String, String)> {
         .expect("Failed to read line");
     let message = message.trim();
 
-    let sock = UdpSocket::bind(format!("{}:{}", server_address, CLIENT_PORT)).await?;
+    let server_address = resolve_server_addr(server_address).await?;
+    let sock = bind_for(server_address, CLIENT_PORT).await?;
 
     println!("\nClient is running on port {}", CLIENT_PORT);
-    println!("\nServer is running on port {}", SERVER_PORT);
+    println!("\nServer is running on {}", server_address);
 
-    Ok((sock, message.to_string(), server_address.to_string()))
+    Ok((sock, message.to_string(), server_address))
 }
 
 /// Send the given message to the server.
-pub async fn send_message(sock: &UdpSocket, message: &str, server_address: &str) -> io::Result<()> {
+pub async fn send_message(
+    sock: &UdpSocket,
+    message: &str,
+    server_address: SocketAddr,
+) -> io::Result<()> {
     println!("\nSending message to server…");
-    let len = sock
-        .send_to(
-            message.as_bytes(),
-            format!("{}:{}", server_address, SERVER_PORT),
-        )
-        .await?;
+    let len = sock.send_to(message.as_bytes(), server_address).await?;
     println!("sent {len} bytes to {server_address}");
     Ok(())
 }
@@ -50,15 +114,20 @@ pub async fn receive_message(sock: &UdpSocket) -> io::Result<()> {
     let (len, addr) = sock.recv_from(&mut buf).await?;
     println!("\n{len:?} bytes received from {addr:?}");
 
-    let message = String::from_utf8_lossy(&buf[..len]);
-    println!("\nReceived message: {message}");
+    match MessageProtocol::deserialize(&buf[..len]) {
+        Ok(message) => println!("\nReceived message: {}", format_message(&message)),
+        Err(_) => println!(
+            "\nReceived message: {}",
+            String::from_utf8_lossy(&buf[..len])
+        ),
+    }
     Ok(())
 }
 
 /// Convenience helper that performs one round‑trip and exits.
 pub async fn run_once() -> io::Result<()> {
     let (sock, message, server_address) = set_up_client().await?;
-    send_message(&sock, &message, &server_address).await?;
+    send_message(&sock, &message, server_address).await?;
     receive_message(&sock).await?;
     println!("closing socket…");
     Ok(())
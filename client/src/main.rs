@@ -1,8 +1,21 @@
 use std::io;
 
-use client::run_once;
+use client::{CLIENT_PORT, bind_for, resolve_server_addr, run_once, tui::run_tui};
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    run_once().await
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        // 全画面モード: `client --tui <server-address> <user-name>`
+        [flag, server_address, user_name] if flag == "--tui" => {
+            let server_addr = resolve_server_addr(server_address).await?;
+            let sock = bind_for(server_addr, CLIENT_PORT).await?;
+            run_tui(sock, server_addr, user_name).await
+        }
+        [flag, ..] if flag == "--tui" => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: client --tui <server-address> <user-name>",
+        )),
+        _ => run_once().await,
+    }
 }
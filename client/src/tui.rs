@@ -0,0 +1,791 @@
+//! Full-screen terminal client.
+//!
+//! The screen is split into a scrollable message pane, a sidebar listing the
+//! users seen in the room with their idle status, and an input line with
+//! history. [`App`] holds all state and is independent of the terminal, so it
+//! can be rendered into `ratatui::backend::TestBackend` in tests.
+//!
+//! `/edit [#id] <text>` and `/delete [#id]` change an earlier message; without
+//! an id they apply to your own latest message. `/reply [#id] <text>` and
+//! `/react [#id] <emoji>` default to the latest message from someone else.
+//!
+//! While a chat line is being typed, a `Typing` frame is sent at most every
+//! [`TYPING_SIGNAL_INTERVAL`]; other users' signals are shown under the
+//! message pane until they expire.
+//!
+//! Received messages are acknowledged automatically, and a read marker is
+//! sent whenever newer messages become visible at the bottom of the pane.
+//! `/receipts [#id]` shows who has received and read a message (default: your
+//! latest one). A heartbeat every [`HEARTBEAT_INTERVAL`] keeps an idle user
+//! from being timed out.
+//!
+//! `/msg <user> <text>` sends a direct message; if the recipient is offline
+//! the server queues it and reports `queued` and later `delivered`.
+//!
+//! `/send <path>` offers a file to the room. Files offered by others are
+//! downloaded automatically and saved under [`DOWNLOAD_DIR`] once their
+//! checksum has been verified; stalled transfers are resumed after
+//! [`FILE_RETRY_AFTER`].
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+};
+use protocol::{
+    MessageKind, MessageProtocol, TYPING_TTL_MS,
+    transfer::{Download, Upload},
+};
+use ratatui::{
+    Frame, Terminal,
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Layout, Position},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, Paragraph},
+};
+use tokio::{net::UdpSocket, sync::mpsc, time::interval};
+
+use crate::{BUFFER_SIZE, format_message};
+
+/// A user is shown as idle after this long without a message.
+pub const IDLE_AFTER: Duration = Duration::from_secs(60);
+/// Minimum time between two typing signals sent by this client.
+pub const TYPING_SIGNAL_INTERVAL: Duration = Duration::from_secs(3);
+/// Width of the user list sidebar, including borders.
+pub const SIDEBAR_WIDTH: u16 = 24;
+/// A transfer without progress for this long is resent or resumed.
+pub const FILE_RETRY_AFTER: Duration = Duration::from_secs(1);
+/// How often an otherwise idle client tells the server it is still there.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Where received files are saved, relative to the working directory.
+pub const DOWNLOAD_DIR: &str = "downloads";
+
+// 送信中のファイルと、最後に確認応答が進んだ時刻
+#[derive(Debug)]
+struct Outgoing {
+    upload: Upload,
+    last_progress: Instant,
+}
+
+// 受信中のファイル。同じ位置からの再送要求は繰り返さない
+#[derive(Debug)]
+struct Incoming {
+    download: Download,
+    last_progress: Instant,
+    requested: Option<u32>,
+}
+
+/// What the caller should do after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    None,
+    /// Send the submitted line as a chat message.
+    Send(String),
+    Quit,
+}
+
+/// Terminal-independent state of the TUI.
+#[derive(Debug)]
+pub struct App {
+    pub user_name: String,
+    pub messages: Vec<MessageProtocol>,
+    /// Lines scrolled up from the bottom of the message pane (0 = follow new messages).
+    pub scroll: usize,
+    pub input: String,
+    pub history: Vec<String>,
+    history_index: Option<usize>,
+    /// Last time each user was seen sending a message.
+    pub users: BTreeMap<String, Instant>,
+    /// Ids of messages that have been edited since they were received.
+    pub edited: HashSet<u64>,
+    /// Latest reaction tally received for each message.
+    pub reactions: HashMap<u64, Vec<(String, usize)>>,
+    /// Last typing signal received from each other user.
+    pub typing: BTreeMap<String, Instant>,
+    last_typing_sent: Option<Instant>,
+    last_read_sent: u64,
+    // サーバの ID が付く前の送信予定のファイル
+    offered: Vec<Upload>,
+    uploads: HashMap<u64, Outgoing>,
+    downloads: HashMap<u64, Incoming>,
+    /// Verified files (name, contents) waiting to be saved.
+    pub received_files: Vec<(String, Vec<u8>)>,
+    /// Feedback shown above the input line (e.g. why a command was rejected).
+    pub status: Option<String>,
+    page_size: usize,
+}
+
+impl App {
+    pub fn new(user_name: impl Into<String>) -> Self {
+        Self {
+            user_name: user_name.into(),
+            messages: Vec::new(),
+            scroll: 0,
+            input: String::new(),
+            history: Vec::new(),
+            history_index: None,
+            users: BTreeMap::new(),
+            edited: HashSet::new(),
+            reactions: HashMap::new(),
+            typing: BTreeMap::new(),
+            last_typing_sent: None,
+            last_read_sent: 0,
+            offered: Vec::new(),
+            uploads: HashMap::new(),
+            downloads: HashMap::new(),
+            received_files: Vec::new(),
+            status: None,
+            page_size: 10,
+        }
+    }
+
+    /// Record an incoming message and mark its sender as active.
+    ///
+    /// Edits, deletions and reaction tallies are applied to the message they
+    /// refer to.
+    pub fn on_message(&mut self, message: MessageProtocol, now: Instant) {
+        // 入力中通知は発言として数えない
+        if message.kind == MessageKind::Typing {
+            if message.user_name != self.user_name {
+                self.typing.insert(message.user_name, now);
+            }
+            return;
+        }
+        self.users.insert(message.user_name.clone(), now);
+        let target = self
+            .messages
+            .iter()
+            .position(|m| m.id != 0 && m.id == message.target_id);
+        match (message.kind, target) {
+            (MessageKind::DirectStatus, _) => {
+                self.status = Some(format!(" {message} "));
+            }
+            (MessageKind::Chat | MessageKind::Direct | MessageKind::FileOffer, _) => {
+                self.typing.remove(&message.user_name);
+                self.messages.push(message);
+                // 遡って読んでいる間は表示位置を固定する
+                if self.scroll > 0 {
+                    self.scroll += 1;
+                }
+            }
+            (MessageKind::Edit, Some(i)) => {
+                self.messages[i].body = message.body;
+                self.edited.insert(message.target_id);
+            }
+            (MessageKind::Receipts, _) => {
+                let states: Vec<String> = message
+                    .receipt_states()
+                    .iter()
+                    .map(|(user_name, status)| format!("{user_name} {status}"))
+                    .collect();
+                let states = if states.is_empty() {
+                    "no recipients".to_string()
+                } else {
+                    states.join(", ")
+                };
+                self.status = Some(format!(" #{}: {states} ", message.target_id));
+            }
+            (MessageKind::Reactions, Some(_)) => {
+                self.reactions.insert(message.target_id, message.tally());
+            }
+            (MessageKind::Delete, Some(i)) => {
+                // 表示範囲より下の行が消えたときも表示位置を保つ
+                if self.scroll > 0 && i >= self.messages.len() - self.scroll {
+                    self.scroll -= 1;
+                }
+                self.messages.remove(i);
+                self.reactions.remove(&message.target_id);
+            }
+            // 手元にないメッセージへの変更と、サーバが中継しない種別は無視する
+            (MessageKind::Edit | MessageKind::Delete | MessageKind::Reactions, None)
+            | (
+                MessageKind::React
+                | MessageKind::Typing
+                | MessageKind::Ack
+                | MessageKind::Read
+                | MessageKind::FileChunk
+                | MessageKind::FileAck
+                | MessageKind::Codec
+                | MessageKind::Heartbeat,
+                _,
+            ) => {}
+        }
+    }
+
+    /// Turn a submitted input line into the frame to send.
+    ///
+    /// Plain lines become chat messages. `/edit` and `/delete` target the
+    /// given `#id` or the user's own latest message; `/reply` and `/react`
+    /// target the given `#id` or the latest message from someone else.
+    pub fn compose(&self, line: &str) -> Result<MessageProtocol, String> {
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        if command == "/send" {
+            return Err("usage: /send <path>".to_string());
+        }
+        if command == "/msg" {
+            return match rest.trim_start().split_once(' ') {
+                Some((recipient, body)) if !body.trim().is_empty() => Ok(MessageProtocol::direct(
+                    &self.user_name,
+                    recipient,
+                    body.trim(),
+                )),
+                _ => Err("usage: /msg <user> <text>".to_string()),
+            };
+        }
+        let own = match command {
+            "/edit" | "/delete" | "/receipts" => true,
+            "/reply" | "/react" => false,
+            _ => return Ok(MessageProtocol::new(&self.user_name, line)),
+        };
+        let rest = rest.trim_start();
+        let (target_id, rest) = match rest.strip_prefix('#') {
+            Some(tagged) => {
+                let (id, rest) = tagged.split_once(' ').unwrap_or((tagged, ""));
+                let id = id
+                    .parse()
+                    .map_err(|_| format!("invalid message id: #{id}"))?;
+                (id, rest.trim())
+            }
+            None => {
+                let latest = self
+                    .messages
+                    .iter()
+                    .rev()
+                    .find(|m| m.id != 0 && (m.user_name == self.user_name) == own)
+                    .ok_or(if own {
+                        "you have no message to change"
+                    } else {
+                        "no message to answer"
+                    })?;
+                (latest.id, rest.trim())
+            }
+        };
+        let user_name = self.user_name.as_str();
+        match (command, rest.is_empty()) {
+            ("/delete", _) => Ok(MessageProtocol::delete(user_name, target_id)),
+            ("/receipts", _) => Ok(MessageProtocol::receipts(user_name, target_id, &[])),
+            (_, true) => Err(format!(
+                "usage: {command} [#id] <{}>",
+                if command == "/react" { "emoji" } else { "text" }
+            )),
+            ("/edit", false) => Ok(MessageProtocol::edit(user_name, target_id, rest)),
+            ("/reply", false) => Ok(MessageProtocol::reply(user_name, target_id, rest)),
+            _ => Ok(MessageProtocol::react(user_name, target_id, rest)),
+        }
+    }
+
+    /// Encode a frame built from user input.
+    ///
+    /// A frame over the protocol's limits (e.g. a very long line or
+    /// recipient) is reported in the status line instead of being sent.
+    pub fn encode_input(&mut self, message: &MessageProtocol) -> Option<Vec<u8>> {
+        match message.serialize() {
+            Ok(frame) => {
+                self.status = None;
+                Some(frame)
+            }
+            Err(e) => {
+                self.status = Some(format!(" {e} "));
+                None
+            }
+        }
+    }
+
+    /// Read the file at `path` and build the offer announcing it.
+    ///
+    /// Chunks are sent once the server has assigned the offer an id.
+    pub fn offer_file(&mut self, path: &Path) -> Result<MessageProtocol, String> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| format!("not a file: {}", path.display()))?;
+        let data = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let upload = Upload::new(name, data);
+        let offer = MessageProtocol::file_offer(&self.user_name, upload.offer());
+        self.offered.push(upload);
+        Ok(offer)
+    }
+
+    /// Advance the transfers a received frame belongs to.
+    ///
+    /// Returns the chunks and acknowledgements to send in response.
+    pub fn on_transfer(&mut self, message: &MessageProtocol, now: Instant) -> Vec<MessageProtocol> {
+        let id = message.target_id;
+        match message.kind {
+            MessageKind::FileOffer if message.user_name == self.user_name => {
+                // 自分の申し出のエコーで転送 ID がわかる
+                let Some(position) = self
+                    .offered
+                    .iter()
+                    .position(|upload| Some(upload.offer()) == message.offer().as_ref())
+                else {
+                    return Vec::new();
+                };
+                let upload = self.offered.remove(position);
+                self.uploads.insert(
+                    message.id,
+                    Outgoing {
+                        upload,
+                        last_progress: now,
+                    },
+                );
+                self.due_chunks(message.id)
+            }
+            MessageKind::FileOffer => {
+                if let Some(offer) = message.offer() {
+                    let download = Incoming {
+                        download: Download::new(offer),
+                        last_progress: now,
+                        requested: None,
+                    };
+                    self.downloads.insert(message.id, download);
+                }
+                Vec::new()
+            }
+            MessageKind::FileAck => {
+                let Some(outgoing) = self.uploads.get_mut(&id) else {
+                    return Vec::new();
+                };
+                if outgoing.upload.on_ack(message.ack_index().unwrap_or(0)) {
+                    outgoing.last_progress = now;
+                }
+                if outgoing.upload.is_complete() {
+                    self.status = Some(format!(" sent {} ", outgoing.upload.offer()));
+                    self.uploads.remove(&id);
+                    return Vec::new();
+                }
+                self.due_chunks(id)
+            }
+            MessageKind::FileChunk => self.receive_chunk(message, now),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Resend or resume every transfer that has made no progress for
+    /// [`FILE_RETRY_AFTER`].
+    pub fn transfer_retries(&mut self, now: Instant) -> Vec<MessageProtocol> {
+        let stalled = |last: Instant| now.duration_since(last) >= FILE_RETRY_AFTER;
+        let mut frames = Vec::new();
+        let resend: Vec<u64> = self
+            .uploads
+            .iter_mut()
+            .filter(|(_, outgoing)| stalled(outgoing.last_progress))
+            .map(|(id, outgoing)| {
+                outgoing.upload.rewind();
+                outgoing.last_progress = now;
+                *id
+            })
+            .collect();
+        for id in resend {
+            frames.extend(self.due_chunks(id));
+        }
+        for (id, incoming) in &mut self.downloads {
+            if stalled(incoming.last_progress) {
+                incoming.last_progress = now;
+                let next = incoming.download.next_index();
+                incoming.requested = Some(next);
+                frames.push(MessageProtocol::file_ack(&self.user_name, *id, Some(next)));
+            }
+        }
+        frames
+    }
+
+    fn due_chunks(&mut self, id: u64) -> Vec<MessageProtocol> {
+        let Some(outgoing) = self.uploads.get_mut(&id) else {
+            return Vec::new();
+        };
+        outgoing
+            .upload
+            .due_chunks()
+            .into_iter()
+            .map(|(index, chunk)| MessageProtocol::file_chunk(&self.user_name, id, index, chunk))
+            .collect()
+    }
+
+    // 順番どおりのチャンクを蓄え、欠けがあればその位置からの再送を頼む
+    fn receive_chunk(&mut self, message: &MessageProtocol, now: Instant) -> Vec<MessageProtocol> {
+        let id = message.target_id;
+        let (Some(incoming), Some((index, chunk))) = (self.downloads.get_mut(&id), message.chunk())
+        else {
+            return Vec::new();
+        };
+        match incoming.download.receive(index, &chunk) {
+            Ok(true) => incoming.last_progress = now,
+            Ok(false) => {
+                let next = incoming.download.next_index();
+                if index > next && incoming.requested != Some(next) {
+                    incoming.requested = Some(next);
+                    return vec![MessageProtocol::file_ack(&self.user_name, id, Some(next))];
+                }
+                return Vec::new();
+            }
+            Err(e) => {
+                self.status = Some(format!(" {}: {e} ", incoming.download.offer()));
+                self.downloads.remove(&id);
+                return Vec::new();
+            }
+        }
+        if incoming.download.is_complete() {
+            let offer = incoming.download.offer().clone();
+            match incoming.download.verified() {
+                Ok(data) => self
+                    .received_files
+                    .push((offer.name.clone(), data.to_vec())),
+                Err(e) => self.status = Some(format!(" {offer}: {e} ")),
+            }
+            self.downloads.remove(&id);
+        }
+        Vec::new()
+    }
+
+    /// Delivery acknowledgement to send for a received message, if any.
+    ///
+    /// Only stamped chat messages from other users are acknowledged.
+    pub fn ack_for(&self, message: &MessageProtocol) -> Option<MessageProtocol> {
+        (message.kind == MessageKind::Chat
+            && message.id != 0
+            && message.user_name != self.user_name)
+            .then(|| MessageProtocol::ack(&self.user_name, message.id))
+    }
+
+    /// Read marker to send, if newer messages are visible than last reported.
+    ///
+    /// Nothing is marked read while the pane is scrolled back.
+    pub fn read_marker_due(&mut self) -> Option<MessageProtocol> {
+        if self.scroll > 0 {
+            return None;
+        }
+        let newest = self.messages.iter().map(|m| m.id).max()?;
+        if newest <= self.last_read_sent {
+            return None;
+        }
+        self.last_read_sent = newest;
+        Some(MessageProtocol::read_up_to(&self.user_name, newest))
+    }
+
+    /// Whether a typing signal should be sent now for the current input.
+    ///
+    /// Commands do not count as typing, and signals are throttled to one per
+    /// [`TYPING_SIGNAL_INTERVAL`]; a `true` result records the send time.
+    pub fn typing_signal_due(&mut self, now: Instant) -> bool {
+        if self.input.is_empty() || self.input.starts_with('/') {
+            return false;
+        }
+        if self
+            .last_typing_sent
+            .is_some_and(|last| now.duration_since(last) < TYPING_SIGNAL_INTERVAL)
+        {
+            return false;
+        }
+        self.last_typing_sent = Some(now);
+        true
+    }
+
+    /// Other users whose typing signal has not expired, sorted by name.
+    pub fn typing_users(&self, now: Instant) -> Vec<&str> {
+        let ttl = Duration::from_millis(TYPING_TTL_MS);
+        self.typing
+            .iter()
+            .filter(|(_, last)| now.duration_since(**last) < ttl)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    // 返信先の要約（例: `<bob>: 元の発言…`）。手元にない場合は ID だけ示す
+    fn reply_context(&self, reply_to: u64) -> String {
+        const SNIPPET_CHARS: usize = 20;
+        match self.messages.iter().find(|m| m.id == reply_to) {
+            Some(original) => {
+                let mut snippet: String = original.body.chars().take(SNIPPET_CHARS).collect();
+                if original.body.chars().count() > SNIPPET_CHARS {
+                    snippet.push('…');
+                }
+                format!("<{}>: {snippet}", original.user_name)
+            }
+            None => format!("#{reply_to}"),
+        }
+    }
+
+    /// Apply a key press to the input line, history or scrollback.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.kind == KeyEventKind::Release {
+            return Action::None;
+        }
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+            KeyCode::Esc => Action::Quit,
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                Action::None
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+                Action::None
+            }
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut self.input);
+                self.history_index = None;
+                if line.trim().is_empty() {
+                    return Action::None;
+                }
+                self.history.push(line.clone());
+                self.scroll = 0;
+                self.last_typing_sent = None;
+                Action::Send(line)
+            }
+            KeyCode::Up => {
+                self.recall_history(true);
+                Action::None
+            }
+            KeyCode::Down => {
+                self.recall_history(false);
+                Action::None
+            }
+            KeyCode::PageUp => {
+                let max = self.messages.len().saturating_sub(1);
+                self.scroll = (self.scroll + self.page_size).min(max);
+                Action::None
+            }
+            KeyCode::PageDown => {
+                self.scroll = self.scroll.saturating_sub(self.page_size);
+                Action::None
+            }
+            _ => Action::None,
+        }
+    }
+
+    // 入力履歴を遡る（older = true）/ 戻る
+    fn recall_history(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+        let index = match (self.history_index, older) {
+            (None, true) => Some(self.history.len() - 1),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i + 1 < self.history.len() => Some(i + 1),
+            (Some(_), false) => None,
+        };
+        self.history_index = index;
+        self.input = index.map_or_else(String::new, |i| self.history[i].clone());
+    }
+
+    /// Render the whole screen.
+    pub fn draw(&mut self, frame: &mut Frame, now: Instant) {
+        let [main, input] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(3)]).areas(frame.area());
+        let [messages, sidebar] =
+            Layout::horizontal([Constraint::Min(10), Constraint::Length(SIDEBAR_WIDTH)])
+                .areas(main);
+
+        // 表示できる行数に合わせてスクロール位置を決める
+        let height = messages.height.saturating_sub(2) as usize;
+        self.page_size = height.max(1);
+        let end = self.messages.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(height);
+        let lines: Vec<Line> = self.messages[start..end]
+            .iter()
+            .map(|message| {
+                let mut line = format_message(message);
+                if message.id != 0 {
+                    line = format!("#{} {line}", message.id);
+                }
+                if message.reply_to != 0 {
+                    line.push_str(&format!(" ↩ {}", self.reply_context(message.reply_to)));
+                }
+                if self.edited.contains(&message.id) {
+                    line.push_str(" (edited)");
+                }
+                if let Some(tally) = self.reactions.get(&message.id)
+                    && !tally.is_empty()
+                {
+                    let tally: Vec<String> = tally
+                        .iter()
+                        .map(|(reaction, count)| format!("{reaction} {count}"))
+                        .collect();
+                    line.push_str(&format!("  [{}]", tally.join(" ")));
+                }
+                Line::from(line)
+            })
+            .collect();
+        let title = if self.scroll > 0 {
+            format!(" Messages (+{} below) ", self.scroll)
+        } else {
+            " Messages ".to_string()
+        };
+        let typing = match self.typing_users(now).as_slice() {
+            [] => String::new(),
+            [one] => format!(" {one} is typing… "),
+            [one, two] => format!(" {one} and {two} are typing… "),
+            _ => " several people are typing… ".to_string(),
+        };
+        frame.render_widget(
+            Paragraph::new(lines).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(title)
+                    .title_bottom(typing),
+            ),
+            messages,
+        );
+
+        let users: Vec<ListItem> = self
+            .users
+            .iter()
+            .map(|(name, last_seen)| {
+                if now.duration_since(*last_seen) >= IDLE_AFTER {
+                    ListItem::new(format!("{name} (idle)"))
+                        .style(Style::default().add_modifier(Modifier::DIM))
+                } else {
+                    ListItem::new(name.as_str())
+                }
+            })
+            .collect();
+        frame.render_widget(
+            List::new(users).block(Block::default().borders(Borders::ALL).title(" Users ")),
+            sidebar,
+        );
+
+        let prompt = format!("{}> ", self.user_name);
+        let cursor_x = input.x + 1 + (prompt.chars().count() + self.input.chars().count()) as u16;
+        frame.render_widget(
+            Paragraph::new(format!("{prompt}{}", self.input)).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(self.status.clone().unwrap_or_default()),
+            ),
+            input,
+        );
+        frame.set_cursor_position(Position::new(cursor_x, input.y + 1));
+    }
+}
+
+/// Run the TUI against `server_addr` until the user quits.
+pub async fn run_tui(sock: UdpSocket, server_addr: SocketAddr, user_name: &str) -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    let result = event_loop(&mut terminal, &sock, server_addr, user_name).await;
+
+    // エラー時も端末の状態を必ず元に戻す
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    result
+}
+
+async fn event_loop<B: Backend>(
+    terminal: &mut Terminal<B>,
+    sock: &UdpSocket,
+    server_addr: SocketAddr,
+    user_name: &str,
+) -> io::Result<()> {
+    let mut app = App::new(user_name);
+
+    // crossterm の読み取りはブロッキングなので専用スレッドで行う
+    let (key_tx, mut keys) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if let Event::Key(key) = event
+                && key_tx.send(key).is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let mut buf = [0u8; BUFFER_SIZE];
+    // アイドル表示を更新するための定期再描画
+    let mut tick = interval(Duration::from_secs(1));
+    // 黙っていてもタイムアウトで外されないよう、定期的に生存を知らせる
+    let mut heartbeat = interval(HEARTBEAT_INTERVAL);
+    loop {
+        terminal.draw(|frame| app.draw(frame, Instant::now()))?;
+        if let Some(marker) = app.read_marker_due() {
+            send_frame(sock, server_addr, &marker).await?;
+        }
+        tokio::select! {
+            Some(key) = keys.recv() => match app.handle_key(key) {
+                Action::None if app.typing_signal_due(Instant::now()) => {
+                    send_frame(sock, server_addr, &MessageProtocol::typing(user_name)).await?;
+                }
+                Action::Send(line) => {
+                    let composed = match line.strip_prefix("/send ") {
+                        Some(path) => app.offer_file(Path::new(path.trim())),
+                        None => app.compose(&line),
+                    };
+                    match composed {
+                        Ok(message) => {
+                            if let Some(frame) = app.encode_input(&message) {
+                                sock.send_to(&frame, server_addr).await?;
+                            }
+                        }
+                        Err(e) => app.status = Some(format!(" {e} ")),
+                    }
+                }
+                Action::Quit => return Ok(()),
+                Action::None => {}
+            },
+            received = sock.recv_from(&mut buf) => {
+                let (len, _) = received?;
+                // 解析できないデータグラムは読み捨てる
+                if let Ok(message) = MessageProtocol::deserialize(&buf[..len]) {
+                    if let Some(ack) = app.ack_for(&message) {
+                        send_frame(sock, server_addr, &ack).await?;
+                    }
+                    for frame in app.on_transfer(&message, Instant::now()) {
+                        send_frame(sock, server_addr, &frame).await?;
+                    }
+                    app.on_message(message, Instant::now());
+                }
+                for (name, data) in std::mem::take(&mut app.received_files) {
+                    app.status = Some(match save_download(Path::new(DOWNLOAD_DIR), &name, &data) {
+                        Ok(path) => format!(" saved {} ", path.display()),
+                        Err(e) => format!(" could not save {name}: {e} "),
+                    });
+                }
+            }
+            _ = tick.tick() => {
+                for frame in app.transfer_retries(Instant::now()) {
+                    send_frame(sock, server_addr, &frame).await?;
+                }
+            }
+            _ = heartbeat.tick() => {
+                send_frame(sock, server_addr, &MessageProtocol::heartbeat(user_name)).await?;
+            }
+        }
+    }
+}
+
+async fn send_frame(
+    sock: &UdpSocket,
+    server_addr: SocketAddr,
+    message: &MessageProtocol,
+) -> io::Result<()> {
+    let frame = message
+        .serialize()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    sock.send_to(&frame, server_addr).await?;
+    Ok(())
+}
+
+/// Write a received file into `dir`, keeping only the final component of `name`.
+pub fn save_download(dir: &Path, name: &str, data: &[u8]) -> io::Result<PathBuf> {
+    // 他人が付けた名前なので、ディレクトリを遡らせない
+    let file_name = Path::new(name)
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"))?;
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(file_name);
+    std::fs::write(&path, data)?;
+    Ok(path)
+}
@@ -0,0 +1,356 @@
+#[cfg(test)]
+mod bot_test {
+    use std::time::Duration;
+
+    use chrono::{NaiveDate, NaiveTime};
+    use client::bot::{
+        Bot, Command, DiceBot, EchoBot, KEEPALIVE_INTERVAL, MAX_REMINDER_DELAY, Outbox,
+        ReminderBot, dispatch, format_duration, parse_duration, run_bot, until_daily,
+    };
+    use protocol::{MessageKind, MessageProtocol};
+    use test_support::harness::TestServer;
+    use tokio::{
+        net::UdpSocket,
+        time::{sleep, timeout},
+    };
+
+    // サーバが採番済みの発言
+    fn chat(user: &str, id: u64, body: &str) -> MessageProtocol {
+        MessageProtocol {
+            id,
+            ..MessageProtocol::new(user, body)
+        }
+    }
+
+    fn direct(user: &str, id: u64, body: &str) -> MessageProtocol {
+        MessageProtocol {
+            id,
+            ..MessageProtocol::direct(user, "bot", body)
+        }
+    }
+
+    // 1 通だけ処理し、送るはずのものを返す
+    fn handle(bot: &mut impl Bot, message: &MessageProtocol) -> Outbox {
+        let mut out = Outbox::new("bot");
+        dispatch(bot, message, &mut out);
+        out
+    }
+
+    fn bodies(out: &Outbox) -> Vec<&str> {
+        out.messages().iter().map(|m| m.body.as_str()).collect()
+    }
+
+    // テスト: コマンドの解析
+    // 目的: 接頭辞つきの行だけがコマンドになり、引数が整えられることを確認する
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            Command::parse("  !roll   2d6 "),
+            Some(Command {
+                name: "roll",
+                args: "2d6"
+            })
+        );
+        assert_eq!(
+            Command::parse("!reminders"),
+            Some(Command {
+                name: "reminders",
+                args: ""
+            })
+        );
+        assert_eq!(Command::parse("hello !roll"), None);
+        assert_eq!(Command::parse("! roll"), None);
+    }
+
+    // テスト: 期間の表記
+    // 目的: 単位の組み合わせを読み書きでき、不正な表記を拒否することを確認する
+    #[test]
+    fn parses_and_formats_durations() {
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86_400)));
+        for bad in ["", "15", "m", "0m", "5x", "-5m"] {
+            assert_eq!(parse_duration(bad), None, "{bad:?}");
+        }
+        assert_eq!(format_duration(Duration::from_secs(5400)), "1h30m");
+        assert_eq!(format_duration(Duration::from_secs(90_061)), "1d1h1m1s");
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+    }
+
+    // テスト: 毎日の時刻までの待ち時間
+    // 目的: 今日の時刻が過ぎていれば翌日になることを確認する
+    #[test]
+    fn waits_until_next_daily_time() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let at = NaiveTime::from_hms_opt(9, 45, 0).unwrap();
+        let morning = day.and_hms_opt(9, 0, 0).unwrap();
+        assert_eq!(until_daily(morning, at), Duration::from_secs(45 * 60));
+        let later = day.and_hms_opt(10, 0, 0).unwrap();
+        assert_eq!(
+            until_daily(later, at),
+            Duration::from_secs(23 * 3600 + 45 * 60)
+        );
+        assert_eq!(
+            until_daily(day.and_time(at), at),
+            Duration::from_secs(86_400)
+        );
+    }
+
+    // テスト: ボットに渡すメッセージの選別
+    // 目的: 自分の発言・未採番のフレーム・チャット以外は渡らず、返信の形が元の種類に合うことを確認する
+    #[test]
+    fn echo_bot_answers_others_only() {
+        let mut bot = EchoBot;
+
+        // ❶ 部屋のコマンドには元の発言への返信で答える
+        let out = handle(&mut bot, &chat("alice", 7, "!echo hi there"));
+        let reply = &out.messages()[0];
+        assert_eq!((reply.body.as_str(), reply.reply_to), ("hi there", 7));
+        assert_eq!(reply.kind, MessageKind::Chat);
+
+        // ❷ DM には DM で答える
+        let out = handle(&mut bot, &direct("alice", 8, "ping"));
+        let reply = &out.messages()[0];
+        assert_eq!(reply.kind, MessageKind::Direct);
+        assert_eq!(reply.recipient, "alice");
+
+        // ❸ 自分の発言、未採番、入力中通知、部屋の普通の発言には反応しない
+        for ignored in [
+            chat("bot", 9, "!echo loop"),
+            chat("alice", 0, "!echo unstamped"),
+            MessageProtocol::typing("alice"),
+            chat("alice", 10, "just chatting"),
+        ] {
+            assert!(handle(&mut bot, &ignored).is_empty(), "{ignored:?}");
+        }
+    }
+
+    // テスト: サイコロ
+    // 目的: 同じシードなら同じ出目になり、範囲と修正値が守られ、不正な指定には使い方を返すことを確認する
+    #[test]
+    fn dice_bot_rolls_within_bounds() {
+        let roll = |seed, body: &str| {
+            let out = handle(&mut DiceBot::seeded(seed), &chat("alice", 1, body));
+            bodies(&out)[0].to_string()
+        };
+        assert_eq!(roll(3, "!roll 4d20+2"), roll(3, "!roll 4d20+2"));
+        assert!(roll(3, "!roll").starts_with("alice rolled 1d6: "));
+
+        for seed in 0..50 {
+            let answer = roll(seed, "!roll 3d6-1");
+            let total: i64 = answer.rsplit(' ').next().unwrap().parse().unwrap();
+            assert!((2..=17).contains(&total), "{answer}");
+            assert!(answer.contains("(-1)"), "{answer}");
+        }
+        for bad in [
+            "!roll 0d6",
+            "!roll 2d1",
+            "!roll 101d6",
+            "!roll d",
+            "!roll 2x6",
+            "!roll d6+9223372036854775807",
+            "!roll d6-1000001",
+        ] {
+            assert!(roll(1, bad).starts_with("usage:"), "{bad}");
+        }
+        // 上限ちょうどの修正値は振れる
+        assert!(roll(1, "!roll d6+1000000").contains("(+1000000)"));
+        assert!(handle(&mut DiceBot::seeded(1), &chat("alice", 1, "!echo")).is_empty());
+    }
+
+    // テスト: 一度きりのリマインダー
+    // 目的: 指定の時間でタイマーが張られ、発火すると依頼者宛てに一度だけ流れることを確認する
+    #[test]
+    fn reminder_fires_once() {
+        let mut bot = ReminderBot::new();
+        let out = handle(&mut bot, &chat("alice", 1, "!remind 15m stand up"));
+        assert_eq!(bodies(&out), ["#1 in 15m"]);
+        assert_eq!(out.timers(), [(Duration::from_secs(900), 1)]);
+
+        let mut out = Outbox::new("bot");
+        bot.on_timer(1, &mut out);
+        assert_eq!(bodies(&out), ["@alice stand up"]);
+        assert!(bot.reminders().is_empty());
+        assert!(out.timers().is_empty());
+
+        // 取り消し済み・未知のタイマーは何もしない
+        let mut out = Outbox::new("bot");
+        bot.on_timer(1, &mut out);
+        assert!(out.is_empty());
+    }
+
+    // テスト: 遠すぎるリマインダー
+    // 目的: 上限を超える待ち時間は使い方を返して断り、ボットが落ちないことを確認する
+    #[tokio::test]
+    async fn oversized_delays_are_refused() {
+        let mut bot = ReminderBot::new();
+        for request in ["!remind 18446744073709551615s x", "!every 366d x"] {
+            let out = handle(&mut bot, &chat("alice", 1, request));
+            assert!(bodies(&out)[0].starts_with("usage:"), "{request}");
+            assert!(out.timers().is_empty());
+        }
+        assert!(bot.reminders().is_empty());
+        let out = handle(&mut bot, &chat("alice", 2, "!remind 365d x"));
+        assert_eq!(out.timers(), [(MAX_REMINDER_DELAY, 1)]);
+
+        // 設定で渡された途方もない待ち時間でも、タイマーを捨てて動き続ける
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let bot = ReminderBot::new().repeat(Duration::MAX, Duration::MAX, "never");
+        let bot = tokio::spawn(run_bot(sock, server.local_addr().unwrap(), "reminder", bot));
+        let mut buf = [0u8; client::BUFFER_SIZE];
+        server.recv_from(&mut buf).await.unwrap();
+        sleep(Duration::from_millis(50)).await;
+        assert!(!bot.is_finished());
+        bot.abort();
+    }
+
+    // テスト: 繰り返しと取り消し
+    // 目的: 繰り返しは張り直され、取り消せるのは依頼者だけで、DM の依頼は本人にしか見えないことを確認する
+    #[test]
+    fn repeating_reminders_and_cancel() {
+        let mut bot = ReminderBot::new();
+        handle(&mut bot, &chat("alice", 1, "!every 1d standup at 10"));
+        let out = handle(&mut bot, &chat("bob", 2, "!every 30s too often"));
+        assert_eq!(bodies(&out), ["reminders repeat at most every 1m"]);
+
+        // ❶ 発火するたびに同じ間隔で張り直す
+        let mut out = Outbox::new("bot");
+        bot.on_timer(1, &mut out);
+        assert_eq!(bodies(&out), ["standup at 10"]);
+        assert_eq!(out.timers(), [(Duration::from_secs(86_400), 1)]);
+
+        // ❷ DM の依頼は DM で届き、一覧にも本人にしか出ない
+        handle(&mut bot, &direct("bob", 3, "!remind 1h call mum"));
+        let listing = |bot: &mut ReminderBot, user| {
+            bodies(&handle(bot, &chat(user, 4, "!reminders")))[0].to_string()
+        };
+        assert!(listing(&mut bot, "bob").contains("call mum"));
+        assert!(!listing(&mut bot, "alice").contains("call mum"));
+        let mut out = Outbox::new("bot");
+        bot.on_timer(2, &mut out);
+        assert_eq!(out.messages()[0].kind, MessageKind::Direct);
+        assert_eq!(out.messages()[0].recipient, "bob");
+
+        // ❸ 他人のものは取り消せない
+        let out = handle(&mut bot, &chat("bob", 5, "!cancel #1"));
+        assert_eq!(bodies(&out), ["#1 is not yours"]);
+        let out = handle(&mut bot, &chat("alice", 6, "!cancel 1"));
+        assert_eq!(bodies(&out), ["cancelled #1"]);
+        assert_eq!(
+            bodies(&handle(&mut bot, &chat("alice", 7, "!reminders"))),
+            ["no reminders"]
+        );
+    }
+
+    // テスト: 起動時に設定した毎日のリマインダー
+    // 目的: 開始時に初回のタイマーが張られ、チャットからは取り消せないことを確認する
+    #[test]
+    fn configured_daily_reminder() {
+        let now = NaiveDate::from_ymd_opt(2026, 10, 19)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        let at = NaiveTime::from_hms_opt(9, 45, 0).unwrap();
+        let mut bot = ReminderBot::new().daily(now, at, "standup in 15 minutes");
+
+        let mut out = Outbox::new("bot");
+        bot.on_start(&mut out);
+        assert_eq!(out.timers(), [(Duration::from_secs(45 * 60), 1)]);
+        let out = handle(&mut bot, &chat("alice", 1, "!cancel 1"));
+        assert_eq!(bodies(&out), ["#1 is not yours"]);
+        let out = handle(&mut bot, &chat("alice", 2, "!reminders"));
+        assert_eq!(
+            bodies(&out),
+            ["#1 daily at 09:45 (config): standup in 15 minutes"]
+        );
+    }
+
+    // 夏時間が始まった朝（24 時間眠ると時計では 1 時間遅れて起きる）
+    fn after_spring_forward() -> chrono::NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 3, 8)
+            .unwrap()
+            .and_hms_opt(10, 45, 0)
+            .unwrap()
+    }
+
+    // テスト: 時計の切り替えをまたぐ毎日のリマインダー
+    // 目的: 発火のたびに時計から次回を測り直し、固定の 24 時間でずれていかないことを確認する
+    #[test]
+    fn daily_reminder_follows_the_clock() {
+        let now = NaiveDate::from_ymd_opt(2026, 3, 7)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        let at = NaiveTime::from_hms_opt(9, 45, 0).unwrap();
+        let mut bot = ReminderBot::new().with_clock(after_spring_forward).daily(
+            now,
+            at,
+            "standup in 15 minutes",
+        );
+        let mut out = Outbox::new("bot");
+        bot.on_start(&mut out);
+        assert_eq!(out.timers(), [(Duration::from_secs(45 * 60), 1)]);
+
+        let mut out = Outbox::new("bot");
+        bot.on_timer(1, &mut out);
+        assert_eq!(bodies(&out), ["standup in 15 minutes"]);
+        assert_eq!(out.timers(), [(Duration::from_secs(23 * 3600), 1)]);
+        assert_eq!(bot.reminders()[&1].daily, Some(at));
+    }
+
+    // テスト: 実際のサーバ越しのボット
+    // 目的: ボットが普通のクライアントとして参加し、部屋には参加を流さず、コマンドに返信することを確認する
+    #[tokio::test]
+    async fn bot_runs_as_a_client() {
+        let server = TestServer::start().await.unwrap();
+        let [alice, bob] = server.join(["alice", "bob"]).await.unwrap();
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let bot = tokio::spawn(run_bot(sock, server.addr(), "echo", EchoBot));
+
+        // ❶ 参加は部屋に流れない
+        timeout(Duration::from_secs(1), async {
+            while !server
+                .state()
+                .client_manager
+                .clients_table
+                .contains_key("echo")
+            {
+                sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("bot did not join");
+        alice.expect_silence().await;
+
+        // ❷ コマンドには元の発言への返信として全員に届く
+        alice.say("!echo hello").await;
+        let sent = alice.expect_from("alice", "!echo hello").await;
+        bob.expect_from("alice", "!echo hello").await;
+        let reply = alice.expect_from("echo", "hello").await;
+        assert_eq!(reply.reply_to, sent.id);
+        bob.expect_from("echo", "hello").await;
+
+        bot.abort();
+    }
+
+    // テスト: 生存通知
+    // 目的: 参加も定期的な生存通知も、サーバが参加として扱うハートビートで送られることを確認する
+    #[tokio::test(start_paused = true)]
+    async fn keepalive_is_a_heartbeat() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let bot = tokio::spawn(run_bot(sock, server.local_addr().unwrap(), "echo", EchoBot));
+
+        let start = tokio::time::Instant::now();
+        let mut buf = [0u8; client::BUFFER_SIZE];
+        for _ in 0..3 {
+            let (len, _) = server.recv_from(&mut buf).await.unwrap();
+            let frame = MessageProtocol::deserialize(&buf[..len]).unwrap();
+            assert_eq!(frame, MessageProtocol::heartbeat("echo"));
+        }
+        // 参加のあとは一定間隔で送る
+        assert_eq!(start.elapsed(), KEEPALIVE_INTERVAL * 2);
+        bot.abort();
+    }
+}
@@ -0,0 +1,32 @@
+#[cfg(test)]
+mod format_test {
+    use client::format_message;
+    use protocol::MessageProtocol;
+
+    // テスト: 未スタンプのメッセージの表示
+    // 目的: サーバ時刻がない場合は従来どおり `<user>: body` で表示されることを確認する
+    #[test]
+    fn unstamped_message_has_no_time() {
+        let message = MessageProtocol::new("bob", "hi");
+        assert_eq!(format_message(&message), "<bob>: hi");
+    }
+
+    // テスト: サーバ時刻つきメッセージの表示
+    // 目的: `[HH:MM:SS]` の時刻が先頭に付くことを確認する（タイムゾーンに依存しない形で検証）
+    #[test]
+    fn stamped_message_shows_time_of_day() {
+        let message = MessageProtocol {
+            id: 1,
+            timestamp_ms: 1_700_000_000_000,
+            ..MessageProtocol::new("bob", "hi")
+        };
+        let rendered = format_message(&message);
+
+        let (time, rest) = rendered.split_once(' ').unwrap();
+        assert_eq!(rest, "<bob>: hi");
+        assert_eq!(time.len(), "[00:00:00]".len(), "{rendered}");
+        assert!(time.starts_with('[') && time.ends_with(']'), "{rendered}");
+        // 秒までは時差の影響を受けない
+        assert!(time.ends_with(":20]"), "{rendered}");
+    }
+}
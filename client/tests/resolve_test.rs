@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod resolve_test {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use client::{SERVER_PORT, bind_for, resolve_server_addr};
+
+    // テスト: IP リテラルの解決
+    // 目的: IPv4 / IPv6（括弧あり・なし）をポート省略時は SERVER_PORT で解決することを確認する
+    #[tokio::test]
+    async fn resolves_ip_literals_with_default_port() {
+        let v4 = SocketAddr::from((Ipv4Addr::LOCALHOST, SERVER_PORT));
+        let v6 = SocketAddr::from((Ipv6Addr::LOCALHOST, SERVER_PORT));
+
+        assert_eq!(resolve_server_addr("127.0.0.1").await.unwrap(), v4);
+        assert_eq!(resolve_server_addr("::1").await.unwrap(), v6);
+        assert_eq!(resolve_server_addr("[::1]").await.unwrap(), v6);
+    }
+
+    // テスト: ポート指定つきアドレスの解決
+    // 目的: 明示したポートが優先されることを確認する
+    #[tokio::test]
+    async fn resolves_explicit_ports() {
+        assert_eq!(
+            resolve_server_addr("127.0.0.1:9100").await.unwrap(),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 9100))
+        );
+        assert_eq!(
+            resolve_server_addr("[::1]:9100").await.unwrap(),
+            SocketAddr::from((Ipv6Addr::LOCALHOST, 9100))
+        );
+    }
+
+    // テスト: ホスト名の解決
+    // 目的: localhost を名前解決でき、ポートの有無を正しく扱うことを確認する
+    #[tokio::test]
+    async fn resolves_host_names() {
+        let addr = resolve_server_addr("localhost").await.unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_eq!(addr.port(), SERVER_PORT);
+
+        let addr = resolve_server_addr("localhost:9100").await.unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_eq!(addr.port(), 9100);
+    }
+
+    // テスト: ループバック ::1 への送受信
+    // 目的: サーバのアドレスファミリに合わせてバインドしたソケットで IPv6 通信できることを確認する
+    #[tokio::test]
+    async fn binds_matching_family_and_talks_over_ipv6_loopback() {
+        let server = tokio::net::UdpSocket::bind("[::1]:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let sock = bind_for(server_addr, 0).await.unwrap();
+        assert!(sock.local_addr().unwrap().is_ipv6());
+
+        sock.send_to(b"hello", server_addr).await.unwrap();
+        let mut buf = [0u8; 16];
+        let (len, _) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"hello");
+    }
+}
@@ -0,0 +1,458 @@
+#[cfg(test)]
+mod tui_test {
+    use std::time::{Duration, Instant};
+
+    use client::tui::{
+        Action, App, FILE_RETRY_AFTER, IDLE_AFTER, TYPING_SIGNAL_INTERVAL, save_download,
+    };
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use protocol::{
+        DeliveryStatus, MessageKind, MessageProtocol, ReceiptStatus, transfer::FILE_CHUNK_SIZE,
+    };
+    use ratatui::{Terminal, backend::TestBackend};
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn type_line(app: &mut App, text: &str) -> Action {
+        for c in text.chars() {
+            app.handle_key(key(KeyCode::Char(c)));
+        }
+        app.handle_key(key(KeyCode::Enter))
+    }
+
+    fn message(user: &str, body: &str) -> MessageProtocol {
+        MessageProtocol::new(user, body)
+    }
+
+    // 描画結果を行ごとの文字列として取り出す
+    fn render(app: &mut App, now: Instant, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| app.draw(frame, now)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect())
+            .collect()
+    }
+
+    // テスト: 入力行からの送信
+    // 目的: Enter で入力内容が送信アクションになり、入力行が空になることを確認する
+    #[test]
+    fn enter_submits_input_line() {
+        let mut app = App::new("alice");
+
+        assert_eq!(type_line(&mut app, "hello"), Action::Send("hello".into()));
+        assert!(app.input.is_empty(), "送信後は入力行が空になるべき");
+
+        // 空行は送信しない
+        assert_eq!(type_line(&mut app, "  "), Action::None);
+        assert_eq!(app.handle_key(key(KeyCode::Esc)), Action::Quit);
+    }
+
+    // テスト: 入力履歴
+    // 目的: 上下キーで過去の入力を遡り、最後に空の入力行へ戻ることを確認する
+    #[test]
+    fn arrow_keys_walk_input_history() {
+        let mut app = App::new("alice");
+        type_line(&mut app, "first");
+        type_line(&mut app, "second");
+
+        app.handle_key(key(KeyCode::Up));
+        assert_eq!(app.input, "second");
+        app.handle_key(key(KeyCode::Up));
+        assert_eq!(app.input, "first");
+        app.handle_key(key(KeyCode::Up));
+        assert_eq!(app.input, "first", "最古の履歴で止まるべき");
+        app.handle_key(key(KeyCode::Down));
+        assert_eq!(app.input, "second");
+        app.handle_key(key(KeyCode::Down));
+        assert_eq!(app.input, "", "最新より先は空の入力行に戻るべき");
+    }
+
+    // テスト: メッセージ・ユーザー一覧・入力行の描画
+    // 目的: 各ペインに期待する内容が描画されることを確認する
+    #[test]
+    fn renders_messages_users_and_input() {
+        let now = Instant::now();
+        let mut app = App::new("alice");
+        app.on_message(
+            message("bob", "hi there"),
+            now - IDLE_AFTER - Duration::from_secs(1),
+        );
+        app.on_message(message("carol", "hello"), now);
+        app.handle_key(key(KeyCode::Char('y')));
+
+        let screen = render(&mut app, now, 60, 12).join("\n");
+
+        assert!(screen.contains("<bob>: hi there"), "{screen}");
+        assert!(screen.contains("<carol>: hello"), "{screen}");
+        assert!(
+            screen.contains("bob (idle)"),
+            "しばらく発言のないユーザーは idle 表示: {screen}"
+        );
+        assert!(!screen.contains("carol (idle)"), "{screen}");
+        assert!(
+            screen.contains("alice> y"),
+            "入力行にプロンプトと入力中の文字: {screen}"
+        );
+    }
+
+    // テスト: スクロールバック
+    // 目的: PageUp で過去のメッセージが表示され、PageDown で最新に戻ることを確認する
+    #[test]
+    fn page_keys_scroll_message_pane() {
+        let now = Instant::now();
+        let mut app = App::new("alice");
+        for i in 0..30 {
+            app.on_message(message("bob", &format!("line {i:02}")), now);
+        }
+
+        // 最新のメッセージだけが見えている
+        let screen = render(&mut app, now, 60, 12).join("\n");
+        assert!(screen.contains("line 29") && !screen.contains("line 00"));
+
+        app.handle_key(key(KeyCode::PageUp));
+        let screen = render(&mut app, now, 60, 12).join("\n");
+        assert!(
+            !screen.contains("line 29"),
+            "遡ると最新は見えなくなる: {screen}"
+        );
+        assert!(screen.contains("below"), "{screen}");
+
+        // 遡っている間に届いたメッセージで表示位置がずれない
+        let before = render(&mut app, now, 60, 12);
+        app.on_message(message("bob", "new"), now);
+        assert_eq!(render(&mut app, now, 60, 12)[1..8], before[1..8]);
+
+        app.handle_key(key(KeyCode::PageDown));
+        app.handle_key(key(KeyCode::PageDown));
+        let screen = render(&mut app, now, 60, 12).join("\n");
+        assert!(screen.contains("<bob>: new"), "{screen}");
+    }
+
+    fn stamped(id: u64, user: &str, body: &str) -> MessageProtocol {
+        MessageProtocol {
+            id,
+            ..MessageProtocol::new(user, body)
+        }
+    }
+
+    // テスト: 編集・削除の反映
+    // 目的: 受信した Edit / Delete が対象メッセージに反映され、編集済みと表示されることを確認する
+    #[test]
+    fn applies_edits_and_deletions() {
+        let now = Instant::now();
+        let mut app = App::new("alice");
+        app.on_message(stamped(1, "bob", "helo"), now);
+        app.on_message(stamped(2, "bob", "oops"), now);
+
+        app.on_message(MessageProtocol::edit("bob", 1, "hello"), now);
+        app.on_message(MessageProtocol::delete("bob", 2), now);
+        // 手元にないメッセージへの変更は無視される
+        app.on_message(MessageProtocol::delete("bob", 99), now);
+
+        assert_eq!(app.messages.len(), 1);
+        let screen = render(&mut app, now, 60, 12).join("\n");
+        assert!(screen.contains("#1 <bob>: hello (edited)"), "{screen}");
+        assert!(!screen.contains("oops"), "{screen}");
+    }
+
+    // テスト: 編集・削除コマンドの組み立て
+    // 目的: `/edit` と `/delete` が ID 指定または自分の最新メッセージを対象にすることを確認する
+    #[test]
+    fn composes_edit_and_delete_requests() {
+        let now = Instant::now();
+        let mut app = App::new("alice");
+
+        assert_eq!(
+            app.compose("hi").unwrap(),
+            MessageProtocol::new("alice", "hi")
+        );
+        assert!(
+            app.compose("/edit fixed").is_err(),
+            "自分のメッセージがまだない"
+        );
+
+        app.on_message(stamped(4, "alice", "teh"), now);
+        app.on_message(stamped(5, "bob", "hi"), now);
+        assert_eq!(
+            app.compose("/edit the").unwrap(),
+            MessageProtocol::edit("alice", 4, "the")
+        );
+        assert_eq!(
+            app.compose("/delete #5").unwrap(),
+            MessageProtocol::delete("alice", 5)
+        );
+        assert_eq!(app.compose("/delete").unwrap().kind, MessageKind::Delete);
+        assert!(app.compose("/edit #x text").is_err());
+        assert!(app.compose("/edit #4").is_err(), "本文のない編集は不可");
+    }
+
+    // テスト: 返信とリアクションの表示
+    // 目的: 返信先の要約とリアクションの集計がメッセージ行に表示されることを確認する
+    #[test]
+    fn renders_reply_context_and_reactions() {
+        let now = Instant::now();
+        let mut app = App::new("alice");
+        app.on_message(stamped(1, "bob", "lunch at noon?"), now);
+        app.on_message(
+            MessageProtocol {
+                id: 2,
+                ..MessageProtocol::reply("carol", 1, "yes")
+            },
+            now,
+        );
+        app.on_message(
+            MessageProtocol::reactions("carol", 1, &[("👍".into(), 2)]),
+            now,
+        );
+
+        let screen = render(&mut app, now, 80, 12).join("\n");
+        assert!(
+            screen.contains("<carol>: yes ↩ <bob>: lunch at noon?"),
+            "{screen}"
+        );
+        // 絵文字は2セル幅なので、後ろに空白セルが入る
+        assert!(screen.contains("[👍"), "{screen}");
+        assert!(screen.contains("2]"), "{screen}");
+
+        // 返信・リアクションの既定の対象は他人の最新メッセージ
+        assert_eq!(
+            app.compose("/reply sure").unwrap(),
+            MessageProtocol::reply("alice", 2, "sure")
+        );
+        assert_eq!(
+            app.compose("/react #1 🎉").unwrap(),
+            MessageProtocol::react("alice", 1, "🎉")
+        );
+        assert!(app.compose("/react").is_err());
+    }
+
+    // テスト: 入力中通知の表示
+    // 目的: 他のユーザーの入力中通知が表示され、発言または時間経過で消えることを確認する
+    #[test]
+    fn shows_and_expires_typing_indicator() {
+        let now = Instant::now();
+        let mut app = App::new("alice");
+        app.on_message(MessageProtocol::typing("bob"), now);
+        // 自分の通知と入力中通知はユーザー一覧の活動に含めない
+        app.on_message(MessageProtocol::typing("alice"), now);
+        assert!(app.users.is_empty());
+
+        let screen = render(&mut app, now, 60, 12).join("\n");
+        assert!(screen.contains("bob is typing…"), "{screen}");
+
+        app.on_message(MessageProtocol::typing("carol"), now);
+        let screen = render(&mut app, now, 60, 12).join("\n");
+        assert!(screen.contains("bob and carol are typing…"), "{screen}");
+
+        // bob の発言で bob の表示は消え、carol の通知は時間切れで消える
+        app.on_message(message("bob", "done"), now);
+        assert_eq!(app.typing_users(now), vec!["carol"]);
+        assert!(app.typing_users(now + Duration::from_secs(10)).is_empty());
+    }
+
+    // テスト: 入力中通知の送信間隔
+    // 目的: 入力中の通知が間引かれ、コマンド入力では送られないことを確認する
+    #[test]
+    fn throttles_typing_signals() {
+        let now = Instant::now();
+        let mut app = App::new("alice");
+        assert!(!app.typing_signal_due(now), "空の入力では送らない");
+
+        app.handle_key(key(KeyCode::Char('h')));
+        assert!(app.typing_signal_due(now));
+        app.handle_key(key(KeyCode::Char('i')));
+        assert!(!app.typing_signal_due(now + Duration::from_secs(1)));
+        assert!(app.typing_signal_due(now + TYPING_SIGNAL_INTERVAL));
+
+        // 送信後はすぐに次の通知を送れる
+        app.handle_key(key(KeyCode::Enter));
+        app.handle_key(key(KeyCode::Char('/')));
+        assert!(
+            !app.typing_signal_due(now + TYPING_SIGNAL_INTERVAL),
+            "コマンドは対象外"
+        );
+        app.handle_key(key(KeyCode::Backspace));
+        app.handle_key(key(KeyCode::Char('o')));
+        assert!(app.typing_signal_due(now + TYPING_SIGNAL_INTERVAL));
+    }
+
+    // テスト: 受信確認と既読位置
+    // 目的: 他人のメッセージにだけ受信確認を返し、最新まで表示したときに既読位置を送ることを確認する
+    #[test]
+    fn acknowledges_and_marks_read() {
+        let now = Instant::now();
+        let mut app = App::new("alice");
+        assert_eq!(app.read_marker_due(), None);
+
+        let from_bob = stamped(1, "bob", "hi");
+        assert_eq!(
+            app.ack_for(&from_bob),
+            Some(MessageProtocol::ack("alice", 1))
+        );
+        assert_eq!(app.ack_for(&stamped(2, "alice", "mine")), None);
+        assert_eq!(app.ack_for(&message("bob", "unstamped")), None);
+
+        app.on_message(from_bob, now);
+        assert_eq!(
+            app.read_marker_due(),
+            Some(MessageProtocol::read_up_to("alice", 1))
+        );
+        assert_eq!(app.read_marker_due(), None, "同じ位置は二度送らない");
+
+        // 遡って読んでいる間は既読にしない
+        app.scroll = 1;
+        app.on_message(stamped(3, "bob", "more"), now);
+        assert_eq!(app.read_marker_due(), None);
+        app.scroll = 0;
+        assert_eq!(app.read_marker_due().unwrap().target_id, 3);
+    }
+
+    // テスト: 受信状況の照会
+    // 目的: `/receipts` の照会フレームと、応答が状態欄に表示されることを確認する
+    #[test]
+    fn queries_and_shows_receipts() {
+        let now = Instant::now();
+        let mut app = App::new("alice");
+        app.on_message(stamped(7, "alice", "did you see this?"), now);
+
+        assert_eq!(
+            app.compose("/receipts").unwrap(),
+            MessageProtocol::receipts("alice", 7, &[])
+        );
+        app.on_message(
+            MessageProtocol::receipts(
+                "system",
+                7,
+                &[
+                    ("bob".into(), ReceiptStatus::Read),
+                    ("carol".into(), ReceiptStatus::Pending),
+                ],
+            ),
+            now,
+        );
+        let screen = render(&mut app, now, 80, 12).join("\n");
+        assert!(screen.contains("#7: bob read, carol pending"), "{screen}");
+    }
+
+    // テスト: ダイレクトメッセージ
+    // 目的: `/msg` で DM を組み立て、受信した DM と配送状況が表示されることを確認する
+    #[test]
+    fn sends_and_shows_direct_messages() {
+        let now = Instant::now();
+        let mut app = App::new("alice");
+        assert_eq!(
+            app.compose("/msg bob  are you there?").unwrap(),
+            MessageProtocol::direct("alice", "bob", "are you there?")
+        );
+        assert!(app.compose("/msg bob").is_err());
+
+        app.on_message(
+            MessageProtocol {
+                id: 8,
+                ..MessageProtocol::direct("alice", "bob", "are you there?")
+            },
+            now,
+        );
+        app.on_message(
+            MessageProtocol::direct_status("system", 8, "bob", DeliveryStatus::Queued),
+            now,
+        );
+        let screen = render(&mut app, now, 80, 12).join("\n");
+        assert!(screen.contains("<alice> → bob: are you there?"), "{screen}");
+        assert!(screen.contains("message #8 to bob: queued"), "{screen}");
+        assert_eq!(
+            app.messages.len(),
+            1,
+            "配送状況はスクロールバックに残さない"
+        );
+    }
+
+    // テスト: ファイルの送受信
+    // 目的: 申し出のエコーで送信が始まり、受信側が欠けを検出して再送を頼み、
+    //       チェックサムを確かめたファイルだけを保存待ちにすることを確認する
+    #[test]
+    fn sends_and_receives_files() {
+        let now = Instant::now();
+        let dir = std::env::temp_dir().join(format!("tui-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let data: Vec<u8> = (0..FILE_CHUNK_SIZE * 2 + 5).map(|i| i as u8).collect();
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, &data).unwrap();
+
+        // ❶ 送信側: 申し出が ID 付きで戻るとチャンクを送り始める
+        let mut alice = App::new("alice");
+        assert!(alice.compose("/send").is_err());
+        assert!(alice.offer_file(&dir.join("missing")).is_err());
+        let offer = alice.offer_file(&path).unwrap();
+        assert_eq!(offer.kind, MessageKind::FileOffer);
+        let echoed = MessageProtocol { id: 5, ..offer };
+        let chunks = alice.on_transfer(&echoed, now);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.target_id == 5));
+        alice.on_message(echoed.clone(), now);
+        let screen = render(&mut alice, now, 80, 12).join("\n");
+        assert!(screen.contains("offers notes.txt (1029 bytes)"), "{screen}");
+
+        // 確認応答がなければ送り直す
+        let later = now + FILE_RETRY_AFTER;
+        assert_eq!(alice.transfer_retries(later).len(), 3);
+        let done = alice.on_transfer(&MessageProtocol::file_ack("system", 5, Some(3)), later);
+        assert!(done.is_empty());
+        assert!(alice.status.as_deref().unwrap().contains("sent notes.txt"));
+
+        // ❷ 受信側: 欠けがあれば一度だけ再送を頼む
+        let mut bob = App::new("bob");
+        bob.on_transfer(&echoed, now);
+        let resume = bob.on_transfer(&chunks[1], now);
+        assert_eq!(resume, vec![MessageProtocol::file_ack("bob", 5, Some(0))]);
+        assert!(bob.on_transfer(&chunks[2], now).is_empty());
+        for chunk in &chunks {
+            bob.on_transfer(chunk, now);
+        }
+        assert_eq!(
+            bob.received_files,
+            vec![("notes.txt".to_string(), data.clone())]
+        );
+        assert!(
+            bob.transfer_retries(later).is_empty(),
+            "完了した転送は再開しない"
+        );
+
+        // ❸ 保存時にはディレクトリ部分を取り除く
+        let saved = save_download(&dir.join("downloads"), "../../evil.txt", &data).unwrap();
+        assert_eq!(saved, dir.join("downloads").join("evil.txt"));
+        assert_eq!(std::fs::read(&saved).unwrap(), data);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // テスト: 上限を超える入力
+    // 目的: 符号化できない入力で落ちず、理由を状態行に出して何も送らないことを確認する
+    #[test]
+    fn oversized_input_is_reported_not_sent() {
+        let mut app = App::new("alice");
+
+        // 圧縮で縮まない長い行
+        let mut seed = 7u32;
+        let line: String = (0..5000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                char::from(b'!' + (seed >> 16) as u8 % 90)
+            })
+            .collect();
+        let long = app.compose(&line).unwrap();
+        assert_eq!(app.encode_input(&long), None);
+        assert!(app.status.is_some(), "理由が表示されるべき");
+
+        let recipient = "r".repeat(300);
+        let direct = app.compose(&format!("/msg {recipient} hi")).unwrap();
+        assert_eq!(app.encode_input(&direct), None);
+
+        // 送れるものを送ると状態行は消える
+        let ok = app.compose("hello").unwrap();
+        assert_eq!(app.encode_input(&ok), Some(ok.serialize().unwrap()));
+        assert_eq!(app.status, None);
+    }
+}
@@ -0,0 +1,22 @@
+//! Fuzz target for [`MessageProtocol::deserialize`].
+//!
+//! 任意のバイト列で panic しないこと、読めたフレームは書き戻しても
+//! 同じメッセージに戻ることを確かめる。
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protocol::MessageProtocol;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(message) = MessageProtocol::deserialize(data) else {
+        return;
+    };
+    // 本文の解釈も受信側で走るので一緒に試す
+    let _ = message.to_string();
+    let _ = (message.offer(), message.chunk(), message.tally());
+    let _ = message.receipt_states();
+    if let Ok(frame) = message.serialize() {
+        assert_eq!(MessageProtocol::deserialize(&frame).unwrap(), message);
+    }
+});
@@ -0,0 +1,156 @@
+//! Interchangeable encodings of [`MessageProtocol`].
+//!
+//! [`WireCodec`] is the hand-rolled layout described at the crate root and
+//! what every client starts with. [`BincodeCodec`] is a compact serde
+//! encoding and [`JsonCodec`] a readable one for debugging. A client switches
+//! by sending a `Codec` frame naming one (see [`CodecId`]); `Codec` frames are
+//! always wire-encoded so either side can read them whatever is in use.
+//! Every codec enforces [`MAX_BUFFER_SIZE`] on encoded frames and the wire
+//! format's field limits ([`MessageProtocol::validate`]) on messages.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{MAX_BUFFER_SIZE, MessageProtocol, ProtocolError};
+
+/// Turns messages into datagrams and back.
+pub trait Codec: Send + Sync {
+    fn id(&self) -> CodecId;
+
+    fn encode(&self, message: &MessageProtocol) -> Result<Vec<u8>, ProtocolError>;
+
+    fn decode(&self, frame: &[u8]) -> Result<MessageProtocol, ProtocolError>;
+}
+
+/// Names of the available codecs, as carried by a `Codec` frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CodecId {
+    #[default]
+    Wire,
+    Bincode,
+    Json,
+}
+
+impl CodecId {
+    pub const ALL: [CodecId; 3] = [CodecId::Wire, CodecId::Bincode, CodecId::Json];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Wire => "wire",
+            Self::Bincode => "bincode",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|id| id.as_str() == name)
+    }
+
+    /// The codec this id names.
+    pub fn codec(self) -> &'static dyn Codec {
+        match self {
+            Self::Wire => &WireCodec,
+            Self::Bincode => &BincodeCodec,
+            Self::Json => &JsonCodec,
+        }
+    }
+}
+
+impl fmt::Display for CodecId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The hand-rolled binary layout ([`MessageProtocol::serialize`]).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WireCodec;
+
+impl Codec for WireCodec {
+    fn id(&self) -> CodecId {
+        CodecId::Wire
+    }
+
+    fn encode(&self, message: &MessageProtocol) -> Result<Vec<u8>, ProtocolError> {
+        message.serialize()
+    }
+
+    fn decode(&self, frame: &[u8]) -> Result<MessageProtocol, ProtocolError> {
+        MessageProtocol::deserialize(frame)
+    }
+}
+
+/// bincode's standard configuration over the serde derives.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn id(&self) -> CodecId {
+        CodecId::Bincode
+    }
+
+    fn encode(&self, message: &MessageProtocol) -> Result<Vec<u8>, ProtocolError> {
+        message.validate()?;
+        let frame = bincode::serde::encode_to_vec(message, bincode::config::standard())
+            .map_err(|e| codec_error(CodecId::Bincode, e))?;
+        check_size(frame)
+    }
+
+    fn decode(&self, frame: &[u8]) -> Result<MessageProtocol, ProtocolError> {
+        check_size(frame)?;
+        // 長さの欄を偽ったフレームで大きな領域を確保しないよう上限を付ける
+        let config = bincode::config::standard().with_limit::<MAX_BUFFER_SIZE>();
+        let (message, read) = bincode::serde::decode_from_slice(frame, config)
+            .map_err(|e| codec_error(CodecId::Bincode, e))?;
+        if read != frame.len() {
+            return Err(codec_error(
+                CodecId::Bincode,
+                format!("{} trailing bytes", frame.len() - read),
+            ));
+        }
+        validated(message)
+    }
+}
+
+/// JSON, for reading frames in a packet capture.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn id(&self) -> CodecId {
+        CodecId::Json
+    }
+
+    fn encode(&self, message: &MessageProtocol) -> Result<Vec<u8>, ProtocolError> {
+        message.validate()?;
+        let frame = serde_json::to_vec(message).map_err(|e| codec_error(CodecId::Json, e))?;
+        check_size(frame)
+    }
+
+    fn decode(&self, frame: &[u8]) -> Result<MessageProtocol, ProtocolError> {
+        check_size(frame)?;
+        serde_json::from_slice(frame)
+            .map_err(|e| codec_error(CodecId::Json, e))
+            .and_then(validated)
+    }
+}
+
+fn check_size<T: AsRef<[u8]>>(frame: T) -> Result<T, ProtocolError> {
+    match frame.as_ref().len() {
+        len if len > MAX_BUFFER_SIZE => Err(ProtocolError::BufferTooLarge(len)),
+        _ => Ok(frame),
+    }
+}
+
+// serde の方式は wire 形式の上限を知らないので、解析後に確かめる
+fn validated(message: MessageProtocol) -> Result<MessageProtocol, ProtocolError> {
+    message.validate().map(|()| message)
+}
+
+fn codec_error(codec: CodecId, reason: impl fmt::Display) -> ProtocolError {
+    ProtocolError::Codec {
+        codec,
+        reason: reason.to_string(),
+    }
+}
@@ -1,17 +1,221 @@
 //! Protocol for the UDP transmission.
 //!
 //! * Max frame size : 4096bytes
-//! * Byte 0 : user-name length (`u8`, 0 - 255)
-//! * Byte 1 - 1 + user-name length : user-name
-//! * Byte user-name length + 1 -: message data
+//! * Byte 0 - 7 : message id (`u64`, big-endian, 0 = not assigned)
+//! * Byte 8 - 15 : server receive time in Unix millis (`u64`, big-endian, 0 = not stamped)
+//! * Byte 16 : message kind (`u8`, see [`MessageKind`]); the high bit is
+//!   [`COMPRESSED_FLAG`]
+//! * Byte 17 - 24 : target message id (`u64`, big-endian, 0 = none)
+//! * Byte 25 - 32 : id of the message being replied to (`u64`, big-endian, 0 = none)
+//! * Byte 33 : user-name length (`u8`, 0 - 255)
+//! * Byte 34 - 34 + user-name length : user-name
+//! * Next byte : recipient length (`u8`, 0 = the whole room)
+//! * Next recipient-length bytes : recipient user-name
+//! * Remaining bytes : message data
+//!
+//! Bodies of at least [`COMPRESSION_THRESHOLD`] bytes are LZ4-compressed when
+//! that makes them smaller. A compressed body starts with its decompressed
+//! length (`u32`, little-endian), which may not exceed
+//! [`MAX_DECOMPRESSED_SIZE`].
+//!
+//! Clients send `id` and `timestamp_ms` as 0; the server fills both in before
+//! relaying. `Edit`, `Delete` and `React` frames name the message they change
+//! in `target_id`; an `Edit` body is the replacement text and a `React` body is
+//! the reaction (e.g. an emoji). The server answers a `React` with a
+//! `Reactions` frame carrying the new tally, one `<reaction> <count>` per line.
+//!
+//! `Typing` frames are ephemeral: they have no id, are never stored, and a
+//! receiver forgets them after [`TYPING_TTL_MS`].
+//!
+//! Receipts are control frames between one client and the server: `Ack`
+//! confirms delivery of message `target_id`, `Read` marks every message up to
+//! `target_id` as read, and a `Receipts` frame with an empty body asks for the
+//! state of message `target_id`. The server answers with a `Receipts` frame
+//! listing `<user> <status>` per line (see [`ReceiptStatus`]).
+//!
+//! A `Direct` frame is a private message to `recipient`. The server answers
+//! the sender with `DirectStatus` frames for the message `target_id` whose
+//! body is a [`DeliveryStatus`].
+//!
+//! Files are sent as a `FileOffer` followed by `FileChunk` and `FileAck`
+//! frames naming the offer in `target_id`; see [`transfer`].
+//!
+//! This layout is the default [`codec::WireCodec`]. A `Codec` frame, always in
+//! this layout, asks to switch to another [`codec::CodecId`] (its body).
 
 use std::fmt;
 
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+pub mod codec;
+pub mod transfer;
+
+use codec::CodecId;
+
+use transfer::FileOffer;
+
 pub const MAX_BUFFER_SIZE: usize = 4096;
+/// Bytes preceding the user-name length byte.
+pub const HEADER_SIZE: usize = 33;
+/// How long a `Typing` signal stays valid without being repeated.
+pub const TYPING_TTL_MS: u64 = 5_000;
+/// Longest accepted reaction, in bytes.
+pub const MAX_REACTION_LEN: usize = 32;
+/// Set in the kind byte when the body is compressed.
+pub const COMPRESSED_FLAG: u8 = 0x80;
+/// Bodies shorter than this are sent as they are.
+pub const COMPRESSION_THRESHOLD: usize = 128;
+/// Largest body a compressed frame may expand to, in bytes.
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024;
+
+/// What a frame asks the receiver to do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum MessageKind {
+    /// A new chat message.
+    #[default]
+    Chat = 0,
+    /// Replace the body of the message `target_id`.
+    Edit = 1,
+    /// Retract the message `target_id`.
+    Delete = 2,
+    /// Toggle the sender's reaction (the body) on the message `target_id`.
+    React = 3,
+    /// Server-sent tally of every reaction on the message `target_id`.
+    Reactions = 4,
+    /// The sender is composing a message.
+    Typing = 5,
+    /// The sender received the message `target_id`.
+    Ack = 6,
+    /// The sender has read every message up to and including `target_id`.
+    Read = 7,
+    /// Query (empty body) or report of the receipt state of message `target_id`.
+    Receipts = 8,
+    /// A private message to `recipient`.
+    Direct = 9,
+    /// Server report on the direct message `target_id` (body: [`DeliveryStatus`]).
+    DirectStatus = 10,
+    /// Announces a file (body: [`FileOffer`]); its id identifies the transfer.
+    FileOffer = 11,
+    /// One piece of the file offered in `target_id`.
+    FileChunk = 12,
+    /// The first chunk of transfer `target_id` not yet received (empty = query).
+    FileAck = 13,
+    /// Request (client) or confirmation (server) of the codec named in the body.
+    Codec = 14,
+    /// Keeps the sender joined while it has nothing to say; never relayed.
+    Heartbeat = 15,
+}
+
+impl TryFrom<u8> for MessageKind {
+    type Error = ProtocolError;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        match byte {
+            0 => Ok(Self::Chat),
+            1 => Ok(Self::Edit),
+            2 => Ok(Self::Delete),
+            3 => Ok(Self::React),
+            4 => Ok(Self::Reactions),
+            5 => Ok(Self::Typing),
+            6 => Ok(Self::Ack),
+            7 => Ok(Self::Read),
+            8 => Ok(Self::Receipts),
+            9 => Ok(Self::Direct),
+            10 => Ok(Self::DirectStatus),
+            11 => Ok(Self::FileOffer),
+            12 => Ok(Self::FileChunk),
+            13 => Ok(Self::FileAck),
+            14 => Ok(Self::Codec),
+            15 => Ok(Self::Heartbeat),
+            other => Err(ProtocolError::UnknownKind(other)),
+        }
+    }
+}
+
+/// How far a message has got with one recipient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ReceiptStatus {
+    Pending,
+    Delivered,
+    Read,
+}
+
+impl ReceiptStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Delivered => "delivered",
+            Self::Read => "read",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "pending" => Some(Self::Pending),
+            "delivered" => Some(Self::Delivered),
+            "read" => Some(Self::Read),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ReceiptStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What happened to a direct message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeliveryStatus {
+    /// The recipient is offline; the message waits on the server.
+    Queued,
+    Delivered,
+    /// The recipient did not come back before the message expired.
+    Expired,
+}
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl DeliveryStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Delivered => "delivered",
+            Self::Expired => "expired",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "queued" => Some(Self::Queued),
+            "delivered" => Some(Self::Delivered),
+            "expired" => Some(Self::Expired),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MessageProtocol {
+    /// Monotonic id assigned by the server (0 until stamped).
+    pub id: u64,
+    /// Server receive time in Unix milliseconds (0 until stamped).
+    pub timestamp_ms: u64,
+    pub kind: MessageKind,
+    /// Message an `Edit`, `Delete`, `React` or `Reactions` refers to (0 = none).
+    pub target_id: u64,
+    /// Message this chat message answers (0 = not a reply).
+    pub reply_to: u64,
     pub user_name: String,
+    /// Recipient of a `Direct` message (empty = the whole room).
+    pub recipient: String,
     pub body: String,
 }
 
@@ -26,25 +230,288 @@ pub enum ProtocolError {
     #[error("frame truncated: expected {expected} bytes, have {actual}")]
     Truncated { expected: usize, actual: usize },
 
+    #[error("unknown message kind: {0}")]
+    UnknownKind(u8),
+
     #[error("invalid UTF‑8 in username: {0}")]
     UsernameUtf8(#[from] std::string::FromUtf8Error),
 
     #[error("invalid UTF‑8 in body: {0}")]
     BodyUtf8(#[from] std::str::Utf8Error),
+
+    #[error("compressed body expands to {0} bytes (max {MAX_DECOMPRESSED_SIZE})")]
+    DecompressedTooLarge(usize),
+
+    #[error("corrupt compressed body")]
+    CorruptBody,
+
+    #[error("{codec} codec: {reason}")]
+    Codec { codec: CodecId, reason: String },
 }
 
 impl MessageProtocol {
+    /// Create an unstamped message, as sent by a client.
+    pub fn new(user_name: impl Into<String>, body: impl Into<String>) -> Self {
+        Self {
+            user_name: user_name.into(),
+            body: body.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Create an unstamped request to replace the body of message `target_id`.
+    pub fn edit(user_name: impl Into<String>, target_id: u64, body: impl Into<String>) -> Self {
+        Self {
+            kind: MessageKind::Edit,
+            target_id,
+            ..Self::new(user_name, body)
+        }
+    }
+
+    /// Create an unstamped chat message answering message `reply_to`.
+    pub fn reply(user_name: impl Into<String>, reply_to: u64, body: impl Into<String>) -> Self {
+        Self {
+            reply_to,
+            ..Self::new(user_name, body)
+        }
+    }
+
+    /// Create an unstamped request to toggle `reaction` on message `target_id`.
+    pub fn react(
+        user_name: impl Into<String>,
+        target_id: u64,
+        reaction: impl Into<String>,
+    ) -> Self {
+        Self {
+            kind: MessageKind::React,
+            target_id,
+            ..Self::new(user_name, reaction)
+        }
+    }
+
+    /// Create a reaction tally for message `target_id`, as sent by the server.
+    pub fn reactions(
+        user_name: impl Into<String>,
+        target_id: u64,
+        tally: &[(String, usize)],
+    ) -> Self {
+        let body = tally
+            .iter()
+            .map(|(reaction, count)| format!("{reaction} {count}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Self {
+            kind: MessageKind::Reactions,
+            target_id,
+            ..Self::new(user_name, body)
+        }
+    }
+
+    /// Parse the body of a `Reactions` frame. Malformed lines are skipped.
+    pub fn tally(&self) -> Vec<(String, usize)> {
+        self.body
+            .lines()
+            .filter_map(|line| {
+                let (reaction, count) = line.rsplit_once(' ')?;
+                Some((reaction.to_string(), count.parse().ok()?))
+            })
+            .collect()
+    }
+
+    /// Create a typing signal.
+    pub fn typing(user_name: impl Into<String>) -> Self {
+        Self {
+            kind: MessageKind::Typing,
+            ..Self::new(user_name, "")
+        }
+    }
+
+    /// Create an unstamped private message to `recipient`.
+    pub fn direct(
+        user_name: impl Into<String>,
+        recipient: impl Into<String>,
+        body: impl Into<String>,
+    ) -> Self {
+        Self {
+            kind: MessageKind::Direct,
+            recipient: recipient.into(),
+            ..Self::new(user_name, body)
+        }
+    }
+
+    /// Create a report on the direct message `target_id`, as sent by the server.
+    pub fn direct_status(
+        user_name: impl Into<String>,
+        target_id: u64,
+        recipient: impl Into<String>,
+        status: DeliveryStatus,
+    ) -> Self {
+        Self {
+            kind: MessageKind::DirectStatus,
+            target_id,
+            recipient: recipient.into(),
+            ..Self::new(user_name, status.as_str())
+        }
+    }
+
+    /// The status carried by a `DirectStatus` frame.
+    pub fn delivery_status(&self) -> Option<DeliveryStatus> {
+        DeliveryStatus::parse(&self.body)
+    }
+
+    /// Create an unstamped offer to send a file to the room.
+    pub fn file_offer(user_name: impl Into<String>, offer: &FileOffer) -> Self {
+        Self {
+            kind: MessageKind::FileOffer,
+            ..Self::new(user_name, offer.to_body())
+        }
+    }
+
+    /// The offer carried by a `FileOffer` frame.
+    pub fn offer(&self) -> Option<FileOffer> {
+        FileOffer::parse(&self.body)
+    }
+
+    /// Create chunk `index` of transfer `target_id`.
+    pub fn file_chunk(
+        user_name: impl Into<String>,
+        target_id: u64,
+        index: u32,
+        data: &[u8],
+    ) -> Self {
+        Self {
+            kind: MessageKind::FileChunk,
+            target_id,
+            ..Self::new(user_name, format!("{index} {}", STANDARD.encode(data)))
+        }
+    }
+
+    /// Parse the index and data of a `FileChunk` frame.
+    pub fn chunk(&self) -> Option<(u32, Vec<u8>)> {
+        let (index, data) = self.body.split_once(' ')?;
+        Some((index.parse().ok()?, STANDARD.decode(data).ok()?))
+    }
+
+    /// Acknowledge every chunk of transfer `target_id` before `next`;
+    /// `None` asks where to resume.
+    pub fn file_ack(user_name: impl Into<String>, target_id: u64, next: Option<u32>) -> Self {
+        Self {
+            kind: MessageKind::FileAck,
+            target_id,
+            ..Self::new(user_name, next.map(|n| n.to_string()).unwrap_or_default())
+        }
+    }
+
+    /// The chunk index carried by a `FileAck` frame (`None` for a query).
+    pub fn ack_index(&self) -> Option<u32> {
+        self.body.parse().ok()
+    }
+
+    /// Create a request for (or confirmation of) `codec`.
+    pub fn codec(user_name: impl Into<String>, codec: CodecId) -> Self {
+        Self {
+            kind: MessageKind::Codec,
+            ..Self::new(user_name, codec.as_str())
+        }
+    }
+
+    /// The codec named by a `Codec` frame.
+    pub fn codec_id(&self) -> Option<CodecId> {
+        CodecId::parse(&self.body)
+    }
+
+    /// Create a keepalive that also rejoins the sender after a timeout.
+    pub fn heartbeat(user_name: impl Into<String>) -> Self {
+        Self {
+            kind: MessageKind::Heartbeat,
+            ..Self::new(user_name, "")
+        }
+    }
+
+    /// Create a delivery acknowledgement for message `target_id`.
+    pub fn ack(user_name: impl Into<String>, target_id: u64) -> Self {
+        Self {
+            kind: MessageKind::Ack,
+            target_id,
+            ..Self::new(user_name, "")
+        }
+    }
+
+    /// Create a marker saying every message up to `target_id` has been read.
+    pub fn read_up_to(user_name: impl Into<String>, target_id: u64) -> Self {
+        Self {
+            kind: MessageKind::Read,
+            target_id,
+            ..Self::new(user_name, "")
+        }
+    }
+
+    /// Create a receipt report for message `target_id`; an empty `states` is a query.
+    pub fn receipts(
+        user_name: impl Into<String>,
+        target_id: u64,
+        states: &[(String, ReceiptStatus)],
+    ) -> Self {
+        let body = states
+            .iter()
+            .map(|(user, status)| format!("{user} {status}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Self {
+            kind: MessageKind::Receipts,
+            target_id,
+            ..Self::new(user_name, body)
+        }
+    }
+
+    /// Parse the body of a `Receipts` frame. Malformed lines are skipped.
+    pub fn receipt_states(&self) -> Vec<(String, ReceiptStatus)> {
+        self.body
+            .lines()
+            .filter_map(|line| {
+                let (user, status) = line.rsplit_once(' ')?;
+                Some((user.to_string(), ReceiptStatus::parse(status)?))
+            })
+            .collect()
+    }
+
+    /// Create an unstamped request to delete message `target_id`.
+    pub fn delete(user_name: impl Into<String>, target_id: u64) -> Self {
+        Self {
+            kind: MessageKind::Delete,
+            target_id,
+            ..Self::new(user_name, "")
+        }
+    }
+
     /// Serialise a [`MessageProtocol`] into a wire‑format byte vector.
     pub fn serialize(&self) -> Result<Vec<u8>, ProtocolError> {
+        self.validate()?;
         let name_bytes = self.user_name.as_bytes();
-        if name_bytes.len() > u8::MAX as usize {
-            return Err(ProtocolError::UsernameTooLong(name_bytes.len()));
-        }
+        let recipient_bytes = self.recipient.as_bytes();
+
+        // 小さくなる場合だけ圧縮する
+        let compressed = (self.body.len() >= COMPRESSION_THRESHOLD)
+            .then(|| lz4_flex::compress_prepend_size(self.body.as_bytes()))
+            .filter(|compressed| compressed.len() < self.body.len());
+        let (kind, body) = match &compressed {
+            Some(compressed) => (self.kind as u8 | COMPRESSED_FLAG, compressed.as_slice()),
+            None => (self.kind as u8, self.body.as_bytes()),
+        };
 
-        let mut buf = Vec::with_capacity(1 + name_bytes.len() + self.body.len());
+        let mut buf = Vec::with_capacity(
+            HEADER_SIZE + 2 + name_bytes.len() + recipient_bytes.len() + body.len(),
+        );
+        buf.extend_from_slice(&self.id.to_be_bytes());
+        buf.extend_from_slice(&self.timestamp_ms.to_be_bytes());
+        buf.push(kind);
+        buf.extend_from_slice(&self.target_id.to_be_bytes());
+        buf.extend_from_slice(&self.reply_to.to_be_bytes());
         buf.push(name_bytes.len() as u8);
         buf.extend_from_slice(name_bytes);
-        buf.extend_from_slice(self.body.as_bytes());
+        buf.push(recipient_bytes.len() as u8);
+        buf.extend_from_slice(recipient_bytes);
+        buf.extend_from_slice(body);
 
         if buf.len() > MAX_BUFFER_SIZE {
             return Err(ProtocolError::BufferTooLarge(buf.len()));
@@ -52,21 +519,45 @@ impl MessageProtocol {
         Ok(buf)
     }
 
+    /// Check the limits the wire format imposes, whatever the codec.
+    ///
+    /// Names and recipients fit in 255 bytes and the body in
+    /// [`MAX_DECOMPRESSED_SIZE`].
+    pub fn validate(&self) -> Result<(), ProtocolError> {
+        for name in [&self.user_name, &self.recipient] {
+            if name.len() > u8::MAX as usize {
+                return Err(ProtocolError::UsernameTooLong(name.len()));
+            }
+        }
+        if self.body.len() > MAX_DECOMPRESSED_SIZE {
+            return Err(ProtocolError::DecompressedTooLarge(self.body.len()));
+        }
+        Ok(())
+    }
+
     /// Deserialise a wire‑format byte vector into a [`MessageProtocol`].
     pub fn deserialize(buf: &[u8]) -> Result<Self, ProtocolError> {
         if buf.len() > MAX_BUFFER_SIZE {
             return Err(ProtocolError::BufferTooLarge(buf.len()));
         }
 
-        if buf.is_empty() {
+        if buf.len() < HEADER_SIZE + 1 {
             return Err(ProtocolError::Truncated {
-                expected: 1,
-                actual: 0,
+                expected: HEADER_SIZE + 1,
+                actual: buf.len(),
             });
         }
 
-        let name_len = buf[0] as usize;
-        let expected_min = 1 + name_len;
+        let id = u64::from_be_bytes(buf[0..8].try_into().unwrap());
+        let timestamp_ms = u64::from_be_bytes(buf[8..16].try_into().unwrap());
+        let compressed = buf[16] & COMPRESSED_FLAG != 0;
+        let kind = MessageKind::try_from(buf[16] & !COMPRESSED_FLAG)
+            .map_err(|_| ProtocolError::UnknownKind(buf[16]))?;
+        let target_id = u64::from_be_bytes(buf[17..25].try_into().unwrap());
+        let reply_to = u64::from_be_bytes(buf[25..33].try_into().unwrap());
+        let name_start = HEADER_SIZE + 1;
+        let name_len = buf[HEADER_SIZE] as usize;
+        let expected_min = name_start + name_len;
         if buf.len() < expected_min {
             return Err(ProtocolError::Truncated {
                 expected: expected_min,
@@ -74,18 +565,115 @@ impl MessageProtocol {
             });
         }
 
-        let username = String::from_utf8(buf[1..1 + name_len].to_vec())?;
-        let body_bytes = &buf[1 + name_len..];
-        let body = std::str::from_utf8(body_bytes)?.to_owned();
+        let username = String::from_utf8(buf[name_start..expected_min].to_vec())?;
+
+        // 宛先の長さのバイトと宛先
+        let recipient_start = expected_min + 1;
+        let recipient_len = *buf.get(expected_min).ok_or(ProtocolError::Truncated {
+            expected: recipient_start,
+            actual: buf.len(),
+        })? as usize;
+        let body_start = recipient_start + recipient_len;
+        if buf.len() < body_start {
+            return Err(ProtocolError::Truncated {
+                expected: body_start,
+                actual: buf.len(),
+            });
+        }
+        let recipient = String::from_utf8(buf[recipient_start..body_start].to_vec())?;
+        let body_bytes = &buf[body_start..];
+        let body = if compressed {
+            let body_bytes = decompress(body_bytes)?;
+            std::str::from_utf8(&body_bytes)?.to_owned()
+        } else {
+            std::str::from_utf8(body_bytes)?.to_owned()
+        };
         Ok(MessageProtocol {
+            id,
+            timestamp_ms,
+            kind,
+            target_id,
+            reply_to,
             user_name: username,
+            recipient,
             body,
         })
     }
 }
 
+// 展開後の長さを先に確かめ、上限を超える領域は確保しない
+fn decompress(body: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let (size, data) = body
+        .split_first_chunk::<4>()
+        .ok_or(ProtocolError::CorruptBody)?;
+    let size = u32::from_le_bytes(*size) as usize;
+    if size > MAX_DECOMPRESSED_SIZE {
+        return Err(ProtocolError::DecompressedTooLarge(size));
+    }
+    lz4_flex::decompress(data, size)
+        .ok()
+        .filter(|body| body.len() == size)
+        .ok_or(ProtocolError::CorruptBody)
+}
+
 impl fmt::Display for MessageProtocol {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "<{}>: {}", self.user_name, self.body)
+        match self.kind {
+            MessageKind::Chat => write!(f, "<{}>: {}", self.user_name, self.body),
+            MessageKind::Edit => write!(
+                f,
+                "<{}> edited #{}: {}",
+                self.user_name, self.target_id, self.body
+            ),
+            MessageKind::Delete => write!(f, "<{}> deleted #{}", self.user_name, self.target_id),
+            MessageKind::React => write!(
+                f,
+                "<{}> reacted {} to #{}",
+                self.user_name, self.body, self.target_id
+            ),
+            MessageKind::Typing => write!(f, "{} is typing…", self.user_name),
+            MessageKind::Direct => write!(
+                f,
+                "<{}> → {}: {}",
+                self.user_name, self.recipient, self.body
+            ),
+            MessageKind::DirectStatus => write!(
+                f,
+                "message #{} to {}: {}",
+                self.target_id, self.recipient, self.body
+            ),
+            MessageKind::FileOffer => match self.offer() {
+                Some(offer) => write!(f, "<{}> offers {offer}", self.user_name),
+                None => write!(f, "<{}> offers a file", self.user_name),
+            },
+            MessageKind::FileChunk => write!(
+                f,
+                "<{}> chunk {} of #{}",
+                self.user_name,
+                self.body.split(' ').next().unwrap_or_default(),
+                self.target_id
+            ),
+            MessageKind::FileAck => write!(
+                f,
+                "<{}> needs chunk {} of #{}",
+                self.user_name, self.body, self.target_id
+            ),
+            MessageKind::Codec => write!(f, "<{}> uses the {} codec", self.user_name, self.body),
+            MessageKind::Heartbeat => write!(f, "<{}> is still here", self.user_name),
+            MessageKind::Ack => write!(f, "<{}> received #{}", self.user_name, self.target_id),
+            MessageKind::Read => write!(f, "<{}> read up to #{}", self.user_name, self.target_id),
+            MessageKind::Receipts => write!(
+                f,
+                "receipts for #{}: {}",
+                self.target_id,
+                self.body.replace('\n', ", ")
+            ),
+            MessageKind::Reactions => write!(
+                f,
+                "reactions on #{}: {}",
+                self.target_id,
+                self.body.replace('\n', ", ")
+            ),
+        }
     }
 }
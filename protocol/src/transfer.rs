@@ -0,0 +1,247 @@
+//! Chunked file transfer.
+//!
+//! A transfer starts with a `FileOffer` frame whose body is
+//! `<size> <sha256 hex> <file name>`; the server stamps it and its id becomes
+//! the transfer id. The file is then sent as `FileChunk` frames of
+//! [`FILE_CHUNK_SIZE`] bytes (body: `<index> <base64 data>`), at most
+//! [`FILE_WINDOW`] chunks ahead of the last acknowledgement. A `FileAck` body
+//! is the index of the first chunk not yet received, so an unacknowledged
+//! window is resent from there (go-back-N), and a transfer interrupted at any
+//! point resumes from the last acknowledged chunk.
+//!
+//! [`Upload`] and [`Download`] hold the state of each side; a download only
+//! yields the file once its SHA-256 matches the offer.
+
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+/// Payload bytes per chunk; a chunk frame stays under 1 KiB after base64.
+pub const FILE_CHUNK_SIZE: usize = 512;
+/// Chunks a sender may have in flight beyond the last acknowledgement.
+pub const FILE_WINDOW: u32 = 8;
+
+/// Name, size and checksum announced before a file is sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOffer {
+    pub name: String,
+    pub size: u64,
+    pub sha256: [u8; 32],
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum TransferError {
+    #[error("chunk {index} is out of range (file has {count} chunks)")]
+    OutOfRange { index: u32, count: u32 },
+
+    #[error("chunk {index} has {actual} bytes, expected {expected}")]
+    BadLength {
+        index: u32,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("only {received} of {count} chunks received")]
+    Incomplete { received: u32, count: u32 },
+
+    #[error("checksum mismatch")]
+    ChecksumMismatch,
+}
+
+impl FileOffer {
+    /// Describe `data` for sending under `name`.
+    pub fn for_data(name: impl Into<String>, data: &[u8]) -> Self {
+        Self {
+            name: name.into(),
+            size: data.len() as u64,
+            sha256: Sha256::digest(data).into(),
+        }
+    }
+
+    /// Number of chunks the file is split into.
+    pub fn chunk_count(&self) -> u32 {
+        self.size.div_ceil(FILE_CHUNK_SIZE as u64) as u32
+    }
+
+    // 最後のチャンクだけが短くなりうる
+    fn chunk_len(&self, index: u32) -> usize {
+        let start = index as u64 * FILE_CHUNK_SIZE as u64;
+        (self.size - start).min(FILE_CHUNK_SIZE as u64) as usize
+    }
+
+    /// Encode as the body of a `FileOffer` frame.
+    pub fn to_body(&self) -> String {
+        let hash: String = self.sha256.iter().map(|b| format!("{b:02x}")).collect();
+        format!("{} {hash} {}", self.size, self.name)
+    }
+
+    /// Parse the body of a `FileOffer` frame.
+    pub fn parse(body: &str) -> Option<Self> {
+        let mut parts = body.splitn(3, ' ');
+        let size = parts.next()?.parse().ok()?;
+        let hash = parts.next()?;
+        let name = parts.next().filter(|name| !name.is_empty())?;
+        if hash.len() != 64 || !hash.is_ascii() {
+            return None;
+        }
+        let mut sha256 = [0u8; 32];
+        for (i, byte) in sha256.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hash[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(Self {
+            name: name.to_string(),
+            size,
+            sha256,
+        })
+    }
+}
+
+impl fmt::Display for FileOffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} bytes)", self.name, self.size)
+    }
+}
+
+/// Sending side of a transfer.
+#[derive(Debug, Clone)]
+pub struct Upload {
+    offer: FileOffer,
+    data: Vec<u8>,
+    // 受信側が受け取った先頭からのチャンク数
+    acked: u32,
+    // 次に送るチャンク
+    next: u32,
+}
+
+impl Upload {
+    pub fn new(name: impl Into<String>, data: Vec<u8>) -> Self {
+        Self {
+            offer: FileOffer::for_data(name, &data),
+            data,
+            acked: 0,
+            next: 0,
+        }
+    }
+
+    pub fn offer(&self) -> &FileOffer {
+        &self.offer
+    }
+
+    /// Chunks the receiver has confirmed, counted from the start.
+    pub fn acked(&self) -> u32 {
+        self.acked
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.acked == self.offer.chunk_count()
+    }
+
+    /// Apply a cumulative acknowledgement. Returns whether it made progress.
+    pub fn on_ack(&mut self, next: u32) -> bool {
+        let next = next.min(self.offer.chunk_count());
+        if next <= self.acked {
+            return false;
+        }
+        self.acked = next;
+        self.next = self.next.max(next);
+        true
+    }
+
+    /// Send again from the last acknowledged chunk, e.g. after a timeout.
+    pub fn rewind(&mut self) {
+        self.next = self.acked;
+    }
+
+    /// Chunks that may be sent now without exceeding the window.
+    pub fn due_chunks(&mut self) -> Vec<(u32, &[u8])> {
+        let end = (self.acked + FILE_WINDOW).min(self.offer.chunk_count());
+        let start = self.next;
+        self.next = end.max(start);
+        (start..end)
+            .map(|index| {
+                let from = index as usize * FILE_CHUNK_SIZE;
+                let to = from + self.offer.chunk_len(index);
+                (index, &self.data[from..to])
+            })
+            .collect()
+    }
+}
+
+/// Receiving side of a transfer; chunks are accepted in order only.
+#[derive(Debug, Clone)]
+pub struct Download {
+    offer: FileOffer,
+    data: Vec<u8>,
+    received: u32,
+}
+
+impl Download {
+    pub fn new(offer: FileOffer) -> Self {
+        Self {
+            offer,
+            data: Vec::new(),
+            received: 0,
+        }
+    }
+
+    pub fn offer(&self) -> &FileOffer {
+        &self.offer
+    }
+
+    /// Index of the first missing chunk; the value to acknowledge.
+    pub fn next_index(&self) -> u32 {
+        self.received
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received == self.offer.chunk_count()
+    }
+
+    /// Store chunk `index`. Returns whether it was new and in order.
+    ///
+    /// Duplicates and chunks beyond a gap are ignored so the sender's window
+    /// is resent from [`Download::next_index`].
+    pub fn receive(&mut self, index: u32, chunk: &[u8]) -> Result<bool, TransferError> {
+        let count = self.offer.chunk_count();
+        if index >= count {
+            return Err(TransferError::OutOfRange { index, count });
+        }
+        let expected = self.offer.chunk_len(index);
+        if chunk.len() != expected {
+            return Err(TransferError::BadLength {
+                index,
+                expected,
+                actual: chunk.len(),
+            });
+        }
+        if index != self.received {
+            return Ok(false);
+        }
+        self.data.extend_from_slice(chunk);
+        self.received += 1;
+        Ok(true)
+    }
+
+    /// A chunk received so far, e.g. to serve another receiver.
+    pub fn chunk(&self, index: u32) -> Option<&[u8]> {
+        if index >= self.received {
+            return None;
+        }
+        let from = index as usize * FILE_CHUNK_SIZE;
+        Some(&self.data[from..from + self.offer.chunk_len(index)])
+    }
+
+    /// The complete file, once its checksum matches the offer.
+    pub fn verified(&self) -> Result<&[u8], TransferError> {
+        if !self.is_complete() {
+            return Err(TransferError::Incomplete {
+                received: self.received,
+                count: self.offer.chunk_count(),
+            });
+        }
+        if <[u8; 32]>::from(Sha256::digest(&self.data)) != self.offer.sha256 {
+            return Err(TransferError::ChecksumMismatch);
+        }
+        Ok(&self.data)
+    }
+}
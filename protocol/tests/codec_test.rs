@@ -0,0 +1,128 @@
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use protocol::{
+        MAX_BUFFER_SIZE, MessageKind, MessageProtocol, ProtocolError,
+        codec::{BincodeCodec, Codec, CodecId, JsonCodec, WireCodec},
+    };
+
+    // 任意の種別・ID・文字列からなるメッセージ（どの方式でも上限に収まる長さ）
+    fn message() -> impl Strategy<Value = MessageProtocol> {
+        (
+            (
+                any::<u64>(),
+                any::<u64>(),
+                0u8..=15,
+                any::<u64>(),
+                any::<u64>(),
+            ),
+            ("\\PC{0,32}", "\\PC{0,16}", "\\PC{0,400}"),
+        )
+            .prop_map(
+                |((id, timestamp_ms, kind, target_id, reply_to), (user_name, recipient, body))| {
+                    MessageProtocol {
+                        id,
+                        timestamp_ms,
+                        kind: MessageKind::try_from(kind).unwrap(),
+                        target_id,
+                        reply_to,
+                        user_name,
+                        recipient,
+                        body,
+                    }
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn wire_roundtrip(message in message()) {
+            let frame = WireCodec.encode(&message).unwrap();
+            prop_assert_eq!(WireCodec.decode(&frame).unwrap(), message);
+        }
+
+        #[test]
+        fn bincode_roundtrip(message in message()) {
+            let frame = BincodeCodec.encode(&message).unwrap();
+            prop_assert_eq!(BincodeCodec.decode(&frame).unwrap(), message);
+        }
+
+        #[test]
+        fn json_roundtrip(message in message()) {
+            let frame = JsonCodec.encode(&message).unwrap();
+            prop_assert_eq!(JsonCodec.decode(&frame).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn codec_ids_roundtrip() {
+        for id in CodecId::ALL {
+            assert_eq!(CodecId::parse(id.as_str()), Some(id));
+            assert_eq!(id.codec().id(), id);
+        }
+        assert_eq!(CodecId::parse("xml"), None);
+        assert_eq!(
+            MessageProtocol::codec("alice", CodecId::Json).codec_id(),
+            Some(CodecId::Json)
+        );
+    }
+
+    #[test]
+    fn json_is_readable() {
+        let frame = JsonCodec
+            .encode(&MessageProtocol::new("alice", "hi"))
+            .unwrap();
+        let text = String::from_utf8(frame).unwrap();
+        assert!(text.contains(r#""kind":"Chat""#), "{text}");
+        assert!(text.contains(r#""body":"hi""#), "{text}");
+    }
+
+    #[test]
+    fn codecs_reject_bad_frames() {
+        let mut frame = BincodeCodec
+            .encode(&MessageProtocol::new("alice", "hi"))
+            .unwrap();
+        frame.push(0);
+        assert!(matches!(
+            BincodeCodec.decode(&frame),
+            Err(ProtocolError::Codec {
+                codec: CodecId::Bincode,
+                ..
+            })
+        ));
+
+        // 上限を超える本文はどの方式でも送れない
+        let huge = MessageProtocol::new("alice", "\u{1}".repeat(MAX_BUFFER_SIZE));
+        for id in [CodecId::Bincode, CodecId::Json] {
+            assert!(matches!(
+                id.codec().encode(&huge),
+                Err(ProtocolError::BufferTooLarge(_))
+            ));
+        }
+        assert!(JsonCodec.decode(b"{").is_err());
+    }
+
+    #[test]
+    fn codecs_enforce_field_limits() {
+        let long_name = MessageProtocol::new("a".repeat(300), "hi");
+        for id in CodecId::ALL {
+            assert_eq!(
+                id.codec().encode(&long_name),
+                Err(ProtocolError::UsernameTooLong(300)),
+                "{id}"
+            );
+        }
+
+        // 手で組み立てた JSON も解析の時点で断る
+        let frame = JsonCodec
+            .encode(&MessageProtocol::new("alice", "hi"))
+            .unwrap();
+        let frame = String::from_utf8(frame)
+            .unwrap()
+            .replace("alice", &"a".repeat(300));
+        assert_eq!(
+            JsonCodec.decode(frame.as_bytes()),
+            Err(ProtocolError::UsernameTooLong(300))
+        );
+    }
+}
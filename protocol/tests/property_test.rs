@@ -0,0 +1,90 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use proptest::prelude::*;
+    use protocol::{MAX_BUFFER_SIZE, MessageProtocol, ProtocolError};
+
+    // fuzz/ で見つけた入力。回帰を防ぐため通常のテストでも読み直す
+    const CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/corpus/deserialize");
+
+    // 読めたフレームは書き戻しても同じメッセージに戻る
+    fn check_frame(frame: &[u8]) -> Result<(), TestCaseError> {
+        if let Ok(message) = MessageProtocol::deserialize(frame) {
+            let _ = message.to_string();
+            if let Ok(again) = message.serialize() {
+                prop_assert_eq!(MessageProtocol::deserialize(&again).unwrap(), message);
+            }
+        }
+        Ok(())
+    }
+
+    proptest! {
+        // テスト: 255 バイト以下の任意の名前と収まる長さの本文は往復できる
+        #[test]
+        fn roundtrip_any_username_and_body(
+            user_name in "\\PC{0,63}",
+            recipient in "\\PC{0,63}",
+            body in "\\PC{0,500}",
+        ) {
+            let original = MessageProtocol { user_name, recipient, ..MessageProtocol::new("", body) };
+            let frame = original.serialize().unwrap();
+            prop_assert_eq!(MessageProtocol::deserialize(&frame).unwrap(), original);
+        }
+
+        // テスト: 長い本文は往復するか、上限超過として断られるかのどちらか
+        #[test]
+        fn long_bodies_roundtrip_or_are_rejected(body in "(\\PC|[a-c ]{8}){0,1500}") {
+            let original = MessageProtocol::new("u", body);
+            match original.serialize() {
+                Ok(frame) => {
+                    prop_assert!(frame.len() <= MAX_BUFFER_SIZE);
+                    prop_assert_eq!(MessageProtocol::deserialize(&frame).unwrap(), original);
+                }
+                Err(e) => prop_assert!(matches!(e, ProtocolError::BufferTooLarge(_)), "{e}"),
+            }
+        }
+
+        // テスト: 256 バイト以上の名前は送れない
+        #[test]
+        fn long_usernames_are_rejected(user_name in "[a-z]{256,400}") {
+            let len = user_name.len();
+            let err = MessageProtocol::new(user_name, "").serialize().unwrap_err();
+            prop_assert!(matches!(err, ProtocolError::UsernameTooLong(l) if l == len));
+        }
+
+        // テスト: 任意のバイト列で panic しない
+        #[test]
+        fn arbitrary_bytes_never_panic(frame in prop::collection::vec(any::<u8>(), 0..MAX_BUFFER_SIZE + 64)) {
+            check_frame(&frame)?;
+        }
+
+        // テスト: 正しいフレームの一部を壊したり切り詰めたりしても panic しない
+        #[test]
+        fn damaged_frames_never_panic(
+            body in "\\PC{0,300}",
+            flips in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..8),
+            keep in any::<prop::sample::Index>(),
+        ) {
+            let mut frame = MessageProtocol::new("alice", body).serialize().unwrap();
+            for (at, mask) in flips {
+                let at = at.index(frame.len());
+                frame[at] ^= mask;
+            }
+            check_frame(&frame)?;
+            check_frame(&frame[..keep.index(frame.len() + 1)])?;
+        }
+    }
+
+    // テスト: 取り込み済みのコーパスはどれも panic せずに処理できる
+    #[test]
+    fn fuzz_corpus_is_handled() {
+        let mut count = 0;
+        for entry in fs::read_dir(CORPUS).unwrap() {
+            let frame = fs::read(entry.unwrap().path()).unwrap();
+            check_frame(&frame).unwrap();
+            count += 1;
+        }
+        assert!(count > 0, "empty corpus at {CORPUS}");
+    }
+}
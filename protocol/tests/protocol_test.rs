@@ -1,12 +1,17 @@
 #[cfg(test)]
 mod tests {
-    use protocol::{MAX_BUFFER_SIZE, MessageProtocol, ProtocolError};
+    use protocol::{
+        COMPRESSED_FLAG, COMPRESSION_THRESHOLD, DeliveryStatus, HEADER_SIZE, MAX_BUFFER_SIZE,
+        MAX_DECOMPRESSED_SIZE, MessageKind, MessageProtocol, ProtocolError, ReceiptStatus,
+        transfer::{Download, FILE_CHUNK_SIZE, FILE_WINDOW, FileOffer, TransferError, Upload},
+    };
 
     #[test]
     fn roundtrip_ok() {
         let original = MessageProtocol {
             user_name: "bob".into(),
             body: "こんにちは、世界！🌏".into(),
+            ..Default::default()
         };
         let frame = original.serialize().expect("serialise");
         let decoded = MessageProtocol::deserialize(&frame).expect("deserialise");
@@ -19,16 +24,30 @@ mod tests {
         let msg = MessageProtocol {
             user_name: long_name,
             body: String::new(),
+            ..Default::default()
         };
         let err = msg.serialize().unwrap_err();
         assert!(matches!(err, ProtocolError::UsernameTooLong(256)));
     }
 
+    // 圧縮が効かない本文（線形合同法による擬似乱数の英数字）
+    fn incompressible(len: usize) -> String {
+        const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+        let mut seed: u32 = 12345;
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                ALPHABET[(seed >> 16) as usize % ALPHABET.len()] as char
+            })
+            .collect()
+    }
+
     #[test]
     fn buffer_too_large_error() {
         let msg = MessageProtocol {
             user_name: "u".into(),
-            body: "a".repeat(MAX_BUFFER_SIZE), // 1(name_len)+1(username)+4096(body) => 4098
+            body: incompressible(MAX_BUFFER_SIZE), // 33(header)+1(name_len)+1(username)+1(recipient_len)+4096(body) => 4132
+            ..Default::default()
         };
         let err = msg.serialize().unwrap_err();
         assert!(matches!(err, ProtocolError::BufferTooLarge(_)));
@@ -37,8 +56,304 @@ mod tests {
     #[test]
     fn truncated_buffer_error() {
         // username length byte says 5 but only 3 bytes of data present
-        let frame = vec![5u8, b'a', b'b', b'c'];
+        let mut frame = vec![0u8; HEADER_SIZE];
+        frame.extend_from_slice(&[5u8, b'a', b'b', b'c']);
         let err = MessageProtocol::deserialize(&frame).unwrap_err();
         assert!(matches!(err, ProtocolError::Truncated { .. }));
     }
+
+    #[test]
+    fn roundtrip_keeps_server_stamp() {
+        let original = MessageProtocol {
+            id: 42,
+            timestamp_ms: 1_700_000_000_123,
+            ..MessageProtocol::new("alice", "hi")
+        };
+        let frame = original.serialize().expect("serialise");
+        assert_eq!(&frame[..8], &42u64.to_be_bytes());
+        let decoded = MessageProtocol::deserialize(&frame).expect("deserialise");
+        assert_eq!(decoded, original);
+    }
+
+    #[test]
+    fn header_only_frame_is_truncated() {
+        let err = MessageProtocol::deserialize(&[0u8; HEADER_SIZE]).unwrap_err();
+        assert_eq!(
+            err,
+            ProtocolError::Truncated {
+                expected: HEADER_SIZE + 1,
+                actual: HEADER_SIZE
+            }
+        );
+    }
+
+    #[test]
+    fn roundtrip_edit_and_delete() {
+        for original in [
+            MessageProtocol::edit("alice", 7, "fixed typo"),
+            MessageProtocol::delete("alice", 7),
+        ] {
+            let frame = original.serialize().expect("serialise");
+            assert_eq!(frame[16], original.kind as u8);
+            let decoded = MessageProtocol::deserialize(&frame).expect("deserialise");
+            assert_eq!(decoded, original);
+            assert_eq!(decoded.target_id, 7);
+        }
+    }
+
+    #[test]
+    fn unknown_kind_error() {
+        let mut frame = MessageProtocol::new("alice", "hi").serialize().unwrap();
+        frame[16] = 0xEE;
+        let err = MessageProtocol::deserialize(&frame).unwrap_err();
+        assert_eq!(err, ProtocolError::UnknownKind(0xEE));
+        assert_eq!(MessageKind::try_from(2), Ok(MessageKind::Delete));
+    }
+
+    #[test]
+    fn roundtrip_reply_and_reactions() {
+        let reply = MessageProtocol::reply("bob", 3, "agreed");
+        let frame = reply.serialize().expect("serialise");
+        assert_eq!(&frame[25..33], &3u64.to_be_bytes());
+        assert_eq!(MessageProtocol::deserialize(&frame).unwrap(), reply);
+
+        let react = MessageProtocol::react("bob", 3, "👍");
+        let decoded = MessageProtocol::deserialize(&react.serialize().unwrap()).unwrap();
+        assert_eq!(decoded, react);
+        assert_eq!(decoded.kind, MessageKind::React);
+    }
+
+    #[test]
+    fn reaction_tally_roundtrip() {
+        let tally = vec![("👍".to_string(), 2), ("🎉".to_string(), 1)];
+        let summary = MessageProtocol::reactions("alice", 3, &tally);
+        let decoded = MessageProtocol::deserialize(&summary.serialize().unwrap()).unwrap();
+        assert_eq!(decoded.kind, MessageKind::Reactions);
+        assert_eq!(decoded.tally(), tally);
+        assert!(
+            MessageProtocol::reactions("alice", 3, &[])
+                .tally()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn roundtrip_typing() {
+        let typing = MessageProtocol::typing("carol");
+        let frame = typing.serialize().expect("serialise");
+        assert_eq!(frame[16], MessageKind::Typing as u8);
+        assert_eq!(MessageProtocol::deserialize(&frame).unwrap(), typing);
+        assert_eq!(typing.to_string(), "carol is typing…");
+    }
+
+    #[test]
+    fn roundtrip_receipts() {
+        for control in [
+            MessageProtocol::ack("bob", 9),
+            MessageProtocol::read_up_to("bob", 9),
+        ] {
+            let decoded = MessageProtocol::deserialize(&control.serialize().unwrap()).unwrap();
+            assert_eq!(decoded, control);
+        }
+
+        let states = vec![
+            ("alice".to_string(), ReceiptStatus::Read),
+            ("bob".to_string(), ReceiptStatus::Delivered),
+            ("carol".to_string(), ReceiptStatus::Pending),
+        ];
+        let report = MessageProtocol::receipts("system", 9, &states);
+        let decoded = MessageProtocol::deserialize(&report.serialize().unwrap()).unwrap();
+        assert_eq!(decoded.kind, MessageKind::Receipts);
+        assert_eq!(decoded.receipt_states(), states);
+        assert!(
+            MessageProtocol::receipts("bob", 9, &[])
+                .receipt_states()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn roundtrip_direct_and_status() {
+        let direct = MessageProtocol::direct("alice", "bob", "psst");
+        let frame = direct.serialize().expect("serialise");
+        // 送信者名の直後に宛先の長さと宛先が続く
+        assert_eq!(frame[HEADER_SIZE + 1 + 5], 3);
+        assert_eq!(&frame[HEADER_SIZE + 1 + 5 + 1..][..3], b"bob");
+        assert_eq!(MessageProtocol::deserialize(&frame).unwrap(), direct);
+
+        let status = MessageProtocol::direct_status("system", 4, "bob", DeliveryStatus::Queued);
+        let decoded = MessageProtocol::deserialize(&status.serialize().unwrap()).unwrap();
+        assert_eq!(decoded.kind, MessageKind::DirectStatus);
+        assert_eq!(decoded.delivery_status(), Some(DeliveryStatus::Queued));
+        assert_eq!(decoded.to_string(), "message #4 to bob: queued");
+    }
+
+    #[test]
+    fn missing_recipient_length_is_truncated() {
+        let mut frame = vec![0u8; HEADER_SIZE];
+        frame.extend_from_slice(&[3u8, b'b', b'o', b'b']);
+        let err = MessageProtocol::deserialize(&frame).unwrap_err();
+        assert_eq!(
+            err,
+            ProtocolError::Truncated {
+                expected: frame.len() + 1,
+                actual: frame.len()
+            }
+        );
+
+        let long_recipient = MessageProtocol::direct("alice", "x".repeat(256), "hi");
+        assert_eq!(
+            long_recipient.serialize().unwrap_err(),
+            ProtocolError::UsernameTooLong(256)
+        );
+    }
+
+    #[test]
+    fn roundtrip_file_frames() {
+        let offer = FileOffer::for_data("screen shot.png", b"hello");
+        let frame = MessageProtocol::file_offer("alice", &offer);
+        let decoded = MessageProtocol::deserialize(&frame.serialize().unwrap()).unwrap();
+        assert_eq!(decoded.kind, MessageKind::FileOffer);
+        // 名前に空白があっても復元できる
+        assert_eq!(decoded.offer(), Some(offer));
+        assert_eq!(
+            decoded.to_string(),
+            "<alice> offers screen shot.png (5 bytes)"
+        );
+
+        // 最大サイズのチャンクと最長の名前でも受信バッファに収まる
+        let data = vec![0xffu8; FILE_CHUNK_SIZE];
+        let chunk = MessageProtocol::file_chunk("x".repeat(255), 7, u32::MAX, &data);
+        let frame = chunk.serialize().unwrap();
+        assert!(frame.len() <= 1024, "{} bytes", frame.len());
+        let decoded = MessageProtocol::deserialize(&frame).unwrap();
+        assert_eq!(decoded.chunk(), Some((u32::MAX, data)));
+
+        let ack = MessageProtocol::file_ack("bob", 7, Some(3));
+        assert_eq!(ack.ack_index(), Some(3));
+        assert_eq!(MessageProtocol::file_ack("bob", 7, None).ack_index(), None);
+        assert_eq!(FileOffer::parse("12 nothex name"), None);
+    }
+
+    #[test]
+    fn upload_resends_window_from_last_ack() {
+        let data: Vec<u8> = (0..FILE_CHUNK_SIZE * 10 + 1).map(|i| i as u8).collect();
+        let mut upload = Upload::new("f", data.clone());
+        assert_eq!(upload.offer().chunk_count(), 11);
+
+        // ❶ 確認応答がなければウィンドウ分までしか送らない
+        let first: Vec<u32> = upload.due_chunks().iter().map(|(i, _)| *i).collect();
+        assert_eq!(first, (0..FILE_WINDOW).collect::<Vec<_>>());
+        assert!(upload.due_chunks().is_empty());
+
+        // ❷ 累積の確認応答でウィンドウが進む
+        assert!(upload.on_ack(2));
+        assert!(!upload.on_ack(1), "古い確認応答は無視する");
+        let more: Vec<u32> = upload.due_chunks().iter().map(|(i, _)| *i).collect();
+        assert_eq!(more, vec![8, 9]);
+
+        // ❸ タイムアウト後は最後に確認された位置から送り直す
+        upload.rewind();
+        let resent = upload.due_chunks();
+        assert_eq!(resent[0].0, 2);
+        assert_eq!(resent.len(), FILE_WINDOW as usize);
+
+        let mut download = Download::new(upload.offer().clone());
+        let mut upload = Upload::new("f", data.clone());
+        while !upload.is_complete() {
+            for (index, chunk) in upload.due_chunks() {
+                download.receive(index, chunk).unwrap();
+            }
+            upload.on_ack(download.next_index());
+        }
+        assert_eq!(download.verified().unwrap(), data.as_slice());
+    }
+
+    #[test]
+    fn download_rejects_gaps_and_bad_checksums() {
+        let data = vec![1u8; FILE_CHUNK_SIZE + 10];
+        let offer = FileOffer::for_data("f", &data);
+        let mut download = Download::new(offer.clone());
+
+        // 欠けの後ろのチャンクは受け取らず、再開位置は変わらない
+        assert_eq!(download.receive(1, &data[FILE_CHUNK_SIZE..]), Ok(false));
+        assert_eq!(download.next_index(), 0);
+        assert_eq!(
+            download.receive(0, &data[..10]),
+            Err(TransferError::BadLength {
+                index: 0,
+                expected: FILE_CHUNK_SIZE,
+                actual: 10
+            })
+        );
+        assert_eq!(
+            download.receive(2, &[]),
+            Err(TransferError::OutOfRange { index: 2, count: 2 })
+        );
+        assert_eq!(
+            download.verified(),
+            Err(TransferError::Incomplete {
+                received: 0,
+                count: 2
+            })
+        );
+
+        // 内容が違えばチェックサムで検出する
+        let mut tampered = Download::new(offer);
+        tampered.receive(0, &data[..FILE_CHUNK_SIZE]).unwrap();
+        tampered.receive(1, &[2u8; 10]).unwrap();
+        assert_eq!(tampered.verified(), Err(TransferError::ChecksumMismatch));
+    }
+
+    #[test]
+    fn long_bodies_are_compressed() {
+        // 閾値未満はそのまま送る
+        let short = MessageProtocol::new("alice", "a".repeat(COMPRESSION_THRESHOLD - 1));
+        let frame = short.serialize().unwrap();
+        assert_eq!(frame[16] & COMPRESSED_FLAG, 0);
+
+        // 繰り返しの多い貼り付けは小さくなり、そのまま復元できる
+        let paste = MessageProtocol::edit("alice", 9, "fn main() {}\n".repeat(500));
+        let frame = paste.serialize().unwrap();
+        assert_eq!(frame[16], MessageKind::Edit as u8 | COMPRESSED_FLAG);
+        assert!(frame.len() < MAX_BUFFER_SIZE, "{} bytes", frame.len());
+        assert_eq!(MessageProtocol::deserialize(&frame).unwrap(), paste);
+
+        // 縮まない本文は圧縮しない
+        let noise = MessageProtocol::new("alice", incompressible(COMPRESSION_THRESHOLD * 2));
+        let frame = noise.serialize().unwrap();
+        assert_eq!(frame[16] & COMPRESSED_FLAG, 0);
+        assert_eq!(MessageProtocol::deserialize(&frame).unwrap(), noise);
+    }
+
+    #[test]
+    fn decompression_is_bounded() {
+        let mut header = MessageProtocol::new("mallory", "").serialize().unwrap();
+        header[16] |= COMPRESSED_FLAG;
+
+        // 展開後の長さが上限を超えるものは展開しない
+        let mut bomb = header.clone();
+        bomb.extend_from_slice(&(u32::MAX).to_le_bytes());
+        bomb.extend_from_slice(&[0x1f, 0x00]);
+        assert_eq!(
+            MessageProtocol::deserialize(&bomb).unwrap_err(),
+            ProtocolError::DecompressedTooLarge(u32::MAX as usize)
+        );
+
+        // 上限ちょうどを名乗っても、実際のデータが足りなければ壊れている
+        let mut lying = header.clone();
+        lying.extend_from_slice(&(MAX_DECOMPRESSED_SIZE as u32).to_le_bytes());
+        lying.extend_from_slice(&[0x1f, 0x00]);
+        assert_eq!(
+            MessageProtocol::deserialize(&lying).unwrap_err(),
+            ProtocolError::CorruptBody
+        );
+
+        let mut short = header;
+        short.extend_from_slice(&[1, 0]);
+        assert_eq!(
+            MessageProtocol::deserialize(&short).unwrap_err(),
+            ProtocolError::CorruptBody
+        );
+    }
 }
@@ -0,0 +1,68 @@
+//! Expiry cost of the deadline heap versus a full `DashMap` scan.
+//!
+//! The table is filled with idle-but-alive clients, which is the common case
+//! for a large room: every tick has to decide that nobody expired.
+
+use std::{hint::black_box, time::Duration};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use server::client_manager::{ClientInfo, ClientManager, Role};
+use tokio::time::Instant;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+fn populated_manager(clients: usize) -> ClientManager {
+    let manager = ClientManager::new(TIMEOUT);
+    let now = Instant::now();
+    for i in 0..clients {
+        manager.upsert_client(ClientInfo {
+            user_name: format!("user{i}"),
+            socket_addr: "127.0.0.1:9050".parse().unwrap(),
+            last_message_time: now,
+            role: Role::Member,
+            ingress: None,
+        });
+    }
+    manager
+}
+
+fn expiry_tick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("expiry_tick");
+    for clients in [1_000, 10_000, 50_000] {
+        let manager = populated_manager(clients);
+
+        group.bench_with_input(BenchmarkId::new("heap", clients), &manager, |b, m| {
+            b.iter(|| black_box(m.cleanup_inactive_clients()))
+        });
+
+        // 以前の実装と同じ全件走査
+        group.bench_with_input(BenchmarkId::new("scan", clients), &manager, |b, m| {
+            b.iter(|| {
+                let now = Instant::now();
+                m.clients_table
+                    .retain(|_, client| now.duration_since(client.last_message_time) < TIMEOUT);
+            })
+        });
+    }
+    group.finish();
+}
+
+fn activity_update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("activity_update");
+    for clients in [1_000, 10_000, 50_000] {
+        let manager = populated_manager(clients);
+        let mut i = 0;
+        group.bench_with_input(BenchmarkId::from_parameter(clients), &manager, |b, m| {
+            b.iter(|| {
+                i = (i + 1) % clients;
+                m.update_client_activity(&format!("user{i}")).unwrap();
+                // 読み捨てエントリの圧縮も含めて計測する
+                m.cleanup_inactive_clients();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, expiry_tick, activity_update);
+criterion_main!(benches);
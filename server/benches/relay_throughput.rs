@@ -0,0 +1,59 @@
+//! Relay fan-out throughput at 1k recipients.
+//!
+//! Compares one `send_to` per recipient with the `sendmmsg` path. Run with
+//! `cargo bench -p server --features batched-io` to include the batched case.
+//! `relay_1k_clients` reports relayed messages per second and
+//! `relay_1k_datagrams` the datagrams sent for them.
+
+use std::{hint::black_box, net::SocketAddr};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use server::fanout;
+use tokio::{net::UdpSocket, runtime::Runtime};
+
+const RECIPIENTS: usize = 1_000;
+// 受信側ソケット数（ファイルディスクリプタ上限を避けるため宛先を使い回す）
+const SINKS: usize = 100;
+const PAYLOAD: &[u8] = b"\x05alicehello from the relay benchmark";
+
+fn relay_throughput(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let (sock, _sinks, recipients) = rt.block_on(async {
+        let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut sinks = Vec::with_capacity(SINKS);
+        for _ in 0..SINKS {
+            sinks.push(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        }
+        let recipients: Vec<SocketAddr> = (0..RECIPIENTS)
+            .map(|i| sinks[i % SINKS].local_addr().unwrap())
+            .collect();
+        (sock, sinks, recipients)
+    });
+
+    // 1 メッセージ = 1k 宛先への送信
+    for (name, per_relay) in [
+        ("relay_1k_clients", 1),
+        ("relay_1k_datagrams", RECIPIENTS as u64),
+    ] {
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Elements(per_relay));
+
+        group.bench_function("send_to", |b| {
+            b.to_async(&rt).iter(|| async {
+                black_box(fanout::send_to_all_plain(&sock, PAYLOAD, &recipients).await)
+            })
+        });
+
+        #[cfg(all(target_os = "linux", feature = "batched-io"))]
+        group.bench_function("sendmmsg", |b| {
+            b.to_async(&rt).iter(|| async {
+                black_box(fanout::send_to_all_batched(&sock, PAYLOAD, &recipients).await)
+            })
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, relay_throughput);
+criterion_main!(benches);
@@ -0,0 +1,89 @@
+//! Socket binding and address handling for mixed IPv4 / IPv6 peers.
+//!
+//! Client addresses are stored in canonical form: an IPv4 peer reaching a
+//! dual-stack socket arrives as `::ffff:a.b.c.d` and is recorded as
+//! `a.b.c.d`. When relaying, addresses are converted back to whatever the
+//! sending socket's family requires.
+//!
+//! A server bound to several addresses answers each client from the socket
+//! it joined through ([`ClientInfo::ingress`](crate::client_manager::ClientInfo)),
+//! looked up in [`Sockets`], so NAT and connected-UDP clients see one source
+//! address and IPv4 clients are never sent to from an IPv6-only socket.
+
+use std::{
+    io,
+    net::{SocketAddr, SocketAddrV6},
+    sync::Arc,
+};
+
+use dashmap::DashMap;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+
+/// Bind a UDP socket on `addr`.
+///
+/// Binding the IPv6 unspecified address (`[::]`) yields a dual-stack socket
+/// that also accepts IPv4 peers, regardless of the platform default.
+pub fn bind_udp(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Strip the IPv4-mapped IPv6 form so each peer has a single representation.
+pub fn canonical_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(v4) => SocketAddr::new(v4.into(), v6.port()),
+            None => addr,
+        },
+        SocketAddr::V4(_) => addr,
+    }
+}
+
+/// Express `to` in the address family of a socket bound to `local`.
+///
+/// IPv4 peers are mapped into IPv6 when sending from a dual-stack socket.
+pub fn addr_for_socket(local: SocketAddr, to: SocketAddr) -> SocketAddr {
+    match (local, to) {
+        (SocketAddr::V6(_), SocketAddr::V4(v4)) => {
+            SocketAddr::V6(SocketAddrV6::new(v4.ip().to_ipv6_mapped(), v4.port(), 0, 0))
+        }
+        _ => to,
+    }
+}
+
+/// Bound server sockets by local address.
+#[derive(Default)]
+pub struct Sockets {
+    by_local: DashMap<SocketAddr, Arc<UdpSocket>>,
+}
+
+impl Sockets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make `sock` available for sending to the clients that joined through it.
+    pub fn register(&self, sock: Arc<UdpSocket>) -> io::Result<()> {
+        self.by_local.insert(sock.local_addr()?, sock);
+        Ok(())
+    }
+
+    /// The registered socket bound to `local`, if any.
+    pub fn get(&self, local: SocketAddr) -> Option<Arc<UdpSocket>> {
+        self.by_local.get(&local).map(|sock| Arc::clone(&sock))
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_local.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_local.is_empty()
+    }
+}
@@ -0,0 +1,283 @@
+//! Admin console on a local Unix domain socket.
+//!
+//! The protocol is line based: each request is one command line, and each
+//! reply is zero or more output lines followed by `OK` or `ERR <reason>`.
+//!
+//! * `list` : connected clients with address, role and idle time
+//! * `kick <user>` : drop a client (it may rejoin by sending again)
+//! * `ban <user>` / `unban <user|ip>` : drop and ignore / stop ignoring a user (persisted)
+//! * `ban-ip <ip>` : ignore every datagram from an address (persisted)
+//! * `notice <text>` : broadcast a message from the system user
+//! * `timeout <seconds>` : change the inactivity timeout
+//! * `receipts <id>` : delivery / read state of a message per recipient
+//! * `stats` : server counters
+
+use std::{io, net::IpAddr, path::Path, sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UdpSocket, UnixListener, UnixStream},
+    time::Instant,
+};
+
+use crate::{moderation::kick, relay, state::ServerState};
+
+/// Default location of the admin socket.
+pub const ADMIN_SOCKET_PATH: &str = "/tmp/online-chat-admin.sock";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminCommand {
+    List,
+    Kick(String),
+    Ban(String),
+    BanIp(IpAddr),
+    Unban(String),
+    Notice(String),
+    Timeout(Duration),
+    Receipts(u64),
+    Stats,
+}
+
+impl AdminCommand {
+    /// Parse one request line.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        let required = |what: &str| {
+            if arg.is_empty() {
+                Err(format!("usage: {name} <{what}>"))
+            } else {
+                Ok(arg.to_string())
+            }
+        };
+        match name {
+            "list" => Ok(Self::List),
+            "kick" => required("user").map(Self::Kick),
+            "ban" => required("user").map(Self::Ban),
+            "ban-ip" => {
+                let ip = required("ip")?;
+                ip.parse()
+                    .map(Self::BanIp)
+                    .map_err(|_| format!("invalid ip: {ip}"))
+            }
+            "unban" => required("user|ip").map(Self::Unban),
+            "notice" => required("text").map(Self::Notice),
+            "timeout" => {
+                let secs = required("seconds")?;
+                match secs.parse::<u64>() {
+                    Ok(secs) if secs > 0 => Ok(Self::Timeout(Duration::from_secs(secs))),
+                    _ => Err(format!("invalid timeout: {secs}")),
+                }
+            }
+            "receipts" => {
+                let id = required("id")?;
+                id.trim_start_matches('#')
+                    .parse()
+                    .map(Self::Receipts)
+                    .map_err(|_| format!("invalid message id: {id}"))
+            }
+            "stats" => Ok(Self::Stats),
+            "" => Err("empty command".to_string()),
+            other => Err(format!("unknown command: {other}")),
+        }
+    }
+
+    /// Run the command and return its output lines.
+    pub async fn execute(
+        self,
+        state: &ServerState,
+        sock: &UdpSocket,
+    ) -> Result<Vec<String>, String> {
+        let manager = &state.client_manager;
+        match self {
+            Self::List => {
+                let now = Instant::now();
+                let mut lines: Vec<String> = manager
+                    .clients_table
+                    .iter()
+                    .map(|client| {
+                        format!(
+                            "{} {} role={:?} idle={}s",
+                            client.user_name,
+                            client.socket_addr,
+                            client.role,
+                            now.duration_since(client.last_message_time).as_secs()
+                        )
+                    })
+                    .collect();
+                lines.sort();
+                Ok(lines)
+            }
+            Self::Kick(user_name) => {
+                kick(state, sock, &user_name, "you were kicked by an operator")
+                    .await
+                    .ok_or_else(|| format!("{user_name} is not connected"))?;
+                Ok(vec![])
+            }
+            Self::Ban(user_name) => {
+                state
+                    .moderation
+                    .ban_user(&user_name)
+                    .map_err(|e| e.to_string())?;
+                // 接続中でなくても BAN は有効
+                let _ = kick(state, sock, &user_name, "you were banned by an operator").await;
+                Ok(vec![])
+            }
+            Self::BanIp(ip) => {
+                state.moderation.ban_ip(ip).map_err(|e| e.to_string())?;
+                let affected: Vec<String> = manager
+                    .clients_table
+                    .iter()
+                    .filter(|client| client.socket_addr.ip() == ip)
+                    .map(|client| client.user_name.clone())
+                    .collect();
+                for user_name in affected {
+                    kick(
+                        state,
+                        sock,
+                        &user_name,
+                        "your address was banned by an operator",
+                    )
+                    .await;
+                }
+                Ok(vec![])
+            }
+            Self::Unban(target) => match state.moderation.unban(&target) {
+                Ok(true) => Ok(vec![]),
+                Ok(false) => Err(format!("{target} is not banned")),
+                Err(e) => Err(e.to_string()),
+            },
+            Self::Notice(text) => {
+                let frame = state
+                    .system_notice(text)
+                    .serialize()
+                    .map_err(|e| e.to_string())?;
+                relay(sock, state, &frame)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Ok(vec![])
+            }
+            Self::Timeout(timeout) => {
+                manager.set_timeout_duration(timeout);
+                Ok(vec![])
+            }
+            Self::Receipts(id) => {
+                let receipts = state
+                    .history
+                    .receipts(id)
+                    .ok_or_else(|| format!("message #{id} not found"))?;
+                Ok(receipts
+                    .into_iter()
+                    .map(|(user_name, status)| format!("{user_name} {status}"))
+                    .collect())
+            }
+            Self::Stats => Ok(vec![
+                format!("clients={}", manager.active_client_count()),
+                format!("messages={}", state.messages_stamped()),
+                format!("history={}", state.history.len()),
+                format!("typing={}", state.typing.active().len()),
+                format!("queued_dms={}", state.direct_queue.len()),
+                format!("transfers={}", state.transfers.len()),
+                format!("banned={}", state.moderation.ban_count()),
+                format!("timeout_secs={}", manager.timeout_duration().as_secs()),
+                format!("uptime_secs={}", state.started_at.elapsed().as_secs()),
+            ]),
+        }
+    }
+}
+
+/// Serve the admin console on `path` until accepting fails.
+///
+/// A stale socket file left by a previous run is removed first; if another
+/// server still answers on `path`, this fails with `AddrInUse` instead of
+/// taking its console over. Notices and kick messages are sent through `sock`.
+pub async fn run_admin(
+    path: impl AsRef<Path>,
+    state: Arc<ServerState>,
+    sock: Arc<UdpSocket>,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    if path.exists() {
+        // 接続を拒否されるものだけが、前回の実行が残したソケット
+        match UnixStream::connect(path).await {
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("another server is listening on {}", path.display()),
+                ));
+            }
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+            Err(e) => return Err(e),
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    println!("Admin console listening on {}", path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let state = Arc::clone(&state);
+        let sock = Arc::clone(&sock);
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &state, &sock).await {
+                eprintln!("admin connection failed: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    stream: UnixStream,
+    state: &ServerState,
+    sock: &UdpSocket,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let result = match AdminCommand::parse(&line) {
+            Ok(command) => command.execute(state, sock).await,
+            Err(e) => Err(e),
+        };
+        let mut reply = String::new();
+        match result {
+            Ok(output) => {
+                for line in output {
+                    reply.push_str(&line);
+                    reply.push('\n');
+                }
+                reply.push_str("OK\n");
+            }
+            Err(e) => reply.push_str(&format!("ERR {e}\n")),
+        }
+        writer.write_all(reply.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Send one command to the admin console at `path` and return its output.
+///
+/// An `ERR` reply is returned as an error of kind [`io::ErrorKind::Other`].
+pub async fn send_command(path: impl AsRef<Path>, command: &str) -> io::Result<String> {
+    let stream = UnixStream::connect(path).await?;
+    let (reader, mut writer) = stream.into_split();
+    writer
+        .write_all(format!("{}\n", command.trim()).as_bytes())
+        .await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    let mut output = String::new();
+    while let Some(line) = lines.next_line().await? {
+        if line == "OK" {
+            return Ok(output);
+        }
+        if let Some(reason) = line.strip_prefix("ERR ") {
+            return Err(io::Error::other(reason.to_string()));
+        }
+        output.push_str(&line);
+        output.push('\n');
+    }
+    Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "admin console closed the connection",
+    ))
+}
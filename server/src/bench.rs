@@ -0,0 +1,337 @@
+//! Relay load generator behind the `chat-bench` binary.
+//!
+//! Simulated clients join with a direct message to themselves, so joining
+//! does not fan out to the whole room. The first [`BenchConfig::senders`] of
+//! them then send chat frames at a fixed rate; every joined client receives
+//! each relay and records how long it took since the send. Deliveries still
+//! missing once [`BenchConfig::drain`] has passed count as lost.
+
+use std::{
+    fmt, io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use protocol::{HEADER_SIZE, MessageKind, MessageProtocol};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::UdpSocket,
+    sync::Semaphore,
+    task::JoinSet,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+use crate::{BUFFER_SIZE, SERVER_PORT};
+
+/// Prefix of every benchmark body, followed by the send time in microseconds.
+pub const BENCH_TAG: &str = "bench";
+/// Joins in flight at once.
+pub const JOIN_CONCURRENCY: usize = 64;
+const JOIN_ATTEMPTS: usize = 3;
+const JOIN_TIMEOUT: Duration = Duration::from_secs(1);
+// 名前は "bench-<番号>"（最大でも 255 バイトに収まる）
+const NAME_PREFIX: &str = "bench-";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchConfig {
+    pub server: SocketAddr,
+    pub clients: usize,
+    pub senders: usize,
+    /// Messages per second from each sender.
+    pub rate: f64,
+    /// Body size in bytes; never less than the tag and timestamp.
+    pub size: usize,
+    pub duration: Duration,
+    /// How long receivers keep listening after the last send.
+    pub drain: Duration,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            server: SocketAddr::new(Ipv4Addr::LOCALHOST.into(), SERVER_PORT),
+            clients: 100,
+            senders: 10,
+            rate: 10.0,
+            size: 64,
+            duration: Duration::from_secs(10),
+            drain: Duration::from_secs(2),
+        }
+    }
+}
+
+impl BenchConfig {
+    /// Largest body whose frame still fits the server's receive buffer.
+    pub fn max_size(&self) -> usize {
+        let name = NAME_PREFIX.len() + (self.clients.max(1) - 1).to_string().len();
+        BUFFER_SIZE.saturating_sub(HEADER_SIZE + 2 + name)
+    }
+
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |reason: String| Err(io::Error::new(io::ErrorKind::InvalidInput, reason));
+        if self.clients == 0 {
+            return invalid("at least one client is needed".into());
+        }
+        if self.senders == 0 || self.senders > self.clients {
+            return invalid(format!("senders must be between 1 and {}", self.clients));
+        }
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            return invalid(format!("rate must be positive, not {}", self.rate));
+        }
+        if self.size > self.max_size() {
+            return invalid(format!("size must be at most {} bytes", self.max_size()));
+        }
+        Ok(())
+    }
+}
+
+/// Relay latency percentiles in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Latency {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub p999: f64,
+    pub max: f64,
+}
+
+impl Latency {
+    /// Summarise latencies given in microseconds.
+    pub fn from_micros(mut samples: Vec<u64>) -> Self {
+        samples.sort_unstable();
+        let ms = |p| percentile(&samples, p) as f64 / 1000.0;
+        Self {
+            p50: ms(50.0),
+            p90: ms(90.0),
+            p99: ms(99.0),
+            p999: ms(99.9),
+            max: ms(100.0),
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted `samples` (0 when empty).
+pub fn percentile(samples: &[u64], p: f64) -> u64 {
+    if samples.is_empty() {
+        return 0;
+    }
+    let rank = (p / 100.0 * samples.len() as f64).ceil() as usize;
+    samples[rank.clamp(1, samples.len()) - 1]
+}
+
+/// Results of one run, serialised as JSON for comparison between runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub clients: usize,
+    pub joined: usize,
+    pub senders: usize,
+    pub rate: f64,
+    pub size: usize,
+    pub duration_ms: u64,
+    pub sent: u64,
+    /// Every message sent should reach every joined client, the sender included.
+    pub expected: u64,
+    pub received: u64,
+    /// Fraction of expected deliveries that never arrived.
+    pub loss: f64,
+    pub deliveries_per_sec: f64,
+    pub latency_ms: Latency,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} clients ({} joined), {} senders at {} msg/s, {}-byte bodies, {:.1} s",
+            self.clients,
+            self.joined,
+            self.senders,
+            self.rate,
+            self.size,
+            self.duration_ms as f64 / 1000.0
+        )?;
+        writeln!(
+            f,
+            "sent {} messages, {} of {} deliveries received ({:.2}% lost)",
+            self.sent,
+            self.received,
+            self.expected,
+            self.loss * 100.0
+        )?;
+        writeln!(f, "throughput {:.0} deliveries/s", self.deliveries_per_sec)?;
+        let l = &self.latency_ms;
+        write!(
+            f,
+            "latency ms: p50 {:.3}  p90 {:.3}  p99 {:.3}  p99.9 {:.3}  max {:.3}",
+            l.p50, l.p90, l.p99, l.p999, l.max
+        )
+    }
+}
+
+/// Join the clients, send for the configured time and measure the relays.
+pub async fn run(config: &BenchConfig) -> io::Result<BenchReport> {
+    config.validate()?;
+    let clients = join_all(config).await?;
+    if clients.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("no client could join {}", config.server),
+        ));
+    }
+
+    // 受信側は送信終了後も drain の間だけ待つ
+    let start = Instant::now();
+    let stop_receiving = start + config.duration + config.drain;
+    let mut receivers = JoinSet::new();
+    for (_, sock) in &clients {
+        receivers.spawn(receive(Arc::clone(sock), start, stop_receiving));
+    }
+    let mut senders = JoinSet::new();
+    for (index, sock) in clients.iter().filter(|(i, _)| *i < config.senders) {
+        senders.spawn(send(
+            Arc::clone(sock),
+            config.clone(),
+            client_name(*index),
+            start,
+        ));
+    }
+
+    let mut sent = 0;
+    while let Some(count) = senders.join_next().await {
+        sent += count.map_err(io::Error::other)?;
+    }
+    let mut samples = Vec::new();
+    while let Some(latencies) = receivers.join_next().await {
+        samples.extend(latencies.map_err(io::Error::other)?);
+    }
+
+    let expected = sent * clients.len() as u64;
+    let received = samples.len() as u64;
+    Ok(BenchReport {
+        clients: config.clients,
+        joined: clients.len(),
+        senders: config.senders,
+        rate: config.rate,
+        size: config.size,
+        duration_ms: config.duration.as_millis() as u64,
+        sent,
+        expected,
+        received,
+        loss: match expected {
+            0 => 0.0,
+            _ => expected.saturating_sub(received) as f64 / expected as f64,
+        },
+        deliveries_per_sec: received as f64 / config.duration.as_secs_f64().max(f64::EPSILON),
+        latency_ms: Latency::from_micros(samples),
+    })
+}
+
+fn client_name(index: usize) -> String {
+    format!("{NAME_PREFIX}{index}")
+}
+
+async fn join_all(config: &BenchConfig) -> io::Result<Vec<(usize, Arc<UdpSocket>)>> {
+    let permits = Arc::new(Semaphore::new(JOIN_CONCURRENCY));
+    let mut joins = JoinSet::new();
+    for index in 0..config.clients {
+        let permits = Arc::clone(&permits);
+        let server = config.server;
+        joins.spawn(async move {
+            let _permit = permits.acquire_owned().await.map_err(io::Error::other)?;
+            let local = match server {
+                SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+                SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+            };
+            let sock = UdpSocket::bind(local).await?;
+            let joined = join(&sock, server, &client_name(index)).await?;
+            Ok::<_, io::Error>(joined.then(|| (index, Arc::new(sock))))
+        });
+    }
+    let mut clients = Vec::with_capacity(config.clients);
+    while let Some(joined) = joins.join_next().await {
+        clients.extend(joined.map_err(io::Error::other)??);
+    }
+    clients.sort_by_key(|(index, _)| *index);
+    Ok(clients)
+}
+
+// 自分宛てのダイレクトメッセージで参加し、その折り返しを待つ
+async fn join(sock: &UdpSocket, server: SocketAddr, name: &str) -> io::Result<bool> {
+    let frame = MessageProtocol::direct(name, name, "join")
+        .serialize()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut buf = [0u8; BUFFER_SIZE];
+    for _ in 0..JOIN_ATTEMPTS {
+        sock.send_to(&frame, server).await?;
+        let deadline = Instant::now() + JOIN_TIMEOUT;
+        while let Ok(received) = time::timeout_at(deadline, sock.recv_from(&mut buf)).await {
+            let (len, _) = received?;
+            if MessageProtocol::deserialize(&buf[..len])
+                .is_ok_and(|m| m.kind == MessageKind::Direct && m.user_name == name)
+            {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+async fn send(sock: Arc<UdpSocket>, config: BenchConfig, name: String, start: Instant) -> u64 {
+    let mut ticks = time::interval(Duration::from_secs_f64(1.0 / config.rate));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut padding = Padding::new(name.len() as u32);
+    let mut sent = 0;
+    loop {
+        ticks.tick().await;
+        if start.elapsed() >= config.duration {
+            return sent;
+        }
+        let mut body = format!("{BENCH_TAG} {} ", start.elapsed().as_micros());
+        padding.fill(&mut body, config.size);
+        let Ok(frame) = MessageProtocol::new(name.as_str(), body).serialize() else {
+            continue;
+        };
+        // 送信バッファが溢れた分は送っていないものとして数える
+        if sock.send_to(&frame, config.server).await.is_ok() {
+            sent += 1;
+        }
+    }
+}
+
+async fn receive(sock: Arc<UdpSocket>, start: Instant, until: Instant) -> Vec<u64> {
+    let mut latencies = Vec::new();
+    let mut buf = [0u8; BUFFER_SIZE];
+    while let Ok(Ok((len, _))) = time::timeout_at(until, sock.recv_from(&mut buf)).await {
+        let now = start.elapsed().as_micros() as u64;
+        let sent_at = MessageProtocol::deserialize(&buf[..len])
+            .ok()
+            .filter(|m| m.kind == MessageKind::Chat)
+            .and_then(|m| {
+                let rest = m.body.strip_prefix(BENCH_TAG)?.trim_start();
+                rest.split(' ').next()?.parse::<u64>().ok()
+            });
+        if let Some(sent_at) = sent_at {
+            latencies.push(now.saturating_sub(sent_at));
+        }
+    }
+    latencies
+}
+
+// 圧縮で縮まないよう、詰め物は線形合同法の擬似乱数で作る
+struct Padding(u32);
+
+impl Padding {
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+    fn new(seed: u32) -> Self {
+        Self(seed)
+    }
+
+    fn fill(&mut self, body: &mut String, size: usize) {
+        while body.len() < size {
+            self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            body.push(Self::ALPHABET[(self.0 >> 16) as usize % Self::ALPHABET.len()] as char);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Load generator for the relay.
+//!
+//! `chat-bench [--server <addr>|--local] [--clients N] [--senders N]
+//! [--rate MSG/S] [--size BYTES] [--duration SECS] [--drain SECS]
+//! [--json <path>]` prints a report, then the results as JSON (to stdout,
+//! or to `<path>`). Thousands of clients need a raised `ulimit -n`.
+
+use std::{io, sync::Arc, time::Duration};
+
+use server::{
+    bench::{self, BenchConfig},
+    client_manager::ClientManager,
+    state::ServerState,
+    worker::{default_worker_count, run_workers},
+};
+use tokio::net::UdpSocket;
+
+const USAGE: &str = "usage: chat-bench [--server <addr>|--local] [--clients N] [--senders N] \
+                     [--rate MSG/S] [--size BYTES] [--duration SECS] [--drain SECS] [--json <path>]";
+
+fn invalid(reason: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, reason.into())
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: Option<String>) -> io::Result<T> {
+    let value = value.ok_or_else(|| invalid(format!("{flag} needs a value")))?;
+    value
+        .parse()
+        .map_err(|_| invalid(format!("{flag}: invalid value {value:?}")))
+}
+
+fn seconds(flag: &str, value: Option<String>) -> io::Result<Duration> {
+    Duration::try_from_secs_f64(parse(flag, value)?).map_err(|e| invalid(format!("{flag}: {e}")))
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let mut config = BenchConfig::default();
+    let mut local = false;
+    let mut json_path = None;
+    let mut args = std::env::args().skip(1);
+    let parsed = loop {
+        let Some(flag) = args.next() else {
+            break config.validate();
+        };
+        let result = match flag.as_str() {
+            "--server" => parse(&flag, args.next()).map(|addr| config.server = addr),
+            "--local" => {
+                local = true;
+                Ok(())
+            }
+            "--clients" => parse(&flag, args.next()).map(|n| config.clients = n),
+            "--senders" => parse(&flag, args.next()).map(|n| config.senders = n),
+            "--rate" => parse(&flag, args.next()).map(|rate| config.rate = rate),
+            "--size" => parse(&flag, args.next()).map(|size| config.size = size),
+            "--duration" => seconds(&flag, args.next()).map(|d| config.duration = d),
+            "--drain" => seconds(&flag, args.next()).map(|d| config.drain = d),
+            "--json" => parse(&flag, args.next()).map(|path: String| json_path = Some(path)),
+            _ => Err(invalid(format!("unknown argument {flag}"))),
+        };
+        if result.is_err() {
+            break result;
+        }
+    };
+    if let Err(e) = parsed {
+        eprintln!("error: {e}\n{USAGE}");
+        std::process::exit(2);
+    }
+
+    // 同じプロセス内でエフェメラルポートのサーバを立てる
+    if local {
+        let sock = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        config.server = sock.local_addr()?;
+        let manager = Arc::new(ClientManager::new(Duration::from_secs(30)));
+        let state = Arc::new(ServerState::new(manager));
+        tokio::spawn(run_workers(sock, state, default_worker_count()));
+    }
+
+    eprintln!(
+        "benchmarking {} with {} clients for {:.1} s…",
+        config.server,
+        config.clients,
+        config.duration.as_secs_f64()
+    );
+    let report = bench::run(&config).await?;
+    println!("{report}");
+    let json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+    match json_path {
+        Some(path) => std::fs::write(&path, json + "\n")?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Command-line client for the server's admin console.
+//!
+//! `chatctl [--socket <path>] <command> [args…]` runs one command;
+//! without a command, lines are read from stdin and sent one by one.
+
+#[cfg(unix)]
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    use server::admin::{ADMIN_SOCKET_PATH, send_command};
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut path = ADMIN_SOCKET_PATH.to_string();
+    if args.first().is_some_and(|arg| arg == "--socket") {
+        if args.len() < 2 {
+            eprintln!("usage: chatctl [--socket <path>] <command> [args…]");
+            std::process::exit(2);
+        }
+        path = args.remove(1);
+        args.remove(0);
+    }
+
+    // 引数があれば 1 コマンドだけ実行する
+    if !args.is_empty() {
+        return match send_command(&path, &args.join(" ")).await {
+            Ok(output) => {
+                print!("{output}");
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+        };
+    }
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match send_command(&path, &line).await {
+            Ok(output) => print!("{output}"),
+            Err(e) => eprintln!("error: {e}"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("chatctl requires Unix domain sockets");
+    std::process::exit(1);
+}
@@ -1,75 +1,306 @@
 use dashmap::DashMap;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
-use tokio::time::{Instant, interval};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{Notify, broadcast},
+    task::JoinHandle,
+    time::{Instant, sleep_until},
+};
+
+/// Number of events buffered for slow subscribers before they start lagging.
+pub const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+// 期限キュー: last_message_time の古い順に取り出せる最小ヒープ
+type ExpiryHeap = BinaryHeap<Reverse<(Instant, String)>>;
+
+// 期限キューと、次の期限が早まったことをクリーンアップタスクに知らせる通知
+#[derive(Default)]
+struct Expiry {
+    heap: Mutex<ExpiryHeap>,
+    changed: Notify,
+}
+
+/// Privilege level of a client within the room, lowest first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    #[default]
+    Member,
+    Operator,
+    /// The first client to join an empty room.
+    Host,
+}
 
 // クライアントの情報を保持する構造体
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ClientInfo {
     pub user_name: String,
     pub socket_addr: SocketAddr,
     pub last_message_time: Instant,
+    pub role: Role,
+    /// Local address of the server socket the client talks to; everything
+    /// sent to the client leaves through it. `None` uses whichever socket is
+    /// sending.
+    pub ingress: Option<SocketAddr>,
+}
+
+/// Presence changes published by [`ClientManager`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientEvent {
+    /// A user name was seen for the first time (or again after leaving).
+    Joined(ClientInfo),
+    /// A client was removed explicitly via [`ClientManager::remove_client`].
+    Left(ClientInfo),
+    /// A client was evicted because it exceeded the inactivity timeout.
+    TimedOut(ClientInfo),
 }
 
 // クライアント情報を管理するマネージャー
+//
+// 期限切れの判定は全件走査ではなく `expiry` ヒープで行う。
+// 活動があるたびに新しいエントリを積み、古いエントリは取り出した時点で
+// テーブルの値と照合して読み捨てる（遅延削除）。
+// そのため `clients_table` の変更は必ずこの構造体のメソッド経由で行うこと。
+//
+// 時刻はすべて `tokio::time` から取るので、`tokio::time::pause` と `advance`
+// でバックグラウンドクリーンアップも含めて決定的に試せる。
 pub struct ClientManager {
     // Dashboardを使用することで並列アクセス可能
     pub clients_table: Arc<DashMap<String, ClientInfo>>,
-    pub timeout_duration: Duration,
+    // 実行中に変更できるようミリ秒単位で保持し、バックグラウンドタスクと共有する
+    timeout_millis: Arc<AtomicU64>,
+    expiry: Arc<Expiry>,
+    // 退出したクライアントのうち、メンバーより上の役割を持っていたものの最後の役割
+    departed_roles: Arc<DashMap<String, Role>>,
+    events: broadcast::Sender<ClientEvent>,
+    cleanup: Option<JoinHandle<()>>,
 }
 
 impl ClientManager {
     pub fn new(timeout_duration: Duration) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
         Self {
             clients_table: Arc::new(DashMap::new()),
-            timeout_duration,
+            timeout_millis: Arc::new(AtomicU64::new(timeout_millis(timeout_duration))),
+            expiry: Arc::default(),
+            departed_roles: Arc::default(),
+            events,
+            cleanup: None,
         }
     }
 
+    /// Like [`ClientManager::new`], plus a task that evicts each client as
+    /// soon as it passes the timeout. The task stops when the manager is dropped.
     pub fn new_with_background_cleanup(timeout_duration: Duration) -> Self {
-        let manager = Self {
-            clients_table: Arc::new(DashMap::new()),
-            timeout_duration,
-        };
+        let mut manager = Self::new(timeout_duration);
 
         // バックグラウンドクリーンアップタスクを開始
-        let manager_clone = Arc::clone(&manager.clients_table);
-        let timeout_duration_clone = timeout_duration;
+        let table = Arc::clone(&manager.clients_table);
+        let expiry = Arc::clone(&manager.expiry);
+        let timeout_millis = Arc::clone(&manager.timeout_millis);
+        let departed_roles = Arc::clone(&manager.departed_roles);
+        let events = manager.events.clone();
 
-        tokio::spawn(async move {
-            let mut interval = interval(Duration::from_secs(1));
+        manager.cleanup = Some(tokio::spawn(async move {
             loop {
-                interval.tick().await;
-                let now = Instant::now();
-                manager_clone.retain(|_, client| {
-                    now.duration_since(client.last_message_time) < timeout_duration_clone
-                });
+                let timeout_duration =
+                    Duration::from_millis(timeout_millis.load(Ordering::Relaxed));
+                evict_inactive(
+                    &table,
+                    &expiry.heap,
+                    &departed_roles,
+                    timeout_duration,
+                    &events,
+                );
+                // 一番古い活動の期限まで眠る（期限が早まれば起こされる）
+                let next_deadline = expiry
+                    .heap
+                    .lock()
+                    .unwrap()
+                    .peek()
+                    .map(|Reverse((last_seen, _))| *last_seen + timeout_duration);
+                match next_deadline {
+                    Some(deadline) => tokio::select! {
+                        () = sleep_until(deadline) => {}
+                        () = expiry.changed.notified() => {}
+                    },
+                    None => expiry.changed.notified().await,
+                }
             }
-        });
+        }));
 
         manager
     }
 
+    pub fn timeout_duration(&self) -> Duration {
+        Duration::from_millis(self.timeout_millis.load(Ordering::Relaxed))
+    }
+
+    /// Change the inactivity timeout; takes effect from the next cleanup.
+    ///
+    /// Timeouts are kept in whole milliseconds, at least one.
+    pub fn set_timeout_duration(&self, timeout_duration: Duration) {
+        self.timeout_millis
+            .store(timeout_millis(timeout_duration), Ordering::Relaxed);
+        self.expiry.changed.notify_one();
+    }
+
+    /// Subscribe to join / leave / timeout notifications.
+    ///
+    /// Only events published after this call are delivered.
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.events.subscribe()
+    }
+
     pub fn upsert_client(&self, client: ClientInfo) {
-        self.clients_table.insert(client.user_name.clone(), client);
+        let joined = client.clone();
+        let previous = self.clients_table.insert(client.user_name.clone(), client);
+        self.schedule_expiry(joined.last_message_time, &joined.user_name);
+        if previous.is_none() {
+            let _ = self.events.send(ClientEvent::Joined(joined));
+        }
+    }
+
+    /// Change the role of a connected client.
+    pub fn set_role(&self, user_name: &str, role: Role) -> Result<(), String> {
+        match self.clients_table.get_mut(user_name) {
+            Some(mut client) => {
+                client.role = role;
+                Ok(())
+            }
+            None => Err(format!("Client '{}' not found", user_name)),
+        }
+    }
+
+    /// Remove a client explicitly and publish [`ClientEvent::Left`].
+    pub fn remove_client(&self, user_name: &str) -> Option<ClientInfo> {
+        let (_, client) = self.clients_table.remove(user_name)?;
+        remember_role(&self.departed_roles, &client);
+        let _ = self.events.send(ClientEvent::Left(client.clone()));
+        Some(client)
+    }
+
+    /// Role of a connected client, or the role above member a departed one
+    /// last held since the server started. `None` if neither applies.
+    pub fn known_role(&self, user_name: &str) -> Option<Role> {
+        match self.clients_table.get(user_name) {
+            Some(client) => Some(client.role),
+            None => self.departed_roles.get(user_name).map(|role| *role),
+        }
     }
 
     pub fn active_client_count(&self) -> usize {
         self.clients_table.len()
     }
 
-    pub fn cleanup_inactive_clients(&self) {
-        let now = Instant::now();
-        self.clients_table.retain(|_, client| {
-            now.duration_since(client.last_message_time) < self.timeout_duration
-        });
+    /// Evict every client past the timeout and return the evicted entries.
+    ///
+    /// Only clients whose last activity is older than the timeout are
+    /// visited, so the cost is `O(k log n)` for `k` expired entries.
+    pub fn cleanup_inactive_clients(&self) -> Vec<ClientInfo> {
+        evict_inactive(
+            &self.clients_table,
+            &self.expiry.heap,
+            &self.departed_roles,
+            self.timeout_duration(),
+            &self.events,
+        )
     }
 
     pub fn update_client_activity(&self, user_name: &str) -> Result<(), String> {
-        if let Some(mut client) = self.clients_table.get_mut(user_name) {
-            client.last_message_time = Instant::now();
-            Ok(())
-        } else {
-            Err(format!("Client '{}' not found", user_name))
+        let now = Instant::now();
+        // ヒープのロックを取る前にシャードのロックを解放する（ロック順序の逆転を防ぐ）
+        match self.clients_table.get_mut(user_name) {
+            Some(mut client) => client.last_message_time = now,
+            None => return Err(format!("Client '{}' not found", user_name)),
+        }
+        self.schedule_expiry(now, user_name);
+        Ok(())
+    }
+
+    // 最終活動時刻を期限キューに登録する（O(log n)）
+    fn schedule_expiry(&self, last_message_time: Instant, user_name: &str) {
+        let mut heap = self.expiry.heap.lock().unwrap();
+        // 先頭が変わるときだけクリーンアップタスクを起こす（通常の活動更新では起こさない）
+        let earliest = heap
+            .peek()
+            .is_none_or(|Reverse((first, _))| last_message_time < *first);
+        heap.push(Reverse((last_message_time, user_name.to_string())));
+        drop(heap);
+        if earliest {
+            self.expiry.changed.notify_one();
+        }
+    }
+}
+
+impl Drop for ClientManager {
+    fn drop(&mut self) {
+        if let Some(cleanup) = &self.cleanup {
+            cleanup.abort();
+        }
+    }
+}
+
+// 1 ミリ秒未満を 0 に切り捨てると全員が即座に削除されるので、最低 1 ミリ秒にする
+fn timeout_millis(timeout_duration: Duration) -> u64 {
+    u64::try_from(timeout_duration.as_millis())
+        .unwrap_or(u64::MAX)
+        .max(1)
+}
+
+// 退出時の役割を覚える（メンバーに戻っていれば忘れる）
+fn remember_role(departed_roles: &DashMap<String, Role>, client: &ClientInfo) {
+    if client.role > Role::Member {
+        departed_roles.insert(client.user_name.clone(), client.role);
+    } else {
+        departed_roles.remove(&client.user_name);
+    }
+}
+
+// タイムアウトしたクライアントを削除し、TimedOut イベントを通知する
+fn evict_inactive(
+    table: &DashMap<String, ClientInfo>,
+    expiry: &Mutex<ExpiryHeap>,
+    departed_roles: &DashMap<String, Role>,
+    timeout_duration: Duration,
+    events: &broadcast::Sender<ClientEvent>,
+) -> Vec<ClientInfo> {
+    let now = Instant::now();
+    let mut evicted = Vec::new();
+    let mut heap = expiry.lock().unwrap();
+
+    while let Some(Reverse((last_seen, _))) = heap.peek() {
+        if now.duration_since(*last_seen) < timeout_duration {
+            break;
+        }
+        let Reverse((_, user_name)) = heap.pop().unwrap();
+        // 取り出したエントリが古くても、テーブル上の最新の時刻で判定する
+        if let Some((_, client)) = table.remove_if(&user_name, |_, client| {
+            now.duration_since(client.last_message_time) >= timeout_duration
+        }) {
+            remember_role(departed_roles, &client);
+            evicted.push(client);
         }
     }
+
+    // 読み捨て待ちのエントリが溜まりすぎたらテーブルから作り直す
+    if heap.len() > 2 * table.len() + 64 {
+        *heap = table
+            .iter()
+            .map(|entry| Reverse((entry.last_message_time, entry.key().clone())))
+            .collect();
+    }
+    drop(heap);
+
+    for client in &evicted {
+        let _ = events.send(ClientEvent::TimedOut(client.clone()));
+    }
+    evicted
 }
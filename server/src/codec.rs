@@ -0,0 +1,120 @@
+//! Per-client codec negotiation.
+//!
+//! Every client starts on the wire codec. Once joined, a `Codec` frame
+//! switches the codec used for that client's socket address in both
+//! directions; the request and the server's confirmation are always
+//! wire-encoded. The choice is forgotten when the client leaves or times out.
+
+use std::{io, net::SocketAddr};
+
+use dashmap::DashMap;
+use protocol::{
+    MessageKind, MessageProtocol, ProtocolError,
+    codec::{Codec, CodecId, WireCodec},
+};
+use tokio::net::UdpSocket;
+
+use crate::{
+    addr::addr_for_socket,
+    state::{SYSTEM_USER_NAME, ServerState, unix_millis},
+};
+
+#[derive(Default)]
+pub struct Codecs {
+    // wire 以外を選んだクライアントだけを保持する
+    by_addr: DashMap<SocketAddr, CodecId>,
+}
+
+impl Codecs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Codec negotiated by the client at `addr` (wire if none).
+    pub fn codec_for(&self, addr: SocketAddr) -> CodecId {
+        self.by_addr.get(&addr).map_or(CodecId::Wire, |id| *id)
+    }
+
+    pub fn set(&self, addr: SocketAddr, codec: CodecId) {
+        if codec == CodecId::Wire {
+            self.by_addr.remove(&addr);
+        } else {
+            self.by_addr.insert(addr, codec);
+        }
+    }
+
+    pub fn forget(&self, addr: SocketAddr) {
+        self.by_addr.remove(&addr);
+    }
+
+    /// Number of clients using something other than the wire codec.
+    pub fn len(&self) -> usize {
+        self.by_addr.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_addr.is_empty()
+    }
+
+    /// Decode a datagram from `addr` with that client's codec.
+    ///
+    /// A wire-encoded `Codec` frame is accepted whatever the client uses.
+    pub fn decode(&self, data: &[u8], addr: SocketAddr) -> Result<MessageProtocol, ProtocolError> {
+        let codec = self.codec_for(addr);
+        codec.codec().decode(data).or_else(|e| match codec {
+            CodecId::Wire => Err(e),
+            _ => WireCodec
+                .decode(data)
+                .ok()
+                .filter(|m| m.kind == MessageKind::Codec)
+                .ok_or(e),
+        })
+    }
+
+    /// Encode `message` for the client at `addr`.
+    pub fn encode_for(
+        &self,
+        addr: SocketAddr,
+        message: &MessageProtocol,
+    ) -> Result<Vec<u8>, ProtocolError> {
+        self.codec_for(addr).codec().encode(message)
+    }
+}
+
+/// Switch the joined sender of a `Codec` frame to the codec it names.
+///
+/// An unknown name leaves the current codec in place. Either way the sender
+/// is told which codec is now in use.
+pub async fn negotiate(
+    sock: &UdpSocket,
+    state: &ServerState,
+    request: &MessageProtocol,
+    socket_addr: SocketAddr,
+) -> io::Result<()> {
+    // 未参加のアドレスの分まで覚えないよう、参加済みのクライアントに限る
+    let joined = state
+        .client_manager
+        .clients_table
+        .get(&request.user_name)
+        .is_some_and(|client| client.socket_addr == socket_addr);
+    if !joined {
+        #[cfg(feature = "metrics")]
+        state.metrics.record_dropped("unjoined");
+        return Ok(());
+    }
+    let codec = request
+        .codec_id()
+        .unwrap_or_else(|| state.codecs.codec_for(socket_addr));
+    let reply = MessageProtocol {
+        timestamp_ms: unix_millis(),
+        ..MessageProtocol::codec(SYSTEM_USER_NAME, codec)
+    };
+    // 確認は切り替え前に wire 形式で送る
+    let frame = WireCodec
+        .encode(&reply)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    sock.send_to(&frame, addr_for_socket(sock.local_addr()?, socket_addr))
+        .await?;
+    state.codecs.set(socket_addr, codec);
+    Ok(())
+}
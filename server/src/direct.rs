@@ -0,0 +1,264 @@
+//! Offline queue for direct messages.
+//!
+//! A direct message to a user who is not in the client table is kept per
+//! recipient until they send something again, up to [`DM_QUEUE_CAPACITY`]
+//! messages each, for at most [`DM_QUEUE_RECIPIENTS`] recipients at once and
+//! for at most [`DM_QUEUE_TTL`]. Expired messages are discarded when the
+//! queue is next touched, and by [`run_queue_sweeper`] for queues nobody
+//! touches again.
+
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use dashmap::DashMap;
+use protocol::{DeliveryStatus, MessageProtocol};
+use tokio::{
+    net::UdpSocket,
+    time::{Instant, interval},
+};
+
+use crate::{
+    client_manager::ClientInfo,
+    moderation::notify,
+    send_to_client,
+    state::{SYSTEM_USER_NAME, ServerState},
+    unicast,
+};
+
+/// Most direct messages kept for one offline recipient.
+pub const DM_QUEUE_CAPACITY: usize = 100;
+/// How long a queued direct message waits for its recipient.
+pub const DM_QUEUE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Most offline recipients with queued messages at once.
+pub const DM_QUEUE_RECIPIENTS: usize = 1_000;
+/// How often [`run_queue_sweeper`] discards expired messages.
+pub const DM_QUEUE_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum QueueFull {
+    #[error("{0} has too many undelivered messages")]
+    Recipient(String),
+    #[error("too many users have undelivered messages")]
+    Recipients,
+}
+
+/// Messages taken from a recipient's queue.
+#[derive(Debug, Default)]
+pub struct Drained {
+    /// Still valid; deliver in this order.
+    pub pending: Vec<MessageProtocol>,
+    /// Waited longer than the queue's time-to-live.
+    pub expired: Vec<MessageProtocol>,
+}
+
+pub struct DirectQueue {
+    capacity: usize,
+    max_recipients: usize,
+    ttl: Duration,
+    // 宛先ごとの (キューに入れた時刻, メッセージ)
+    queues: DashMap<String, VecDeque<(Instant, MessageProtocol)>>,
+}
+
+impl Default for DirectQueue {
+    fn default() -> Self {
+        Self::new(DM_QUEUE_CAPACITY, DM_QUEUE_TTL)
+    }
+}
+
+impl DirectQueue {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            max_recipients: DM_QUEUE_RECIPIENTS,
+            ttl,
+            queues: DashMap::new(),
+        }
+    }
+
+    /// Limit how many recipients may have queued messages at once.
+    pub fn with_max_recipients(mut self, max_recipients: usize) -> Self {
+        self.max_recipients = max_recipients;
+        self
+    }
+
+    /// Total number of queued messages.
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(|queue| queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of recipients with queued messages.
+    pub fn recipient_count(&self) -> usize {
+        self.queues.len()
+    }
+
+    /// Queue a stamped direct message for its offline recipient.
+    ///
+    /// Returns the messages that expired meanwhile, so their senders can be told.
+    pub fn enqueue(&self, message: MessageProtocol) -> Result<Vec<MessageProtocol>, QueueFull> {
+        let now = Instant::now();
+        // 新しい宛先は、期限切れを掃除してもまだ上限なら断る
+        // （エントリのロックを取る前に数える）
+        let mut expired = Vec::new();
+        if !self.queues.contains_key(&message.recipient) && self.queues.len() >= self.max_recipients
+        {
+            expired = self.sweep();
+            if self.queues.len() >= self.max_recipients {
+                return Err(QueueFull::Recipients);
+            }
+        }
+        let mut queue = self.queues.entry(message.recipient.clone()).or_default();
+        expired.extend(self.drop_expired(&mut queue, now));
+        if queue.len() >= self.capacity {
+            return Err(QueueFull::Recipient(message.recipient));
+        }
+        queue.push_back((now, message));
+        Ok(expired)
+    }
+
+    /// Discard expired messages from every queue and forget emptied recipients.
+    ///
+    /// Returns the discarded messages, so their senders can be told.
+    pub fn sweep(&self) -> Vec<MessageProtocol> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        self.queues.retain(|_, queue| {
+            expired.extend(self.drop_expired(queue, now));
+            !queue.is_empty()
+        });
+        expired
+    }
+
+    /// Take every message queued for `recipient`.
+    pub fn drain(&self, recipient: &str) -> Drained {
+        let Some((_, mut queue)) = self.queues.remove(recipient) else {
+            return Drained::default();
+        };
+        let expired = self.drop_expired(&mut queue, Instant::now());
+        Drained {
+            pending: queue.into_iter().map(|(_, message)| message).collect(),
+            expired,
+        }
+    }
+
+    // 古い順に並んでいるので、先頭から期限切れを取り除く
+    fn drop_expired(
+        &self,
+        queue: &mut VecDeque<(Instant, MessageProtocol)>,
+        now: Instant,
+    ) -> Vec<MessageProtocol> {
+        let mut expired = Vec::new();
+        while let Some((queued_at, _)) = queue.front() {
+            if now.duration_since(*queued_at) < self.ttl {
+                break;
+            }
+            expired.extend(queue.pop_front().map(|(_, message)| message));
+        }
+        expired
+    }
+}
+
+/// Deliver a stamped direct message, or queue it if the recipient is offline.
+///
+/// The sender gets the message echoed back followed by a `DirectStatus`.
+pub async fn send_direct(
+    sock: &UdpSocket,
+    state: &ServerState,
+    message: MessageProtocol,
+    sender: &ClientInfo,
+) {
+    if message.recipient.is_empty() || message.recipient == SYSTEM_USER_NAME {
+        notify(state, sock, sender, "direct messages need a recipient").await;
+        return;
+    }
+    unicast(sock, state, sender.socket_addr, &message).await;
+
+    let status = if send_to_user(sock, state, &message.recipient, &message).await {
+        DeliveryStatus::Delivered
+    } else {
+        match state.direct_queue.enqueue(message.clone()) {
+            Ok(expired) => {
+                report_expired(sock, state, expired).await;
+                DeliveryStatus::Queued
+            }
+            Err(e) => {
+                notify(state, sock, sender, &e.to_string()).await;
+                return;
+            }
+        }
+    };
+    unicast(
+        sock,
+        state,
+        sender.socket_addr,
+        &status_frame(&message, status),
+    )
+    .await;
+}
+
+/// Sweep the offline queue every [`DM_QUEUE_SWEEP_INTERVAL`] and tell the
+/// senders of expired messages. Runs until the task is aborted.
+pub async fn run_queue_sweeper(sock: Arc<UdpSocket>, state: Arc<ServerState>) {
+    let mut ticks = interval(DM_QUEUE_SWEEP_INTERVAL);
+    loop {
+        ticks.tick().await;
+        let expired = state.direct_queue.sweep();
+        report_expired(&sock, &state, expired).await;
+    }
+}
+
+/// Hand every queued direct message to a user who has just (re)joined.
+pub async fn deliver_queued(sock: &UdpSocket, state: &ServerState, recipient: &ClientInfo) {
+    let drained = state.direct_queue.drain(&recipient.user_name);
+    for message in drained.pending {
+        send_to_client(sock, state, recipient, &message).await;
+        // 送信者がオフラインなら通知は届かない
+        send_to_user(
+            sock,
+            state,
+            &message.user_name,
+            &status_frame(&message, DeliveryStatus::Delivered),
+        )
+        .await;
+    }
+    report_expired(sock, state, drained.expired).await;
+}
+
+async fn report_expired(sock: &UdpSocket, state: &ServerState, expired: Vec<MessageProtocol>) {
+    for message in expired {
+        let status = status_frame(&message, DeliveryStatus::Expired);
+        send_to_user(sock, state, &message.user_name, &status).await;
+    }
+}
+
+fn status_frame(message: &MessageProtocol, status: DeliveryStatus) -> MessageProtocol {
+    MessageProtocol {
+        timestamp_ms: crate::state::unix_millis(),
+        ..MessageProtocol::direct_status(
+            SYSTEM_USER_NAME,
+            message.id,
+            message.recipient.as_str(),
+            status,
+        )
+    }
+}
+
+// 接続中のユーザーにだけ送る。送れたかどうかを返す
+async fn send_to_user(
+    sock: &UdpSocket,
+    state: &ServerState,
+    user_name: &str,
+    message: &MessageProtocol,
+) -> bool {
+    let client = state
+        .client_manager
+        .clients_table
+        .get(user_name)
+        .map(|client| client.value().clone());
+    match client {
+        Some(client) => send_to_client(sock, state, &client, message).await,
+        None => false,
+    }
+}
@@ -0,0 +1,176 @@
+//! Sending one datagram to many recipients.
+//!
+//! The default path issues one `send_to` per recipient, driven concurrently.
+//! With the `batched-io` feature on Linux, relays use `sendmmsg` so a whole
+//! room is covered by a handful of syscalls, and the receive loop drains the
+//! socket with `recvmmsg`.
+
+use std::{io, net::SocketAddr};
+
+use futures::future::join_all;
+use tokio::net::UdpSocket;
+
+/// Send `data` to every address in `recipients` using the fastest available path.
+///
+/// Returns the recipients that could not be reached together with the error.
+pub async fn send_to_all(
+    sock: &UdpSocket,
+    data: &[u8],
+    recipients: &[SocketAddr],
+) -> Vec<(SocketAddr, io::Error)> {
+    #[cfg(all(target_os = "linux", feature = "batched-io"))]
+    return send_to_all_batched(sock, data, recipients).await;
+
+    #[cfg(not(all(target_os = "linux", feature = "batched-io")))]
+    return send_to_all_plain(sock, data, recipients).await;
+}
+
+/// Send `data` with one concurrent `send_to` per recipient.
+pub async fn send_to_all_plain(
+    sock: &UdpSocket,
+    data: &[u8],
+    recipients: &[SocketAddr],
+) -> Vec<(SocketAddr, io::Error)> {
+    let results = join_all(recipients.iter().map(|to| sock.send_to(data, to))).await;
+    recipients
+        .iter()
+        .zip(results)
+        .filter_map(|(to, result)| result.err().map(|e| (*to, e)))
+        .collect()
+}
+
+#[cfg(all(target_os = "linux", feature = "batched-io"))]
+pub use linux::{MAX_BATCH, recv_batch, send_to_all_batched};
+
+#[cfg(all(target_os = "linux", feature = "batched-io"))]
+mod linux {
+    use std::{io, mem, net::SocketAddr, os::fd::AsRawFd, ptr};
+
+    use socket2::SockAddr;
+    use tokio::{io::Interest, net::UdpSocket};
+
+    /// Largest number of messages passed to one `sendmmsg` / `recvmmsg` call (`UIO_MAXIOV`).
+    pub const MAX_BATCH: usize = 1024;
+
+    /// Send `data` to every recipient with as few `sendmmsg` calls as possible.
+    pub async fn send_to_all_batched(
+        sock: &UdpSocket,
+        data: &[u8],
+        recipients: &[SocketAddr],
+    ) -> Vec<(SocketAddr, io::Error)> {
+        let addrs: Vec<SockAddr> = recipients.iter().map(|&addr| addr.into()).collect();
+        let mut failures = Vec::new();
+        let mut sent = 0;
+        while sent < addrs.len() {
+            let end = (sent + MAX_BATCH).min(addrs.len());
+            match send_chunk(sock, data, &addrs[sent..end]).await {
+                Ok(n) => sent += n,
+                // sendmmsg は先頭のメッセージで失敗したときだけエラーを返すので、1件飛ばして続ける
+                Err(e) => {
+                    failures.push((recipients[sent], e));
+                    sent += 1;
+                }
+            }
+        }
+        failures
+    }
+
+    async fn send_chunk(sock: &UdpSocket, data: &[u8], addrs: &[SockAddr]) -> io::Result<usize> {
+        // 生ポインタを含むヘッダは Send ではないため、await をまたがないようクロージャ内で組み立てる
+        sock.async_io(Interest::WRITABLE, || {
+            let mut iov = libc::iovec {
+                iov_base: data.as_ptr() as *mut libc::c_void,
+                iov_len: data.len(),
+            };
+            let mut msgs: Vec<libc::mmsghdr> = addrs
+                .iter()
+                .map(|addr| {
+                    // SAFETY: mmsghdr is a plain C struct for which all-zero is a valid value.
+                    let mut msg: libc::mmsghdr = unsafe { mem::zeroed() };
+                    msg.msg_hdr.msg_name = addr.as_ptr() as *mut libc::c_void;
+                    msg.msg_hdr.msg_namelen = addr.len();
+                    msg.msg_hdr.msg_iov = &mut iov;
+                    msg.msg_hdr.msg_iovlen = 1;
+                    msg
+                })
+                .collect();
+            // SAFETY: every header points at live addresses and at `data`, which the
+            // kernel only reads.
+            let n = unsafe {
+                libc::sendmmsg(
+                    sock.as_raw_fd(),
+                    msgs.as_mut_ptr(),
+                    msgs.len() as libc::c_uint,
+                    0,
+                )
+            };
+            if n < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(n as usize)
+            }
+        })
+        .await
+    }
+
+    /// Receive up to `bufs.len()` datagrams with a single `recvmmsg` call.
+    ///
+    /// Waits until at least one datagram is available and returns the length
+    /// and sender of each datagram written into `bufs`, in order.
+    pub async fn recv_batch<const N: usize>(
+        sock: &UdpSocket,
+        bufs: &mut [[u8; N]],
+    ) -> io::Result<Vec<(usize, SocketAddr)>> {
+        let count = bufs.len().min(MAX_BATCH);
+        sock.async_io(Interest::READABLE, || {
+            // SAFETY: sockaddr_storage is valid when zeroed.
+            let mut names: Vec<libc::sockaddr_storage> = vec![unsafe { mem::zeroed() }; count];
+            let mut iovs: Vec<libc::iovec> = bufs[..count]
+                .iter_mut()
+                .map(|buf| libc::iovec {
+                    iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+                    iov_len: N,
+                })
+                .collect();
+            let mut msgs: Vec<libc::mmsghdr> = names
+                .iter_mut()
+                .zip(iovs.iter_mut())
+                .map(|(name, iov)| {
+                    // SAFETY: see `send_chunk`.
+                    let mut msg: libc::mmsghdr = unsafe { mem::zeroed() };
+                    msg.msg_hdr.msg_name = name as *mut _ as *mut libc::c_void;
+                    msg.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as u32;
+                    msg.msg_hdr.msg_iov = iov;
+                    msg.msg_hdr.msg_iovlen = 1;
+                    msg
+                })
+                .collect();
+            // SAFETY: every header points at a distinct, live buffer and address slot.
+            let n = unsafe {
+                libc::recvmmsg(
+                    sock.as_raw_fd(),
+                    msgs.as_mut_ptr(),
+                    count as libc::c_uint,
+                    libc::MSG_DONTWAIT,
+                    ptr::null_mut(),
+                )
+            };
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            msgs[..n as usize]
+                .iter()
+                .zip(&names)
+                .map(|(msg, name)| {
+                    // SAFETY: the kernel filled `name` and reported its length.
+                    let addr = unsafe { SockAddr::new(*name, msg.msg_hdr.msg_namelen) };
+                    addr.as_socket()
+                        .map(|addr| (msg.msg_len as usize, addr))
+                        .ok_or_else(|| io::Error::other("datagram from a non-IP address"))
+                })
+                .collect()
+        })
+        .await
+    }
+}
@@ -0,0 +1,230 @@
+//! Recent chat history, kept so messages can be edited, deleted and reacted
+//! to by id, and so their delivery and read receipts can be queried.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
+
+use dashmap::DashMap;
+use protocol::{MAX_REACTION_LEN, MessageKind, MessageProtocol, ReceiptStatus};
+
+use crate::client_manager::{ClientInfo, Role};
+
+/// Number of chat messages remembered; older ones can no longer be changed.
+pub const HISTORY_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HistoryError {
+    #[error("message #{0} not found")]
+    NotFound(u64),
+
+    #[error("only the author or an operator may change message #{0}")]
+    NotAuthor(u64),
+
+    #[error("invalid reaction: {0:?}")]
+    InvalidReaction(String),
+
+    #[error("clients may not send {0:?} frames")]
+    Unsupported(MessageKind),
+}
+
+struct Entry {
+    message: MessageProtocol,
+    // リアクションごとに、付けたユーザーの集合
+    reactions: BTreeMap<String, BTreeSet<String>>,
+    // 中継時に部屋にいた送信者以外のクライアントと、そのうち受信を確認したクライアント
+    recipients: BTreeSet<String>,
+    delivered: BTreeSet<String>,
+}
+
+impl Entry {
+    fn tally(&self) -> Vec<(String, usize)> {
+        self.reactions
+            .iter()
+            .map(|(reaction, users)| (reaction.clone(), users.len()))
+            .collect()
+    }
+}
+
+/// Stamped chat messages and their reactions, indexed by id.
+pub struct History {
+    capacity: usize,
+    // ID 順に並ぶので、先頭が最も古いメッセージ
+    messages: Mutex<BTreeMap<u64, Entry>>,
+    // ユーザーごとの既読位置
+    read_up_to: DashMap<String, u64>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(HISTORY_CAPACITY)
+    }
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            messages: Mutex::new(BTreeMap::new()),
+            read_up_to: DashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Current version of message `id`, if it is still remembered.
+    pub fn get(&self, id: u64) -> Option<MessageProtocol> {
+        let messages = self.messages.lock().unwrap();
+        messages.get(&id).map(|entry| entry.message.clone())
+    }
+
+    /// Reaction counts on message `id`, sorted by reaction.
+    pub fn reactions(&self, id: u64) -> Vec<(String, usize)> {
+        let messages = self.messages.lock().unwrap();
+        messages.get(&id).map(Entry::tally).unwrap_or_default()
+    }
+
+    /// Remember a stamped chat message. Other kinds are ignored.
+    pub fn record(&self, message: &MessageProtocol) {
+        if message.kind != MessageKind::Chat || message.id == 0 {
+            return;
+        }
+        let mut messages = self.messages.lock().unwrap();
+        messages.insert(
+            message.id,
+            Entry {
+                message: message.clone(),
+                reactions: BTreeMap::new(),
+                recipients: BTreeSet::new(),
+                delivered: BTreeSet::new(),
+            },
+        );
+        while messages.len() > self.capacity {
+            messages.pop_first();
+        }
+    }
+
+    /// Remember who message `id` was relayed to, so their receipts can be tracked.
+    pub fn track_delivery(&self, id: u64, recipients: impl IntoIterator<Item = String>) {
+        let mut messages = self.messages.lock().unwrap();
+        if let Some(entry) = messages.get_mut(&id) {
+            entry.recipients.extend(recipients);
+        }
+    }
+
+    /// Record that `user_name` received message `id`.
+    ///
+    /// Returns `false` if the message is unknown or was not relayed to them.
+    pub fn acknowledge(&self, user_name: &str, id: u64) -> bool {
+        let mut messages = self.messages.lock().unwrap();
+        match messages.get_mut(&id) {
+            Some(entry) if entry.recipients.contains(user_name) => {
+                entry.delivered.insert(user_name.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Record that `user_name` has read every message up to `id`.
+    ///
+    /// Markers only move forward and never past the newest message.
+    pub fn mark_read(&self, user_name: &str, id: u64) {
+        let newest = {
+            let messages = self.messages.lock().unwrap();
+            messages.last_key_value().map_or(0, |(&newest, _)| newest)
+        };
+        let id = id.min(newest);
+        self.read_up_to
+            .entry(user_name.to_string())
+            .and_modify(|read| *read = (*read).max(id))
+            .or_insert(id);
+    }
+
+    /// Receipt state of message `id` for each recipient, sorted by user name.
+    pub fn receipts(&self, id: u64) -> Option<Vec<(String, ReceiptStatus)>> {
+        let messages = self.messages.lock().unwrap();
+        let entry = messages.get(&id)?;
+        let states = entry
+            .recipients
+            .iter()
+            .map(|user_name| {
+                let read = self
+                    .read_up_to
+                    .get(user_name)
+                    .is_some_and(|read| *read >= id);
+                let status = if read {
+                    ReceiptStatus::Read
+                } else if entry.delivered.contains(user_name) {
+                    ReceiptStatus::Delivered
+                } else {
+                    ReceiptStatus::Pending
+                };
+                (user_name.clone(), status)
+            })
+            .collect();
+        Some(states)
+    }
+
+    /// Apply an `Edit`, `Delete` or `React` request from `sender`.
+    ///
+    /// `sender` is the registered client the frame came from, not the name it
+    /// claims. Edits and deletions must come from the original author or an
+    /// operator; anyone may react. Returns the frame to relay: the request
+    /// itself, or the updated tally for a reaction.
+    pub fn apply(
+        &self,
+        request: &MessageProtocol,
+        sender: &ClientInfo,
+    ) -> Result<MessageProtocol, HistoryError> {
+        let id = request.target_id;
+        let mut messages = self.messages.lock().unwrap();
+        let entry = messages.get_mut(&id).ok_or(HistoryError::NotFound(id))?;
+        let is_author = entry.message.user_name == sender.user_name;
+        match request.kind {
+            MessageKind::Edit | MessageKind::Delete
+                if !is_author && sender.role < Role::Operator =>
+            {
+                Err(HistoryError::NotAuthor(id))
+            }
+            MessageKind::Edit => {
+                entry.message.body = request.body.clone();
+                Ok(request.clone())
+            }
+            MessageKind::Delete => {
+                messages.remove(&id);
+                Ok(request.clone())
+            }
+            MessageKind::React => {
+                let reaction = &request.body;
+                if reaction.is_empty()
+                    || reaction.len() > MAX_REACTION_LEN
+                    || reaction.chars().any(char::is_whitespace)
+                {
+                    return Err(HistoryError::InvalidReaction(reaction.clone()));
+                }
+                // 同じリアクションをもう一度送ると取り消しになる
+                let users = entry.reactions.entry(reaction.clone()).or_default();
+                if !users.insert(sender.user_name.clone()) {
+                    users.remove(&sender.user_name);
+                    if users.is_empty() {
+                        entry.reactions.remove(reaction);
+                    }
+                }
+                Ok(MessageProtocol::reactions(
+                    &sender.user_name,
+                    id,
+                    &entry.tally(),
+                ))
+            }
+            kind => Err(HistoryError::Unsupported(kind)),
+        }
+    }
+}
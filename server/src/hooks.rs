@@ -0,0 +1,110 @@
+//! Server-side hooks that see, change or stop messages.
+//!
+//! Hooks are registered on [`ServerState::hooks`](crate::state::ServerState)
+//! and run in registration order. [`MessageHook::on_receive`] sees every
+//! decoded frame before the server acts on it; [`MessageHook::on_relay`] sees
+//! chat, edits, deletions, reactions, direct messages and file offers from
+//! joined, unmuted users just before they are stored and sent on, to the room
+//! or to a direct message's recipient. Either may change the message in
+//! place, drop it, or answer the sender instead. The first hook that drops or
+//! replies ends the chain. Joins and departures are reported to
+//! [`MessageHook::on_join`] and [`MessageHook::on_leave`] as they happen.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+};
+
+use protocol::MessageProtocol;
+
+use crate::client_manager::ClientInfo;
+
+/// What the server should do with a message after a hook has seen it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookAction {
+    /// Carry on with the message, including any changes the hook made.
+    Pass,
+    /// Discard the message silently.
+    Drop,
+    /// Discard the message and send this one to its sender instead.
+    Reply(MessageProtocol),
+}
+
+/// A step in the server's message pipeline; every method defaults to passing.
+pub trait MessageHook: Send + Sync {
+    /// A frame from `from` was decoded; nothing has been done with it yet.
+    ///
+    /// Changes to the sender's name are honoured, including by ban checks.
+    fn on_receive(&self, _message: &mut MessageProtocol, _from: SocketAddr) -> HookAction {
+        HookAction::Pass
+    }
+
+    /// A message from `sender` is about to be stored and sent on.
+    fn on_relay(&self, _message: &mut MessageProtocol, _sender: &ClientInfo) -> HookAction {
+        HookAction::Pass
+    }
+
+    /// A user joined the room.
+    fn on_join(&self, _client: &ClientInfo) {}
+
+    /// A user left the room or timed out.
+    fn on_leave(&self, _client: &ClientInfo) {}
+}
+
+/// Hooks in the order they run.
+#[derive(Default)]
+pub struct Hooks {
+    chain: RwLock<Vec<Arc<dyn MessageHook>>>,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a hook; it runs after every hook registered before it.
+    pub fn register(&self, hook: impl MessageHook + 'static) {
+        self.chain.write().unwrap().push(Arc::new(hook));
+    }
+
+    pub fn len(&self) -> usize {
+        self.chain.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run [`MessageHook::on_receive`] along the chain.
+    pub fn on_receive(&self, message: &mut MessageProtocol, from: SocketAddr) -> HookAction {
+        self.run(|hook| hook.on_receive(message, from))
+    }
+
+    /// Run [`MessageHook::on_relay`] along the chain.
+    pub fn on_relay(&self, message: &mut MessageProtocol, sender: &ClientInfo) -> HookAction {
+        self.run(|hook| hook.on_relay(message, sender))
+    }
+
+    pub fn on_join(&self, client: &ClientInfo) {
+        for hook in self.chain.read().unwrap().iter() {
+            hook.on_join(client);
+        }
+    }
+
+    pub fn on_leave(&self, client: &ClientInfo) {
+        for hook in self.chain.read().unwrap().iter() {
+            hook.on_leave(client);
+        }
+    }
+
+    // 破棄か返信をしたフックで打ち切る
+    fn run(&self, mut step: impl FnMut(&dyn MessageHook) -> HookAction) -> HookAction {
+        for hook in self.chain.read().unwrap().iter() {
+            match step(hook.as_ref()) {
+                HookAction::Pass => {}
+                action => return action,
+            }
+        }
+        HookAction::Pass
+    }
+}
@@ -1,22 +1,59 @@
-use protocol::MessageProtocol;
-use std::{io, sync::Arc};
-use tokio::{net::UdpSocket, time::Instant};
+use protocol::{MessageKind, MessageProtocol, codec::CodecId};
+use std::{collections::HashMap, io, net::SocketAddr, sync::Arc};
+use tokio::{
+    net::UdpSocket,
+    sync::broadcast::{self, error::RecvError},
+    time::Instant,
+};
 
+pub mod addr;
+#[cfg(unix)]
+pub mod admin;
+pub mod bench;
 pub mod client_manager;
-use client_manager::{ClientInfo, ClientManager};
+pub mod codec;
+pub mod direct;
+pub mod fanout;
+pub mod history;
+pub mod hooks;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod moderation;
+pub mod state;
+pub mod transfer;
+pub mod typing;
+pub mod worker;
+use client_manager::{ClientEvent, ClientInfo, Role};
+use moderation::ModCommand;
+use state::ServerState;
 
 pub const SERVER_ADDRESS: &str = "0.0.0.0";
+/// Bind address that accepts both IPv4 and IPv6 peers on one socket.
+pub const DUAL_STACK_ADDRESS: &str = "::";
 pub const SERVER_PORT: u16 = 9001;
-pub const BUFFER_SIZE: usize = 1024;
+/// Receive buffer size; fits the largest frame the protocol allows.
+pub const BUFFER_SIZE: usize = protocol::MAX_BUFFER_SIZE;
 
 /// Bind a UDP socket and prepare a fixed‑size buffer.
 pub async fn set_up_server() -> io::Result<(UdpSocket, [u8; BUFFER_SIZE])> {
-    let sock = UdpSocket::bind(format!("{}:{}", SERVER_ADDRESS, SERVER_PORT)).await?;
+    let sock = UdpSocket::bind((SERVER_ADDRESS, SERVER_PORT)).await?;
     let buf = [0; BUFFER_SIZE];
     println!("Server is running on port {}", SERVER_PORT);
     Ok((sock, buf))
 }
 
+/// Bind one UDP socket per address; `[::]` binds dual-stack.
+pub fn set_up_server_on(addrs: &[SocketAddr]) -> io::Result<Vec<UdpSocket>> {
+    addrs
+        .iter()
+        .map(|&addr| {
+            let sock = addr::bind_udp(addr)?;
+            println!("Server is running on {}", sock.local_addr()?);
+            Ok(sock)
+        })
+        .collect()
+}
+
 /// Receive one datagram and echo it back.
 pub async fn handle_client(sock: &UdpSocket, buf: &mut [u8; BUFFER_SIZE]) -> io::Result<()> {
     println!("\nWaiting for a message…");
@@ -34,52 +71,509 @@ pub async fn handle_client(sock: &UdpSocket, buf: &mut [u8; BUFFER_SIZE]) -> io:
     Ok(())
 }
 
-/// Receive one datagram, echo it back, and manage client information.
+/// Receive one datagram, relay it to every known client, and manage client information.
 pub async fn handle_client_with_manager(
     sock: &UdpSocket,
     buf: &mut [u8; BUFFER_SIZE],
-    client_manager: &Arc<ClientManager>,
+    state: &Arc<ServerState>,
 ) -> io::Result<()> {
     println!("\nWaiting for a message…");
 
     let (len, addr) = sock.recv_from(buf).await?;
     println!("\n{len:?} bytes received from {addr:?}");
+    println!(
+        "\nReceived message: {}",
+        String::from_utf8_lossy(&buf[..len])
+    );
 
-    let message = String::from_utf8_lossy(&buf[..len]);
-    println!("\nReceived message: {message}");
+    process_datagram(sock, &buf[..len], addr, state).await
+}
 
-    if !message.is_empty() {
-        // プロトコルに従ってメッセージを解析
-        let user_name = match MessageProtocol::deserialize(&buf[..len]) {
-            Ok(msg_protocol) => msg_protocol.user_name,
-            Err(_) => {
-                // プロトコル解析に失敗した場合は従来の方法でフォールバック
-                message
-                    .split_whitespace()
-                    .next()
-                    .unwrap_or("anonymous")
-                    .to_string()
-            }
-        };
+/// Register the sender of `data` and relay the datagram to every known client.
+///
+/// A name held by a client at another address cannot be used: such frames
+/// are answered with a notice and otherwise ignored, so nobody can take over
+/// another user's entry, role or traffic.
+///
+/// Protocol frames are stamped with a message id and the server receive time
+/// before relaying; anything else is relayed unchanged. The sender is included
+/// in the fan-out, so it receives its own message as an echo. Edit, delete and
+/// reaction requests update the history first and are only relayed if allowed;
+/// a reaction is relayed as the message's new reaction tally. Typing signals
+/// are throttled, never stored, and do not refresh the sender's activity.
+/// Receipt frames update the history's delivery state and are never relayed;
+/// heartbeats only (re)join the sender.
+/// Direct messages go only to their recipient, or wait in the offline queue
+/// until the recipient sends something again. File offers are size-checked
+/// and relayed like chat; their chunks are reassembled, acknowledged and
+/// relayed by [`transfer::handle`]. Frames are decoded, and sent back, in
+/// the codec each client negotiated (see [`codec`]). Registered [`hooks`]
+/// see every decoded frame first, and chat, direct messages and file offers
+/// again before they are sent on.
+pub async fn process_datagram(
+    sock: &UdpSocket,
+    data: &[u8],
+    addr: SocketAddr,
+    state: &Arc<ServerState>,
+) -> io::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    #[cfg(feature = "metrics")]
+    state.metrics.record_received(data.len());
+
+    // 送信元が選んだ方式でメッセージを解析
+    let socket_addr = addr::canonical_addr(addr);
+    let mut message = state
+        .codecs
+        .decode(data, socket_addr)
+        .inspect_err(|_e| {
+            #[cfg(feature = "metrics")]
+            state.metrics.record_decode_failure(_e);
+        })
+        .ok();
+
+    // フックは解析できたフレームだけを、サーバが扱う前に見る（送信者名の書き換えも反映する）
+    if let Some(received) = message.as_mut() {
+        let action = state.hooks.on_receive(received, socket_addr);
+        if !apply_hook(sock, state, action, socket_addr).await {
+            return Ok(());
+        }
+    }
+    let user_name = match &message {
+        Some(msg_protocol) => msg_protocol.user_name.clone(),
+        // 方式を切り替えたクライアントのフレームは従来の形式として読まない
+        None if state.codecs.codec_for(socket_addr) != CodecId::Wire => return Ok(()),
+        None => {
+            // プロトコル解析に失敗した場合は従来の方法でフォールバック
+            String::from_utf8_lossy(data)
+                .split_whitespace()
+                .next()
+                .unwrap_or("anonymous")
+                .to_string()
+        }
+    };
+
+    // BAN されたユーザーと、システム名を騙るフレームは破棄する
+    if user_name == state::SYSTEM_USER_NAME {
+        #[cfg(feature = "metrics")]
+        state.metrics.record_dropped("reserved_name");
+        return Ok(());
+    }
+    if state.moderation.is_banned(&user_name, socket_addr.ip()) {
+        #[cfg(feature = "metrics")]
+        state.metrics.record_dropped("banned");
+        return Ok(());
+    }
+
+    // 符号化方式の切り替えは中継しない
+    if let Some(request) = message.as_ref().filter(|m| m.kind == MessageKind::Codec) {
+        return codec::negotiate(sock, state, request, socket_addr).await;
+    }
+
+    // 受信確認・既読・照会は中継しない
+    if let Some(control) = message.as_ref().filter(|m| {
+        matches!(
+            m.kind,
+            MessageKind::Ack | MessageKind::Read | MessageKind::Receipts
+        )
+    }) {
+        return handle_receipt(sock, state, control, socket_addr).await;
+    }
+
+    // ファイルのチャンクと確認応答は転送ごとに処理する
+    if let Some(control) = message
+        .as_ref()
+        .filter(|m| matches!(m.kind, MessageKind::FileChunk | MessageKind::FileAck))
+    {
+        return transfer::handle(sock, state, control, socket_addr).await;
+    }
 
-        // クライアント情報を作成・更新
-        let client_info = ClientInfo {
-            user_name: user_name.clone(),
-            socket_addr: addr,
-            last_message_time: Instant::now(),
+    // 入力中通知は保存せず、最終発言時刻も更新しない
+    if let Some(typing) = message.as_ref().filter(|m| m.kind == MessageKind::Typing) {
+        return relay_typing(sock, state, typing, socket_addr).await;
+    }
+
+    // 別のアドレスが使っている名前は名乗れない（役割も引き継がせない）
+    let manager = &state.client_manager;
+    let holder = manager
+        .clients_table
+        .get(&user_name)
+        .map(|c| (c.socket_addr, c.role));
+    if holder.is_some_and(|(holder_addr, _)| holder_addr != socket_addr) {
+        #[cfg(feature = "metrics")]
+        state.metrics.record_dropped("name_taken");
+        let notice = state.system_notice(format!("the name {user_name} is already in use"));
+        unicast(sock, state, socket_addr, &notice).await;
+        return Ok(());
+    }
+
+    // 既存の役割は引き継ぎ、空の部屋に最初に入ったクライアントをホストにする
+    let existing = holder.map(|(_, role)| role);
+    let role = match existing {
+        Some(role) => role,
+        None if manager.active_client_count() == 0 => Role::Host,
+        None => Role::Member,
+    };
+
+    // クライアント情報を作成・更新
+    let client_info = ClientInfo {
+        user_name,
+        socket_addr,
+        last_message_time: Instant::now(),
+        role,
+        ingress: Some(sock.local_addr()?),
+    };
+
+    // クライアントをテーブルに追加または更新
+    manager.upsert_client(client_info.clone());
+
+    // 戻ってきたユーザーには、不在中に届いた DM を先に渡す
+    if existing.is_none() {
+        direct::deliver_queued(sock, state, &client_info).await;
+    }
+
+    // 生存通知は参加状態と最終活動時刻を更新するだけで中継しない
+    if message
+        .as_ref()
+        .is_some_and(|m| m.kind == MessageKind::Heartbeat)
+    {
+        return Ok(());
+    }
+
+    // コマンドは中継せず、エラーは本人にだけ返す
+    if let Some(command) = message
+        .as_ref()
+        .filter(|m| m.kind == MessageKind::Chat)
+        .and_then(|m| ModCommand::parse(&m.body))
+    {
+        let result = match command {
+            Ok(command) => moderation::execute(command, &client_info, sock, state).await,
+            Err(e) => Err(e),
         };
+        if let Err(e) = result {
+            moderation::notify(state, sock, &client_info, &e.to_string()).await;
+        }
+        return Ok(());
+    }
+    if let Some(remaining) = state.moderation.muted_for(&client_info.user_name) {
+        #[cfg(feature = "metrics")]
+        state.metrics.record_dropped("muted");
+        let notice = format!("you are muted for {}s", remaining.as_secs() + 1);
+        moderation::notify(state, sock, &client_info, &notice).await;
+        return Ok(());
+    }
 
-        // クライアントをテーブルに追加または更新
-        client_manager.upsert_client(client_info);
-        println!("Updated client info for user: {}", user_name);
+    // フックは部屋への発言だけでなく、DM とファイルの申し出も送り出す前に見る
+    if let Some(outgoing) = message.as_mut() {
+        let action = state.hooks.on_relay(outgoing, &client_info);
+        if !apply_hook(sock, state, action, socket_addr).await {
+            return Ok(());
+        }
+    }
 
-        // メッセージをエコーバック
-        let sent = sock.send_to(message.as_bytes(), addr).await?;
-        println!("sent {sent} bytes to {addr:?}");
+    // DM は宛先と送信者にだけ送る
+    if let Some(mut direct) = message
+        .as_ref()
+        .filter(|m| m.kind == MessageKind::Direct)
+        .cloned()
+    {
+        state.stamp(&mut direct);
+        direct::send_direct(sock, state, direct, &client_info).await;
+        return Ok(());
+    }
+
+    // ファイルの申し出は大きさを確かめてから、ID を転送 ID として中継する
+    if let Some(mut offer) = message
+        .as_ref()
+        .filter(|m| m.kind == MessageKind::FileOffer)
+        .cloned()
+    {
+        match transfer::Transfers::check_offer(&offer) {
+            Ok(file) => {
+                state.stamp(&mut offer);
+                state.transfers.open(offer.id, &client_info.user_name, file);
+                let frame = offer
+                    .serialize()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                return relay(sock, state, &frame).await;
+            }
+            Err(e) => {
+                moderation::notify(state, sock, &client_info, &e.to_string()).await;
+                return Ok(());
+            }
+        }
+    }
+
+    let frame = match message {
+        Some(mut msg_protocol) => {
+            // 編集・削除・リアクションは履歴を更新できた場合だけ中継する
+            if msg_protocol.kind != MessageKind::Chat {
+                match state.history.apply(&msg_protocol, &client_info) {
+                    Ok(update) => msg_protocol = update,
+                    Err(e) => {
+                        moderation::notify(state, sock, &client_info, &e.to_string()).await;
+                        return Ok(());
+                    }
+                }
+            }
+            state.stamp(&mut msg_protocol);
+            state.history.record(&msg_protocol);
+            if msg_protocol.kind == MessageKind::Chat {
+                state.typing.clear(&msg_protocol.user_name);
+                let recipients: Vec<String> = manager
+                    .clients_table
+                    .iter()
+                    .filter(|client| client.user_name != msg_protocol.user_name)
+                    .map(|client| client.user_name.clone())
+                    .collect();
+                state.history.track_delivery(msg_protocol.id, recipients);
+            }
+            msg_protocol
+                .serialize()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        }
+        None => data.to_vec(),
+    };
+    relay(sock, state, &frame).await
+}
+
+// フックの判断を反映し、処理を続けるかどうかを返す
+async fn apply_hook(
+    sock: &UdpSocket,
+    state: &ServerState,
+    action: hooks::HookAction,
+    socket_addr: SocketAddr,
+) -> bool {
+    match action {
+        hooks::HookAction::Pass => return true,
+        hooks::HookAction::Drop => {}
+        hooks::HookAction::Reply(mut reply) => {
+            state.stamp(&mut reply);
+            unicast(sock, state, socket_addr, &reply).await;
+        }
+    }
+    #[cfg(feature = "metrics")]
+    state.metrics.record_dropped("hook");
+    false
+}
+
+// 参加済みクライアントからの受信確認・既読位置・受信状況の照会を処理する
+async fn handle_receipt(
+    sock: &UdpSocket,
+    state: &ServerState,
+    control: &MessageProtocol,
+    socket_addr: SocketAddr,
+) -> io::Result<()> {
+    let user_name = control.user_name.as_str();
+    let joined = state
+        .client_manager
+        .clients_table
+        .get(user_name)
+        .is_some_and(|client| client.socket_addr == socket_addr);
+    if !joined {
+        #[cfg(feature = "metrics")]
+        state.metrics.record_dropped("unjoined");
+        return Ok(());
     }
+    // 受信確認も生存の証拠として扱う
+    let _ = state.client_manager.update_client_activity(user_name);
+
+    let reply = match control.kind {
+        MessageKind::Ack => {
+            state.history.acknowledge(user_name, control.target_id);
+            return Ok(());
+        }
+        MessageKind::Read => {
+            state.history.mark_read(user_name, control.target_id);
+            return Ok(());
+        }
+        _ => match state.history.receipts(control.target_id) {
+            Some(receipts) => {
+                MessageProtocol::receipts(state::SYSTEM_USER_NAME, control.target_id, &receipts)
+            }
+            None => MessageProtocol::new(
+                state::SYSTEM_USER_NAME,
+                history::HistoryError::NotFound(control.target_id).to_string(),
+            ),
+        },
+    };
+    let reply = MessageProtocol {
+        timestamp_ms: state::unix_millis(),
+        ..reply
+    };
+    unicast(sock, state, socket_addr, &reply).await;
     Ok(())
 }
 
+// 参加済みでミュートされていないクライアントの入力中通知を、間引いたうえで中継する
+async fn relay_typing(
+    sock: &UdpSocket,
+    state: &ServerState,
+    typing: &MessageProtocol,
+    socket_addr: SocketAddr,
+) -> io::Result<()> {
+    let joined = state
+        .client_manager
+        .clients_table
+        .get(&typing.user_name)
+        .is_some_and(|client| client.socket_addr == socket_addr);
+    if !joined
+        || state.moderation.muted_for(&typing.user_name).is_some()
+        || !state.typing.signal(&typing.user_name)
+    {
+        #[cfg(feature = "metrics")]
+        state.metrics.record_dropped("typing");
+        return Ok(());
+    }
+    // ID は割り当てず、受信時刻だけを付ける
+    let signal = MessageProtocol {
+        timestamp_ms: state::unix_millis(),
+        ..MessageProtocol::typing(typing.user_name.as_str())
+    };
+    let frame = signal
+        .serialize()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    relay(sock, state, &frame).await
+}
+
+/// Log joins and departures, forget departed clients' codecs and tell the hooks.
+///
+/// Runs until the client manager is dropped.
+pub async fn follow_client_events(
+    state: Arc<ServerState>,
+    mut events: broadcast::Receiver<ClientEvent>,
+) {
+    loop {
+        match events.recv().await {
+            Ok(ClientEvent::Joined(client)) => {
+                println!("{} joined from {}", client.user_name, client.socket_addr);
+                state.hooks.on_join(&client);
+            }
+            Ok(ClientEvent::Left(client)) => {
+                println!("{} left", client.user_name);
+                state.codecs.forget(client.socket_addr);
+                state.hooks.on_leave(&client);
+            }
+            Ok(ClientEvent::TimedOut(client)) => {
+                println!("{} timed out", client.user_name);
+                state.codecs.forget(client.socket_addr);
+                state.hooks.on_leave(&client);
+            }
+            Err(RecvError::Lagged(skipped)) => println!("missed {skipped} client events"),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Send `frame` to every known client concurrently.
+///
+/// A failure for one recipient is logged and does not stop the others.
+pub async fn relay(sock: &UdpSocket, state: &ServerState, frame: &[u8]) -> io::Result<()> {
+    relay_except(sock, state, frame, None).await
+}
+
+/// Like [`relay`], leaving out the client named `skip`.
+///
+/// `frame` is wire-encoded; clients that negotiated another codec get it
+/// re-encoded, once per codec.
+pub async fn relay_except(
+    sock: &UdpSocket,
+    state: &ServerState,
+    frame: &[u8],
+    skip: Option<&str>,
+) -> io::Result<()> {
+    #[cfg(feature = "metrics")]
+    let started = Instant::now();
+
+    // 参加したソケットと符号化方式ごとに分ける
+    let mut groups: HashMap<(Option<SocketAddr>, CodecId), Vec<SocketAddr>> = HashMap::new();
+    for client in state.client_manager.clients_table.iter() {
+        if Some(client.user_name.as_str()) != skip {
+            groups
+                .entry((client.ingress, state.codecs.codec_for(client.socket_addr)))
+                .or_default()
+                .push(client.socket_addr);
+        }
+    }
+    let mut failures = Vec::new();
+    for ((ingress, codec), peers) in groups {
+        // 送信ソケットのアドレスファミリに合わせる
+        let routed = route(state, ingress);
+        let sock = routed.as_deref().unwrap_or(sock);
+        let local = sock.local_addr()?;
+        let recipients: Vec<SocketAddr> = peers
+            .into_iter()
+            .map(|peer| addr::addr_for_socket(local, peer))
+            .collect();
+        // プロトコル以外のデータグラムはそのまま送る
+        let encoded = match codec {
+            CodecId::Wire => None,
+            _ => MessageProtocol::deserialize(frame)
+                .and_then(|message| codec.codec().encode(&message))
+                .ok(),
+        };
+        let frame = encoded.as_deref().unwrap_or(frame);
+        let failed = fanout::send_to_all(sock, frame, &recipients).await;
+        #[cfg(feature = "metrics")]
+        state
+            .metrics
+            .record_sent(recipients.len() - failed.len(), frame.len());
+        failures.extend(failed);
+    }
+
+    #[cfg(feature = "metrics")]
+    state.metrics.record_relay_latency(started.elapsed());
+    for (to, e) in failures {
+        eprintln!("failed to relay to {to}: {e}");
+    }
+    Ok(())
+}
+
+/// Send `message` to the single client at `socket_addr`, in its codec.
+///
+/// Returns whether the datagram was sent.
+pub async fn unicast(
+    sock: &UdpSocket,
+    state: &ServerState,
+    socket_addr: SocketAddr,
+    message: &MessageProtocol,
+) -> bool {
+    let Ok(frame) = state.codecs.encode_for(socket_addr, message) else {
+        return false;
+    };
+    let Ok(local) = sock.local_addr() else {
+        return false;
+    };
+    sock.send_to(&frame, addr::addr_for_socket(local, socket_addr))
+        .await
+        .is_ok()
+}
+
+/// Send `message` to `client` through the socket it joined through.
+///
+/// Returns whether the datagram was sent.
+pub async fn send_to_client(
+    sock: &UdpSocket,
+    state: &ServerState,
+    client: &ClientInfo,
+    message: &MessageProtocol,
+) -> bool {
+    let routed = route(state, client.ingress);
+    unicast(
+        routed.as_deref().unwrap_or(sock),
+        state,
+        client.socket_addr,
+        message,
+    )
+    .await
+}
+
+// 参加したソケットがまだ登録されていればそれを返す
+fn route(state: &ServerState, ingress: Option<SocketAddr>) -> Option<Arc<UdpSocket>> {
+    ingress.and_then(|local| state.sockets.get(local))
+}
+
 /// Production helper that runs forever.
 pub async fn run_forever() -> io::Result<()> {
     let (sock, mut buf) = set_up_server().await?;
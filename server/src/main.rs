@@ -1,10 +1,40 @@
-use std::{io, sync::Arc, time::Duration};
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
 
-use server::{client_manager::ClientManager, handle_client_with_manager, set_up_server};
+use tokio::task::JoinSet;
+
+use server::{
+    SERVER_ADDRESS, SERVER_PORT,
+    client_manager::ClientManager,
+    follow_client_events,
+    moderation::Moderation,
+    set_up_server_on,
+    state::ServerState,
+    worker::{default_worker_count, run_workers},
+};
+
+#[cfg(unix)]
+use server::admin::ADMIN_SOCKET_PATH;
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    let (sock, mut buf) = set_up_server().await?;
+    // 引数でバインドアドレスを複数指定できる（例: `server [::]:9001 0.0.0.0:9002`）
+    let mut addrs = std::env::args()
+        .skip(1)
+        .map(|arg| {
+            arg.parse::<SocketAddr>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{arg}: {e}")))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    if addrs.is_empty() {
+        addrs.push(SocketAddr::new(
+            SERVER_ADDRESS.parse().unwrap(),
+            SERVER_PORT,
+        ));
+    }
+    let socks: Vec<Arc<_>> = set_up_server_on(&addrs)?
+        .into_iter()
+        .map(Arc::new)
+        .collect();
 
     // クライアント管理機能を初期化（30秒のタイムアウト、バックグラウンドクリーンアップ有効）
     let client_manager = Arc::new(ClientManager::new_with_background_cleanup(
@@ -13,9 +43,60 @@ async fn main() -> io::Result<()> {
 
     println!("Client manager initialized with 30s timeout and background cleanup");
 
-    loop {
-        handle_client_with_manager(&sock, &mut buf, &client_manager).await?;
-        println!("Active clients: {}", client_manager.active_client_count());
-        println!("--------------------------------");
+    let events = client_manager.subscribe();
+
+    // ソケットごとに受信ループとワーカータスクでデータグラムを並行処理
+    // BAN リストはファイルに保存する（パスは CHAT_BAN_FILE で変更可能）
+    let ban_file = std::env::var("CHAT_BAN_FILE")
+        .unwrap_or_else(|_| server::moderation::BAN_FILE_PATH.to_string());
+    let moderation = Moderation::load(&ban_file)?;
+    println!("Loaded {} bans from {ban_file}", moderation.ban_count());
+    let state = Arc::new(ServerState::new_with_moderation(client_manager, moderation));
+
+    // 入退室イベントをログに出力し、退室したクライアントの符号化方式を忘れ、フックに知らせる
+    tokio::spawn(follow_client_events(Arc::clone(&state), events));
+
+    // 宛先が戻らないまま期限切れになった DM を定期的に捨てる
+    tokio::spawn(server::direct::run_queue_sweeper(
+        Arc::clone(&socks[0]),
+        Arc::clone(&state),
+    ));
+
+    // 管理コンソール（通知やキックは最初のソケットから送信する）
+    #[cfg(unix)]
+    {
+        let state = Arc::clone(&state);
+        let sock = Arc::clone(&socks[0]);
+        tokio::spawn(async move {
+            if let Err(e) = server::admin::run_admin(ADMIN_SOCKET_PATH, state, sock).await {
+                eprintln!("admin console stopped: {e}");
+            }
+        });
+    }
+
+    // Prometheus メトリクス（アドレスは CHAT_METRICS_ADDR で変更可能）
+    #[cfg(feature = "metrics")]
+    {
+        let addr = std::env::var("CHAT_METRICS_ADDR")
+            .unwrap_or_else(|_| server::metrics::METRICS_ADDRESS.to_string())
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(e) = server::metrics::run_metrics_server(addr, state).await {
+                eprintln!("metrics endpoint stopped: {e}");
+            }
+        });
+    }
+
+    let workers = default_worker_count();
+    println!("Processing datagrams on {workers} workers per socket");
+    let mut servers = JoinSet::new();
+    for sock in socks {
+        servers.spawn(run_workers(sock, Arc::clone(&state), workers));
+    }
+    while let Some(result) = servers.join_next().await {
+        result.map_err(io::Error::other)??;
     }
+    Ok(())
 }
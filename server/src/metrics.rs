@@ -0,0 +1,273 @@
+//! Prometheus metrics (enabled with the `metrics` feature).
+//!
+//! Counters are plain atomics updated on the datagram path. They are rendered
+//! in the Prometheus text exposition format by a minimal HTTP listener that
+//! answers `GET /metrics`.
+
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use dashmap::DashMap;
+use protocol::ProtocolError;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast::error::RecvError,
+    time::timeout,
+};
+
+use crate::{client_manager::ClientEvent, state::ServerState};
+
+/// Default listen address of the metrics endpoint.
+pub const METRICS_ADDRESS: &str = "0.0.0.0:9464";
+
+/// How long a connection may take to send its request line.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bounds (seconds) of the relay latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 10] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1,
+];
+
+#[derive(Default)]
+pub struct Metrics {
+    pub datagrams_received: AtomicU64,
+    pub datagrams_sent: AtomicU64,
+    pub bytes_received: AtomicU64,
+    pub bytes_sent: AtomicU64,
+    pub evictions: AtomicU64,
+    decode_failures: DashMap<&'static str, AtomicU64>,
+    dropped: DashMap<&'static str, AtomicU64>,
+    relay_latency: Histogram,
+}
+
+#[derive(Default)]
+struct Histogram {
+    // 各バケットは累積ではなく個別に数え、出力時に累積する
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_received(&self, bytes: usize) {
+        self.datagrams_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_sent(&self, datagrams: usize, bytes_each: usize) {
+        self.datagrams_sent
+            .fetch_add(datagrams as u64, Ordering::Relaxed);
+        self.bytes_sent
+            .fetch_add((datagrams * bytes_each) as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_decode_failure(&self, error: &ProtocolError) {
+        let variant = match error {
+            ProtocolError::UsernameTooLong(_) => "username_too_long",
+            ProtocolError::BufferTooLarge(_) => "buffer_too_large",
+            ProtocolError::Truncated { .. } => "truncated",
+            ProtocolError::UnknownKind(_) => "unknown_kind",
+            ProtocolError::UsernameUtf8(_) => "username_utf8",
+            ProtocolError::BodyUtf8(_) => "body_utf8",
+            ProtocolError::DecompressedTooLarge(_) => "decompressed_too_large",
+            ProtocolError::CorruptBody => "corrupt_body",
+            ProtocolError::Codec { .. } => "codec",
+        };
+        increment(&self.decode_failures, variant);
+    }
+
+    /// Count a datagram discarded by the server, labelled with the reason.
+    pub fn record_dropped(&self, reason: &'static str) {
+        increment(&self.dropped, reason);
+    }
+
+    pub fn record_relay_latency(&self, elapsed: Duration) {
+        let latency = &self.relay_latency;
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&bound| secs <= bound) {
+            latency.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        latency.count.fetch_add(1, Ordering::Relaxed);
+        latency
+            .sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Render every metric in the Prometheus text format.
+    pub fn render(&self, active_clients: usize) -> String {
+        let mut out = String::new();
+        counter(
+            &mut out,
+            "chat_datagrams_received_total",
+            "Datagrams received.",
+            &self.datagrams_received,
+        );
+        counter(
+            &mut out,
+            "chat_datagrams_sent_total",
+            "Datagrams sent.",
+            &self.datagrams_sent,
+        );
+        counter(
+            &mut out,
+            "chat_bytes_received_total",
+            "Bytes received.",
+            &self.bytes_received,
+        );
+        counter(
+            &mut out,
+            "chat_bytes_sent_total",
+            "Bytes sent.",
+            &self.bytes_sent,
+        );
+        counter(
+            &mut out,
+            "chat_evictions_total",
+            "Clients evicted by the inactivity timeout.",
+            &self.evictions,
+        );
+        labelled(
+            &mut out,
+            "chat_decode_failures_total",
+            "Datagrams that failed to decode, by protocol error.",
+            "error",
+            &self.decode_failures,
+        );
+        labelled(
+            &mut out,
+            "chat_dropped_datagrams_total",
+            "Datagrams discarded by the server, by reason.",
+            "reason",
+            &self.dropped,
+        );
+
+        out.push_str("# HELP chat_active_clients Clients currently in the client table.\n");
+        out.push_str("# TYPE chat_active_clients gauge\n");
+        out.push_str(&format!("chat_active_clients {active_clients}\n"));
+
+        let latency = &self.relay_latency;
+        out.push_str(
+            "# HELP chat_relay_duration_seconds Time to fan a message out to all clients.\n",
+        );
+        out.push_str("# TYPE chat_relay_duration_seconds histogram\n");
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&latency.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            out.push_str(&format!(
+                "chat_relay_duration_seconds_bucket{{le=\"{bound}\"}} {cumulative}\n"
+            ));
+        }
+        let count = latency.count.load(Ordering::Relaxed);
+        out.push_str(&format!(
+            "chat_relay_duration_seconds_bucket{{le=\"+Inf\"}} {count}\n"
+        ));
+        out.push_str(&format!(
+            "chat_relay_duration_seconds_sum {}\n",
+            latency.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        ));
+        out.push_str(&format!("chat_relay_duration_seconds_count {count}\n"));
+        out
+    }
+}
+
+fn increment(map: &DashMap<&'static str, AtomicU64>, label: &'static str) {
+    map.entry(label)
+        .or_default()
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: &AtomicU64) {
+    out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} counter\n"));
+    out.push_str(&format!("{name} {}\n", value.load(Ordering::Relaxed)));
+}
+
+fn labelled(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: &DashMap<&'static str, AtomicU64>,
+) {
+    out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} counter\n"));
+    let mut values: Vec<(&str, u64)> = values
+        .iter()
+        .map(|entry| (*entry.key(), entry.value().load(Ordering::Relaxed)))
+        .collect();
+    values.sort();
+    for (value, count) in values {
+        out.push_str(&format!("{name}{{{label}=\"{value}\"}} {count}\n"));
+    }
+}
+
+/// Serve `GET /metrics` on `addr` until accepting fails.
+///
+/// Also starts counting timeout evictions from the client manager's events.
+pub async fn run_metrics_server(addr: SocketAddr, state: Arc<ServerState>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    println!(
+        "Metrics available on http://{}/metrics",
+        listener.local_addr()?
+    );
+    serve_metrics(listener, state).await
+}
+
+/// Like [`run_metrics_server`], on an already bound listener.
+pub async fn serve_metrics(listener: TcpListener, state: Arc<ServerState>) -> io::Result<()> {
+    let mut events = state.client_manager.subscribe();
+    let counted = Arc::clone(&state);
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(ClientEvent::TimedOut(_)) => {
+                    counted.metrics.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &state).await {
+                eprintln!("metrics request failed: {e}");
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, state: &ServerState) -> io::Result<()> {
+    // リクエスト行だけを見る（ヘッダーと本文は読み捨てる）
+    let mut buf = [0u8; 1024];
+    // 何も送ってこない接続にタスクを握らせ続けない
+    let len = timeout(REQUEST_TIMEOUT, stream.read(&mut buf))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no request received"))??;
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+
+    let response = if request.starts_with("GET ") && path == "/metrics" {
+        let body = state
+            .metrics
+            .render(state.client_manager.active_client_count());
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
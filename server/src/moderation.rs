@@ -0,0 +1,407 @@
+//! Room moderation: roles, kick, timed mute and bans.
+//!
+//! Clients issue commands as chat messages starting with `/`:
+//!
+//! * `/kick <user>`
+//! * `/mute <user> <seconds>` / `/unmute <user>`
+//! * `/ban <user>` / `/ban-ip <user|ip>` / `/unban <user|ip>`
+//! * `/op <user>` / `/deop <user>` (host only)
+//!
+//! Operators may act on members; the host may act on everyone. Bans are
+//! written to a plain text file (`user <name>` / `ip <addr>` per line) so they
+//! survive restarts.
+
+use std::{
+    fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use dashmap::{DashMap, DashSet};
+use tokio::{net::UdpSocket, time::Instant};
+
+use crate::{
+    client_manager::{ClientInfo, Role},
+    relay,
+    state::ServerState,
+};
+
+/// Default location of the persisted ban list.
+pub const BAN_FILE_PATH: &str = "bans.txt";
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ModerationError {
+    #[error("/{command} requires the {required:?} role")]
+    NotAuthorized {
+        command: &'static str,
+        required: Role,
+    },
+
+    #[error("cannot /{command} {target}: they outrank or equal you")]
+    Outranked {
+        command: &'static str,
+        target: String,
+    },
+
+    #[error("{0} is not connected")]
+    UnknownUser(String),
+
+    #[error("{0} is not banned")]
+    NotBanned(String),
+
+    #[error("invalid command: {0}")]
+    Invalid(String),
+
+    #[error("failed to save bans: {0}")]
+    Persist(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModCommand {
+    Kick(String),
+    Mute(String, Duration),
+    Unmute(String),
+    Ban(String),
+    /// Ban an IP literal, or the current IP of a connected user.
+    BanIp(String),
+    Unban(String),
+    Op(String),
+    Deop(String),
+}
+
+impl ModCommand {
+    /// Parse a chat body. Returns `None` if it is not a moderation command;
+    /// other `/` lines are left to the hooks and the room.
+    pub fn parse(body: &str) -> Option<Result<Self, ModerationError>> {
+        let command = body.strip_prefix('/')?;
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let target = words.next().map(str::to_string);
+        let usage = |args: &str| ModerationError::Invalid(format!("usage: /{name} {args}"));
+
+        let parsed = match (name, target) {
+            ("kick", Some(user)) => Ok(Self::Kick(user)),
+            ("mute", Some(user)) => match words.next().map(str::parse::<u64>) {
+                Some(Ok(secs)) if secs > 0 => Ok(Self::Mute(user, Duration::from_secs(secs))),
+                _ => Err(usage("<user> <seconds>")),
+            },
+            ("unmute", Some(user)) => Ok(Self::Unmute(user)),
+            ("ban", Some(user)) => Ok(Self::Ban(user)),
+            ("ban-ip", Some(target)) => Ok(Self::BanIp(target)),
+            ("unban", Some(target)) => Ok(Self::Unban(target)),
+            ("op", Some(user)) => Ok(Self::Op(user)),
+            ("deop", Some(user)) => Ok(Self::Deop(user)),
+            ("kick" | "unmute" | "ban" | "op" | "deop", None) => Err(usage("<user>")),
+            ("mute", None) => Err(usage("<user> <seconds>")),
+            ("ban-ip" | "unban", None) => Err(usage("<user|ip>")),
+            _ => return None,
+        };
+        Some(parsed)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Kick(_) => "kick",
+            Self::Mute(..) => "mute",
+            Self::Unmute(_) => "unmute",
+            Self::Ban(_) => "ban",
+            Self::BanIp(_) => "ban-ip",
+            Self::Unban(_) => "unban",
+            Self::Op(_) => "op",
+            Self::Deop(_) => "deop",
+        }
+    }
+
+    /// Lowest role allowed to issue this command.
+    pub fn required_role(&self) -> Role {
+        match self {
+            Self::Op(_) | Self::Deop(_) => Role::Host,
+            _ => Role::Operator,
+        }
+    }
+}
+
+/// Check that `actor` may run `command` against a target holding `target`.
+///
+/// Operators may only act on members; the host may act on anyone but itself.
+pub fn authorize(
+    command: &ModCommand,
+    actor: Role,
+    target: Option<(&str, Role)>,
+) -> Result<(), ModerationError> {
+    let required = command.required_role();
+    if actor < required {
+        return Err(ModerationError::NotAuthorized {
+            command: command.name(),
+            required,
+        });
+    }
+    match target {
+        Some((name, role)) if role >= actor => Err(ModerationError::Outranked {
+            command: command.name(),
+            target: name.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Bans and mutes shared by the datagram path, chat commands and the admin console.
+#[derive(Default)]
+pub struct Moderation {
+    banned_users: DashSet<String>,
+    banned_ips: DashSet<IpAddr>,
+    // ミュート解除時刻
+    muted: DashMap<String, Instant>,
+    ban_file: Option<PathBuf>,
+    // ファイルへの書き込みを直列化する
+    persist_lock: Mutex<()>,
+}
+
+impl Moderation {
+    /// Moderation state that is not persisted.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load bans from `path` (missing file = no bans) and save changes back to it.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let moderation = Self {
+            ban_file: Some(path.to_path_buf()),
+            ..Self::default()
+        };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(moderation),
+            Err(e) => return Err(e),
+        };
+        for (number, line) in contents.lines().enumerate() {
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: invalid ban entry", path.display(), number + 1),
+                )
+            };
+            match line.trim().split_once(' ') {
+                Some(("user", name)) => {
+                    moderation.banned_users.insert(name.to_string());
+                }
+                Some(("ip", ip)) => {
+                    moderation
+                        .banned_ips
+                        .insert(ip.parse().map_err(|_| invalid())?);
+                }
+                None if line.trim().is_empty() => {}
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(moderation)
+    }
+
+    pub fn is_banned(&self, user_name: &str, ip: IpAddr) -> bool {
+        self.banned_users.contains(user_name) || self.banned_ips.contains(&ip)
+    }
+
+    pub fn ban_count(&self) -> usize {
+        self.banned_users.len() + self.banned_ips.len()
+    }
+
+    pub fn ban_user(&self, user_name: &str) -> io::Result<()> {
+        self.banned_users.insert(user_name.to_string());
+        self.persist()
+    }
+
+    pub fn ban_ip(&self, ip: IpAddr) -> io::Result<()> {
+        self.banned_ips.insert(ip);
+        self.persist()
+    }
+
+    /// Lift a ban on a user name or IP literal. Returns whether a ban existed.
+    pub fn unban(&self, target: &str) -> io::Result<bool> {
+        let removed = match target.parse::<IpAddr>() {
+            Ok(ip) => self.banned_ips.remove(&ip).is_some(),
+            Err(_) => self.banned_users.remove(target).is_some(),
+        };
+        if removed {
+            self.persist()?;
+        }
+        Ok(removed)
+    }
+
+    pub fn mute(&self, user_name: &str, duration: Duration) {
+        self.muted
+            .insert(user_name.to_string(), Instant::now() + duration);
+    }
+
+    pub fn unmute(&self, user_name: &str) -> bool {
+        self.muted.remove(user_name).is_some()
+    }
+
+    /// Remaining mute time, or `None` if the user may speak.
+    pub fn muted_for(&self, user_name: &str) -> Option<Duration> {
+        let now = Instant::now();
+        let until = *self.muted.get(user_name)?;
+        if until > now {
+            Some(until - now)
+        } else {
+            // 期限切れのミュートはここで片付ける
+            self.muted.remove_if(user_name, |_, until| *until <= now);
+            None
+        }
+    }
+
+    fn persist(&self) -> io::Result<()> {
+        let Some(path) = &self.ban_file else {
+            return Ok(());
+        };
+        let _guard = self.persist_lock.lock().unwrap();
+        let mut lines: Vec<String> = self
+            .banned_users
+            .iter()
+            .map(|name| format!("user {}", *name))
+            .chain(self.banned_ips.iter().map(|ip| format!("ip {}", *ip)))
+            .collect();
+        lines.sort();
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+
+        // 書き込み途中で落ちても壊れないよう一時ファイル経由で置き換える
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(tmp, path)
+    }
+}
+
+/// Authorize and run a chat command issued by `actor`.
+///
+/// Successful actions are announced to the room.
+pub async fn execute(
+    command: ModCommand,
+    actor: &ClientInfo,
+    sock: &UdpSocket,
+    state: &ServerState,
+) -> Result<(), ModerationError> {
+    let manager = &state.client_manager;
+    let moderation = &state.moderation;
+    let connected = |user_name: &str| {
+        manager
+            .clients_table
+            .get(user_name)
+            .map(|client| client.value().clone())
+    };
+    let require = |user_name: &str| {
+        connected(user_name).ok_or_else(|| ModerationError::UnknownUser(user_name.to_string()))
+    };
+    let persisted =
+        |result: io::Result<()>| result.map_err(|e| ModerationError::Persist(e.to_string()));
+
+    let announcement = match &command {
+        ModCommand::Kick(user_name) => {
+            let target = require(user_name)?;
+            authorize(&command, actor.role, Some((user_name, target.role)))?;
+            kick(state, sock, user_name, "you were kicked").await;
+            format!("{user_name} was kicked by {}", actor.user_name)
+        }
+        ModCommand::Mute(user_name, duration) => {
+            let target = require(user_name)?;
+            authorize(&command, actor.role, Some((user_name, target.role)))?;
+            moderation.mute(user_name, *duration);
+            format!(
+                "{user_name} was muted for {}s by {}",
+                duration.as_secs(),
+                actor.user_name
+            )
+        }
+        ModCommand::Unmute(user_name) => {
+            authorize(&command, actor.role, None)?;
+            if !moderation.unmute(user_name) {
+                return Err(ModerationError::Invalid(format!(
+                    "{user_name} is not muted"
+                )));
+            }
+            format!("{user_name} was unmuted by {}", actor.user_name)
+        }
+        ModCommand::Ban(user_name) => {
+            // 退出中の相手も、最後に持っていた役割で比べる
+            let role = manager.known_role(user_name).unwrap_or_default();
+            authorize(&command, actor.role, Some((user_name, role)))?;
+            persisted(moderation.ban_user(user_name))?;
+            kick(state, sock, user_name, "you were banned").await;
+            format!("{user_name} was banned by {}", actor.user_name)
+        }
+        ModCommand::BanIp(target) => {
+            let ip = match target.parse::<IpAddr>() {
+                Ok(ip) => ip,
+                Err(_) => require(target)?.socket_addr.ip(),
+            };
+            authorize(&command, actor.role, None)?;
+            if ip == actor.socket_addr.ip() {
+                return Err(ModerationError::Invalid(
+                    "refusing to ban your own address".to_string(),
+                ));
+            }
+            // 同じ IP から接続している全員が退出させられるので、全員より上位でなければならない
+            let affected: Vec<ClientInfo> = manager
+                .clients_table
+                .iter()
+                .filter(|client| client.socket_addr.ip() == ip)
+                .map(|client| client.value().clone())
+                .collect();
+            for client in &affected {
+                authorize(&command, actor.role, Some((&client.user_name, client.role)))?;
+            }
+            persisted(moderation.ban_ip(ip))?;
+            for client in affected {
+                kick(state, sock, &client.user_name, "your address was banned").await;
+            }
+            format!("{target} was banned by address by {}", actor.user_name)
+        }
+        ModCommand::Unban(target) => {
+            authorize(&command, actor.role, None)?;
+            match moderation.unban(target) {
+                Ok(true) => {}
+                Ok(false) => return Err(ModerationError::NotBanned(target.clone())),
+                Err(e) => return Err(ModerationError::Persist(e.to_string())),
+            }
+            format!("{target} was unbanned by {}", actor.user_name)
+        }
+        ModCommand::Op(user_name) | ModCommand::Deop(user_name) => {
+            let target = require(user_name)?;
+            authorize(&command, actor.role, Some((user_name, target.role)))?;
+            let (role, verb) = match command {
+                ModCommand::Op(_) => (Role::Operator, "is now an operator"),
+                _ => (Role::Member, "is no longer an operator"),
+            };
+            manager
+                .set_role(user_name, role)
+                .map_err(|_| ModerationError::UnknownUser(user_name.clone()))?;
+            format!("{user_name} {verb}")
+        }
+    };
+
+    if let Ok(frame) = state.system_notice(announcement).serialize() {
+        let _ = relay(sock, state, &frame).await;
+    }
+    Ok(())
+}
+
+/// Remove a client and tell only that client why.
+///
+/// Returns `None` if the user was not connected.
+pub async fn kick(
+    state: &ServerState,
+    sock: &UdpSocket,
+    user_name: &str,
+    reason: &str,
+) -> Option<ClientInfo> {
+    let client = state.client_manager.remove_client(user_name)?;
+    notify(state, sock, &client, reason).await;
+    Some(client)
+}
+
+/// Send a system notice to a single client.
+pub async fn notify(state: &ServerState, sock: &UdpSocket, client: &ClientInfo, text: &str) {
+    crate::send_to_client(sock, state, client, &state.system_notice(text)).await;
+}
@@ -0,0 +1,101 @@
+//! Shared state handed to every datagram handler.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use protocol::MessageProtocol;
+use tokio::time::Instant;
+
+use crate::{
+    addr::Sockets, client_manager::ClientManager, codec::Codecs, direct::DirectQueue,
+    history::History, hooks::Hooks, moderation::Moderation, transfer::Transfers,
+    typing::TypingTracker,
+};
+
+/// Sender name used for server-generated notices; clients may not claim it.
+pub const SYSTEM_USER_NAME: &str = "system";
+
+pub struct ServerState {
+    pub client_manager: Arc<ClientManager>,
+    /// Bans and mutes.
+    pub moderation: Moderation,
+    /// Recent messages that may still be edited or deleted.
+    pub history: History,
+    /// Codec negotiated by each client that does not use the wire format.
+    pub codecs: Codecs,
+    /// Direct messages waiting for offline recipients.
+    pub direct_queue: DirectQueue,
+    /// Files being relayed, kept so receivers can resume.
+    pub transfers: Transfers,
+    /// Who is typing right now; never persisted.
+    pub typing: TypingTracker,
+    /// Plugins that filter, rewrite or answer messages.
+    pub hooks: Hooks,
+    /// Sockets the server receives on, for answering through the right one.
+    pub sockets: Sockets,
+    pub started_at: Instant,
+    #[cfg(feature = "metrics")]
+    pub metrics: crate::metrics::Metrics,
+    // 最後に割り当てたメッセージ ID
+    last_message_id: AtomicU64,
+}
+
+impl ServerState {
+    pub fn new(client_manager: Arc<ClientManager>) -> Self {
+        Self::new_with_moderation(client_manager, Moderation::new())
+    }
+
+    /// Like [`ServerState::new`], with existing (e.g. persisted) moderation state.
+    pub fn new_with_moderation(client_manager: Arc<ClientManager>, moderation: Moderation) -> Self {
+        Self {
+            client_manager,
+            moderation,
+            history: History::default(),
+            codecs: Codecs::new(),
+            direct_queue: DirectQueue::default(),
+            transfers: Transfers::default(),
+            typing: TypingTracker::new(),
+            hooks: Hooks::new(),
+            sockets: Sockets::new(),
+            started_at: Instant::now(),
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
+            last_message_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of messages stamped since start-up.
+    pub fn messages_stamped(&self) -> u64 {
+        self.last_message_id.load(Ordering::Relaxed)
+    }
+
+    /// Build a stamped notice from [`SYSTEM_USER_NAME`].
+    pub fn system_notice(&self, body: impl Into<String>) -> MessageProtocol {
+        let mut notice = MessageProtocol::new(SYSTEM_USER_NAME, body);
+        self.stamp(&mut notice);
+        notice
+    }
+
+    /// Allocate the next message id. Ids start at 1 and never repeat.
+    pub fn next_message_id(&self) -> u64 {
+        self.last_message_id.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Assign a fresh id and the current server time to an accepted message.
+    pub fn stamp(&self, message: &mut MessageProtocol) {
+        message.id = self.next_message_id();
+        message.timestamp_ms = unix_millis();
+    }
+}
+
+/// Current wall-clock time in Unix milliseconds.
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
@@ -0,0 +1,250 @@
+//! Relaying file transfers.
+//!
+//! The server is a receiver like any other: it reassembles each offered file
+//! (up to [`MAX_FILE_SIZE`]), acknowledges the sender's chunks, and relays
+//! every new in-order chunk to the rest of the room. A room member that
+//! missed chunks sends a `FileAck` with the first one it lacks and gets a
+//! window resent from the server's copy, so only the server talks to the
+//! sender. The last [`MAX_TRANSFERS`] files are kept for such resumes.
+
+use std::{collections::BTreeMap, io, net::SocketAddr, sync::Mutex};
+
+use protocol::{
+    MessageKind, MessageProtocol,
+    transfer::{Download, FILE_WINDOW, FileOffer, TransferError},
+};
+use tokio::net::UdpSocket;
+
+use crate::{
+    moderation::notify,
+    relay_except,
+    state::{SYSTEM_USER_NAME, ServerState, unix_millis},
+    unicast,
+};
+
+/// Largest file the server accepts, in bytes.
+pub const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// Transfers kept in memory; the oldest is dropped first.
+pub const MAX_TRANSFERS: usize = 16;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum FileTransferError {
+    #[error("malformed file offer")]
+    InvalidOffer,
+
+    #[error("file too large: {0} bytes (max {MAX_FILE_SIZE})")]
+    TooLarge(u64),
+
+    #[error("transfer #{0} not found")]
+    NotFound(u64),
+
+    #[error("transfer #{0} was offered by someone else")]
+    NotSender(u64),
+
+    #[error("malformed chunk for transfer #{0}")]
+    InvalidChunk(u64),
+
+    #[error("transfer #{0} failed: {1}")]
+    Failed(u64, TransferError),
+}
+
+/// Outcome of a chunk from the sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Received {
+    /// The first chunk still missing; acknowledged to the sender.
+    pub next: u32,
+    /// Whether the chunk was new and in order, and so should be relayed.
+    pub accepted: bool,
+}
+
+struct Transfer {
+    sender: String,
+    download: Download,
+}
+
+pub struct Transfers {
+    capacity: usize,
+    transfers: Mutex<BTreeMap<u64, Transfer>>,
+}
+
+impl Default for Transfers {
+    fn default() -> Self {
+        Self::new(MAX_TRANSFERS)
+    }
+}
+
+impl Transfers {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            transfers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.transfers.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Validate the body of a `FileOffer` frame.
+    pub fn check_offer(offer: &MessageProtocol) -> Result<FileOffer, FileTransferError> {
+        let offer = offer.offer().ok_or(FileTransferError::InvalidOffer)?;
+        if offer.size > MAX_FILE_SIZE {
+            return Err(FileTransferError::TooLarge(offer.size));
+        }
+        Ok(offer)
+    }
+
+    /// Start receiving the file offered by `sender` as transfer `id`.
+    pub fn open(&self, id: u64, sender: &str, offer: FileOffer) {
+        let mut transfers = self.transfers.lock().unwrap();
+        transfers.insert(
+            id,
+            Transfer {
+                sender: sender.to_string(),
+                download: Download::new(offer),
+            },
+        );
+        while transfers.len() > self.capacity {
+            transfers.pop_first();
+        }
+    }
+
+    /// Store chunk `index` of transfer `id` sent by `sender`.
+    ///
+    /// A completed file whose checksum does not match is dropped.
+    pub fn receive(
+        &self,
+        id: u64,
+        sender: &str,
+        index: u32,
+        chunk: &[u8],
+    ) -> Result<Received, FileTransferError> {
+        let mut transfers = self.transfers.lock().unwrap();
+        let transfer = transfers
+            .get_mut(&id)
+            .ok_or(FileTransferError::NotFound(id))?;
+        if transfer.sender != sender {
+            return Err(FileTransferError::NotSender(id));
+        }
+        let accepted = transfer
+            .download
+            .receive(index, chunk)
+            .map_err(|e| FileTransferError::Failed(id, e))?;
+        if accepted
+            && transfer.download.is_complete()
+            && let Err(e) = transfer.download.verified()
+        {
+            transfers.remove(&id);
+            return Err(FileTransferError::Failed(id, e));
+        }
+        Ok(Received {
+            next: transfer.download.next_index(),
+            accepted,
+        })
+    }
+
+    /// Sender and first missing chunk of transfer `id`.
+    pub fn progress(&self, id: u64) -> Option<(String, u32)> {
+        let transfers = self.transfers.lock().unwrap();
+        let transfer = transfers.get(&id)?;
+        Some((transfer.sender.clone(), transfer.download.next_index()))
+    }
+
+    /// Up to [`FILE_WINDOW`] chunks of transfer `id` held by the server, from `from`.
+    pub fn chunks_from(&self, id: u64, from: u32) -> Vec<(u32, Vec<u8>)> {
+        let transfers = self.transfers.lock().unwrap();
+        let Some(transfer) = transfers.get(&id) else {
+            return Vec::new();
+        };
+        (from..from.saturating_add(FILE_WINDOW))
+            .map_while(|index| Some((index, transfer.download.chunk(index)?.to_vec())))
+            .collect()
+    }
+}
+
+/// Handle a `FileChunk` or `FileAck` frame from a joined client.
+pub async fn handle(
+    sock: &UdpSocket,
+    state: &ServerState,
+    frame: &MessageProtocol,
+    socket_addr: SocketAddr,
+) -> io::Result<()> {
+    let client = state
+        .client_manager
+        .clients_table
+        .get(&frame.user_name)
+        .filter(|client| client.socket_addr == socket_addr)
+        .map(|client| client.value().clone());
+    let Some(client) = client else {
+        #[cfg(feature = "metrics")]
+        state.metrics.record_dropped("unjoined");
+        return Ok(());
+    };
+    let _ = state
+        .client_manager
+        .update_client_activity(&client.user_name);
+
+    let id = frame.target_id;
+    let result = match (frame.kind, state.transfers.progress(id)) {
+        (_, None) => Err(FileTransferError::NotFound(id)),
+        (MessageKind::FileChunk, Some(_)) => match frame.chunk() {
+            Some((index, chunk)) => state
+                .transfers
+                .receive(id, &client.user_name, index, &chunk)
+                .map(|received| (index, chunk, received)),
+            None => Err(FileTransferError::InvalidChunk(id)),
+        },
+        // 送信者からの照会には再開位置を返す
+        (_, Some((sender, next))) if sender == client.user_name => {
+            unicast(sock, state, client.socket_addr, &ack_frame(id, next)).await;
+            return Ok(());
+        }
+        // 受信者には、要求された位置からサーバの手元にある分を送り直す
+        (_, Some((sender, _))) => {
+            let from = frame.ack_index().unwrap_or(0);
+            for (index, chunk) in state.transfers.chunks_from(id, from) {
+                let resent = chunk_frame(&sender, id, index, &chunk);
+                unicast(sock, state, client.socket_addr, &resent).await;
+            }
+            return Ok(());
+        }
+    };
+
+    match result {
+        Ok((index, chunk, received)) => {
+            if received.accepted {
+                let relayed = chunk_frame(&client.user_name, id, index, &chunk)
+                    .serialize()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                relay_except(sock, state, &relayed, Some(&client.user_name)).await?;
+            }
+            unicast(
+                sock,
+                state,
+                client.socket_addr,
+                &ack_frame(id, received.next),
+            )
+            .await;
+        }
+        Err(e) => notify(state, sock, &client, &e.to_string()).await,
+    }
+    Ok(())
+}
+
+fn ack_frame(id: u64, next: u32) -> MessageProtocol {
+    MessageProtocol {
+        timestamp_ms: unix_millis(),
+        ..MessageProtocol::file_ack(SYSTEM_USER_NAME, id, Some(next))
+    }
+}
+
+fn chunk_frame(sender: &str, id: u64, index: u32, chunk: &[u8]) -> MessageProtocol {
+    MessageProtocol {
+        timestamp_ms: unix_millis(),
+        ..MessageProtocol::file_chunk(sender, id, index, chunk)
+    }
+}
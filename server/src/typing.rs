@@ -0,0 +1,85 @@
+//! Ephemeral "is typing" signals.
+//!
+//! Typing frames are relayed but never stored in the history, and they do not
+//! count as activity for the inactivity timeout. The tracker only remembers
+//! when each user last signalled, to throttle relays and to report who is
+//! typing right now.
+
+use std::time::Duration;
+
+use dashmap::DashMap;
+use protocol::TYPING_TTL_MS;
+use tokio::time::Instant;
+
+/// A signal is dropped if the same user was relayed more recently than this.
+pub const TYPING_RELAY_INTERVAL: Duration = Duration::from_secs(1);
+/// A user stops counting as typing this long after their last signal.
+pub const TYPING_TTL: Duration = Duration::from_millis(TYPING_TTL_MS);
+
+#[derive(Default)]
+pub struct TypingTracker {
+    // 最後に中継した時刻
+    last_signal: DashMap<String, Instant>,
+}
+
+impl TypingTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a typing signal. Returns whether it should be relayed.
+    ///
+    /// A user not yet tracked first causes expired signals to be dropped, so
+    /// the tracker only ever holds users who typed within [`TYPING_TTL`].
+    pub fn signal(&self, user_name: &str) -> bool {
+        let now = Instant::now();
+        if !self.last_signal.contains_key(user_name) {
+            self.prune(now);
+        }
+        let mut relay = true;
+        self.last_signal
+            .entry(user_name.to_string())
+            .and_modify(|last| {
+                if now.duration_since(*last) < TYPING_RELAY_INTERVAL {
+                    relay = false;
+                } else {
+                    *last = now;
+                }
+            })
+            .or_insert(now);
+        relay
+    }
+
+    /// Forget a user's signal, e.g. once their message has been sent.
+    pub fn clear(&self, user_name: &str) {
+        self.last_signal.remove(user_name);
+    }
+
+    /// Users whose last signal has not expired, sorted by name.
+    ///
+    /// Expired signals are dropped as a side effect.
+    pub fn active(&self) -> Vec<String> {
+        self.prune(Instant::now());
+        let mut users: Vec<String> = self
+            .last_signal
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        users.sort();
+        users
+    }
+
+    /// Number of users tracked, expired or not.
+    pub fn len(&self) -> usize {
+        self.last_signal.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last_signal.is_empty()
+    }
+
+    fn prune(&self, now: Instant) {
+        self.last_signal
+            .retain(|_, last| now.duration_since(*last) < TYPING_TTL);
+    }
+}
@@ -0,0 +1,125 @@
+//! Concurrent datagram processing.
+//!
+//! A single receive loop reads datagrams from the shared socket and hands them
+//! to a fixed pool of worker tasks. Datagrams are sharded by sender address, so
+//! every message from one sender is processed by the same worker and relayed in
+//! the order it arrived, while different senders are handled in parallel.
+
+use std::{
+    future,
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use tokio::{net::UdpSocket, sync::mpsc};
+
+use crate::{BUFFER_SIZE, process_datagram, state::ServerState};
+
+/// Datagrams buffered per worker before the receive loop waits.
+pub const WORKER_QUEUE_SIZE: usize = 1024;
+
+/// Datagrams drained per `recvmmsg` call when `batched-io` is enabled.
+#[cfg(all(target_os = "linux", feature = "batched-io"))]
+pub const RECV_BATCH_SIZE: usize = 32;
+
+/// Number of workers to use when none is configured: one per available core.
+pub fn default_worker_count() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Receive datagrams forever and process them on `workers` tasks.
+///
+/// Returns only when receiving from the socket fails.
+pub async fn run_workers(
+    sock: Arc<UdpSocket>,
+    state: Arc<ServerState>,
+    workers: usize,
+) -> io::Result<()> {
+    run_workers_until(sock, state, workers, future::pending()).await
+}
+
+/// Like [`run_workers`], but stop receiving once `shutdown` completes.
+///
+/// Datagrams already handed to the workers are still processed.
+pub async fn run_workers_until(
+    sock: Arc<UdpSocket>,
+    state: Arc<ServerState>,
+    workers: usize,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()> {
+    state.sockets.register(Arc::clone(&sock))?;
+    let workers = workers.max(1);
+    let mut queues = Vec::with_capacity(workers);
+    for _ in 0..workers {
+        let (tx, rx) = mpsc::channel(WORKER_QUEUE_SIZE);
+        tokio::spawn(worker_loop(rx, Arc::clone(&sock), Arc::clone(&state)));
+        queues.push(tx);
+    }
+    // 受信ループを抜けるとキューが閉じ、ワーカーは残りを処理して終わる
+    tokio::select! {
+        result = receive_loop(&sock, &queues) => result,
+        () = shutdown => Ok(()),
+    }
+}
+
+async fn receive_loop(
+    sock: &UdpSocket,
+    queues: &[mpsc::Sender<(Vec<u8>, SocketAddr)>],
+) -> io::Result<()> {
+    #[cfg(all(target_os = "linux", feature = "batched-io"))]
+    {
+        let mut bufs = vec![[0u8; BUFFER_SIZE]; RECV_BATCH_SIZE];
+        loop {
+            for (i, (len, addr)) in crate::fanout::recv_batch(sock, &mut bufs)
+                .await?
+                .into_iter()
+                .enumerate()
+            {
+                dispatch(queues, &bufs[i][..len], addr).await?;
+            }
+        }
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "batched-io")))]
+    {
+        let mut buf = [0u8; BUFFER_SIZE];
+        loop {
+            let (len, addr) = sock.recv_from(&mut buf).await?;
+            dispatch(queues, &buf[..len], addr).await?;
+        }
+    }
+}
+
+async fn dispatch(
+    queues: &[mpsc::Sender<(Vec<u8>, SocketAddr)>],
+    data: &[u8],
+    addr: SocketAddr,
+) -> io::Result<()> {
+    let queue = &queues[worker_index(addr, queues.len())];
+    // ワーカーが詰まっている場合はここで待ち、カーネルのバッファに溜める
+    queue
+        .send((data.to_vec(), addr))
+        .await
+        .map_err(|_| io::Error::other("worker task stopped"))
+}
+
+async fn worker_loop(
+    mut rx: mpsc::Receiver<(Vec<u8>, SocketAddr)>,
+    sock: Arc<UdpSocket>,
+    state: Arc<ServerState>,
+) {
+    while let Some((data, addr)) = rx.recv().await {
+        if let Err(e) = process_datagram(&sock, &data, addr, &state).await {
+            eprintln!("failed to process datagram from {addr}: {e}");
+        }
+    }
+}
+
+// 同じ送信元は常に同じワーカーに割り当てる
+fn worker_index(addr: SocketAddr, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    addr.hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
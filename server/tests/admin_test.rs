@@ -0,0 +1,194 @@
+//! Admin console integration test.
+//!
+//! 一時ディレクトリの Unix ソケットで管理コンソールを起動し、
+//! `send_command` 経由で各コマンドの動作を確認する。
+#![cfg(unix)]
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use protocol::MessageProtocol;
+use server::{
+    BUFFER_SIZE,
+    admin::{AdminCommand, run_admin, send_command},
+    client_manager::{ClientInfo, ClientManager, Role},
+    process_datagram,
+    state::{SYSTEM_USER_NAME, ServerState},
+};
+use tokio::{
+    net::UdpSocket,
+    time::{Instant, sleep, timeout},
+};
+
+async fn recv_message(sock: &UdpSocket) -> MessageProtocol {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    MessageProtocol::deserialize(&buf[..len]).unwrap()
+}
+
+// テストごとに別のソケットパスを使う
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("online-chat-{name}-{}.sock", std::process::id()))
+}
+
+async fn start_admin(name: &str) -> (PathBuf, Arc<ServerState>, Arc<UdpSocket>) {
+    let path = socket_path(name);
+    let manager = Arc::new(ClientManager::new(Duration::from_secs(30)));
+    let state = Arc::new(ServerState::new(manager));
+    let sock = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    tokio::spawn(run_admin(
+        path.clone(),
+        Arc::clone(&state),
+        Arc::clone(&sock),
+    ));
+    // リスナーの準備ができるまで待つ
+    for _ in 0..50 {
+        if path.exists() {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    (path, state, sock)
+}
+
+async fn join(state: &ServerState, user_name: &str) -> UdpSocket {
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    state.client_manager.upsert_client(ClientInfo {
+        user_name: user_name.into(),
+        socket_addr: client.local_addr().unwrap(),
+        last_message_time: Instant::now(),
+        role: Role::Member,
+        ingress: None,
+    });
+    client
+}
+
+#[test]
+fn parses_commands() {
+    assert_eq!(AdminCommand::parse("list"), Ok(AdminCommand::List));
+    assert_eq!(
+        AdminCommand::parse("kick alice"),
+        Ok(AdminCommand::Kick("alice".into()))
+    );
+    assert_eq!(
+        AdminCommand::parse("notice server restarts at 5pm"),
+        Ok(AdminCommand::Notice("server restarts at 5pm".into()))
+    );
+    assert_eq!(
+        AdminCommand::parse("timeout 60"),
+        Ok(AdminCommand::Timeout(Duration::from_secs(60)))
+    );
+    assert_eq!(
+        AdminCommand::parse("receipts #12"),
+        Ok(AdminCommand::Receipts(12))
+    );
+    assert!(AdminCommand::parse("ban-ip not-an-ip").is_err());
+    assert!(AdminCommand::parse("timeout 0").is_err());
+    assert!(AdminCommand::parse("kick").is_err());
+    assert!(AdminCommand::parse("reboot").is_err());
+}
+
+#[tokio::test]
+async fn lists_and_kicks_clients() {
+    let (path, state, _sock) = start_admin("kick").await;
+    let alice = join(&state, "alice").await;
+    let _bob = join(&state, "bob").await;
+
+    let listing = send_command(&path, "list").await.unwrap();
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines.len(), 2, "{listing}");
+    assert!(lines[0].starts_with("alice 127.0.0.1:"), "{listing}");
+    assert!(lines[1].starts_with("bob "), "{listing}");
+
+    // キックされたクライアントは削除され、本人に通知が届く
+    send_command(&path, "kick alice").await.unwrap();
+    assert!(!state.client_manager.clients_table.contains_key("alice"));
+    let notice = recv_message(&alice).await;
+    assert_eq!(notice.user_name, SYSTEM_USER_NAME);
+    assert!(notice.body.contains("kicked"));
+
+    // 存在しないユーザーのキックはエラー
+    let err = send_command(&path, "kick alice").await.unwrap_err();
+    assert!(err.to_string().contains("not connected"), "{err}");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn banned_users_are_ignored() {
+    let (path, state, sock) = start_admin("ban").await;
+    let _alice = join(&state, "alice").await;
+
+    send_command(&path, "ban alice").await.unwrap();
+    assert!(
+        state
+            .moderation
+            .is_banned("alice", "192.0.2.1".parse().unwrap())
+    );
+
+    // BAN されたユーザーのメッセージは登録も中継もされない
+    let frame = MessageProtocol::new("alice", "let me back in")
+        .serialize()
+        .unwrap();
+    process_datagram(&sock, &frame, "127.0.0.1:9999".parse().unwrap(), &state)
+        .await
+        .unwrap();
+    assert_eq!(state.client_manager.active_client_count(), 0);
+
+    send_command(&path, "unban alice").await.unwrap();
+    assert!(send_command(&path, "unban alice").await.is_err());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn broadcasts_notice_and_changes_timeout() {
+    let (path, state, _sock) = start_admin("notice").await;
+    let alice = join(&state, "alice").await;
+    let bob = join(&state, "bob").await;
+
+    send_command(&path, "notice maintenance at 5pm")
+        .await
+        .unwrap();
+    for client in [&alice, &bob] {
+        let notice = recv_message(client).await;
+        assert_eq!(notice.user_name, SYSTEM_USER_NAME);
+        assert_eq!(notice.body, "maintenance at 5pm");
+        assert!(notice.id > 0, "通知にもメッセージ ID が付与されるべき");
+    }
+
+    send_command(&path, "timeout 90").await.unwrap();
+    assert_eq!(
+        state.client_manager.timeout_duration(),
+        Duration::from_secs(90)
+    );
+
+    let stats = send_command(&path, "stats").await.unwrap();
+    assert!(stats.contains("clients=2"), "{stats}");
+    assert!(stats.contains("timeout_secs=90"), "{stats}");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn keeps_a_live_console_and_replaces_a_stale_one() {
+    // ❶ 動いているコンソールは二つ目のサーバに奪われない
+    let (path, state, sock) = start_admin("exclusive").await;
+    let err = run_admin(&path, Arc::clone(&state), Arc::clone(&sock))
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+    assert!(send_command(&path, "list").await.is_ok());
+
+    // ❷ 誰も待っていないソケットファイルは置き換える
+    let stale = socket_path("stale");
+    drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+    assert!(stale.exists());
+    tokio::spawn(run_admin(stale.clone(), state, sock));
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while send_command(&stale, "list").await.is_err() {
+        assert!(Instant::now() < deadline, "stale socket was not replaced");
+        sleep(Duration::from_millis(10)).await;
+    }
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&stale);
+}
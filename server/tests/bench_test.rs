@@ -0,0 +1,73 @@
+//! Load generator integration test.
+//!
+//! 小さな負荷をエフェメラルポートのサーバにかけ、全配送の遅延が測れて損失が
+//! ないこと、レポートが JSON で往復できること、不正な設定を断ることを確認する。
+
+use std::time::Duration;
+
+use server::bench::{self, BenchConfig, BenchReport, percentile};
+use test_support::harness::TestServer;
+
+#[test]
+fn percentiles_use_nearest_rank() {
+    let samples: Vec<u64> = (1..=100).collect();
+    assert_eq!(percentile(&samples, 50.0), 50);
+    assert_eq!(percentile(&samples, 99.9), 100);
+    assert_eq!(percentile(&samples, 0.0), 1);
+    assert_eq!(percentile(&[], 50.0), 0);
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+    let config = BenchConfig::default();
+    for bad in [
+        BenchConfig {
+            clients: 0,
+            ..config.clone()
+        },
+        BenchConfig {
+            senders: config.clients + 1,
+            ..config.clone()
+        },
+        BenchConfig {
+            rate: 0.0,
+            ..config.clone()
+        },
+        BenchConfig {
+            size: config.max_size() + 1,
+            ..config.clone()
+        },
+    ] {
+        assert!(bad.validate().is_err(), "{bad:?}");
+    }
+    assert!(config.validate().is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn measures_every_delivery() {
+    let server = TestServer::start().await.unwrap();
+    let config = BenchConfig {
+        server: server.addr(),
+        clients: 20,
+        senders: 2,
+        rate: 50.0,
+        size: 300,
+        duration: Duration::from_millis(200),
+        drain: Duration::from_millis(500),
+    };
+
+    // ❶ 全員が参加し、送った分が全員に届く
+    let report = bench::run(&config).await.unwrap();
+    assert_eq!(report.joined, 20);
+    assert!(report.sent > 0);
+    assert_eq!(report.expected, report.sent * 20);
+    assert_eq!(report.received, report.expected, "{report}");
+    assert_eq!(report.loss, 0.0);
+    let latency = report.latency_ms;
+    assert!(latency.p50 <= latency.p99 && latency.p99 <= latency.max);
+
+    // ❷ JSON にしても同じ結果に戻る
+    let json = serde_json::to_string(&report).unwrap();
+    assert!(json.contains("\"latency_ms\""), "{json}");
+    assert_eq!(serde_json::from_str::<BenchReport>(&json).unwrap(), report);
+}
@@ -1,12 +1,15 @@
 #[cfg(test)]
 mod client_manager_test {
-    use server::client_manager::{ClientInfo, ClientManager};
+    use server::client_manager::{ClientEvent, ClientInfo, ClientManager, Role};
     use std::{
         net::{IpAddr, Ipv4Addr, SocketAddr},
         sync::Arc,
         time::Duration,
     };
-    use tokio::time::{Instant, sleep};
+    use tokio::{
+        task::yield_now,
+        time::{Instant, advance, sleep},
+    };
 
     // テスト: ClientManagerの初期化
     // 目的: タイムアウトが正しく設定され、クライアントテーブルが空であることを確認する
@@ -18,7 +21,8 @@ mod client_manager_test {
 
         // タイムアウトが正しく設定されていることを検証
         assert_eq!(
-            manager.timeout_duration, timeout,
+            manager.timeout_duration(),
+            timeout,
             "タイムアウト時間が設定された値と一致するはず"
         );
         // 初期状態ではクライアントテーブルが空であることを検証
@@ -28,6 +32,19 @@ mod client_manager_test {
         );
     }
 
+    // テスト: 1 ミリ秒未満のタイムアウト
+    // 目的: ミリ秒への変換で 0 に切り捨てられず、最低 1 ミリ秒になることを確認する
+    #[test]
+    fn sub_millisecond_timeouts_are_clamped() {
+        let manager = ClientManager::new(Duration::from_micros(500));
+        assert_eq!(manager.timeout_duration(), Duration::from_millis(1));
+
+        manager.set_timeout_duration(Duration::ZERO);
+        assert_eq!(manager.timeout_duration(), Duration::from_millis(1));
+        manager.set_timeout_duration(Duration::from_micros(2_500));
+        assert_eq!(manager.timeout_duration(), Duration::from_millis(2));
+    }
+
     // テスト: 単一クライアント追加
     // 目的: 単一のクライアントを追加して、カウントと格納内容を検証する
     #[tokio::test]
@@ -39,6 +56,8 @@ mod client_manager_test {
             user_name: "alice".to_string(),
             socket_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080),
             last_message_time: Instant::now(),
+            role: Role::Member,
+            ingress: None,
         };
 
         // クライアントを追加
@@ -76,12 +95,16 @@ mod client_manager_test {
             user_name: "alice".to_string(),
             socket_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8081),
             last_message_time: Instant::now(),
+            role: Role::Member,
+            ingress: None,
         };
         // 2番目のクライアントを作成
         let client2 = ClientInfo {
             user_name: "bob".to_string(),
             socket_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8082),
             last_message_time: Instant::now(),
+            role: Role::Member,
+            ingress: None,
         };
 
         // それぞれのクライアントを追加
@@ -117,6 +140,8 @@ mod client_manager_test {
             user_name: "alice".to_string(),
             socket_addr: "127.0.0.1:8080".parse().unwrap(),
             last_message_time: initial_time,
+            role: Role::Member,
+            ingress: None,
         };
 
         // クライアントを追加
@@ -178,6 +203,8 @@ mod client_manager_test {
             user_name: "alice".to_string(),
             socket_addr: "127.0.0.1:8080".parse().unwrap(),
             last_message_time: inactive_time,
+            role: Role::Member,
+            ingress: None,
         };
 
         // クライアントを追加
@@ -219,6 +246,8 @@ mod client_manager_test {
                 user_name: format!("user{}", i),
                 socket_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8000 + i),
                 last_message_time: Instant::now(),
+                role: Role::Member,
+                ingress: None,
             };
             handles.push(tokio::spawn(async move {
                 mgr.upsert_client(client);
@@ -240,7 +269,7 @@ mod client_manager_test {
 
     // テスト: バックグラウンドクリーンアップタスクの動作
     // 目的: 指定したインターバルで自動的に非アクティブクライアントが削除されるかを確認する
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_background_cleanup_task() {
         // タイムアウトを1秒に設定
         let manager = Arc::new(ClientManager::new_with_background_cleanup(
@@ -252,6 +281,8 @@ mod client_manager_test {
             user_name: "alice".to_string(),
             socket_addr: "127.0.0.1:8080".parse().unwrap(),
             last_message_time: Instant::now() - Duration::from_secs(5),
+            role: Role::Member,
+            ingress: None,
         };
 
         // クライアントを追加（バックグラウンドタスクが動作中と想定）
@@ -267,4 +298,254 @@ mod client_manager_test {
             "バックグラウンドクリーンアップ後にアクティブクライアントは0であるべき"
         );
     }
+
+    // テスト: 新規クライアント追加時の Joined イベント
+    // 目的: 初めて登録されたときだけ Joined が通知され、更新では通知されないことを確認する
+    #[tokio::test]
+    async fn test_joined_event_only_for_new_clients() {
+        let manager = ClientManager::new(Duration::from_secs(10));
+        let mut events = manager.subscribe();
+
+        let client = ClientInfo {
+            user_name: "alice".to_string(),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            last_message_time: Instant::now(),
+            role: Role::Member,
+            ingress: None,
+        };
+
+        // 1回目は新規追加、2回目は既存クライアントの更新
+        manager.upsert_client(client.clone());
+        manager.upsert_client(client.clone());
+
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ClientEvent::Joined(client),
+            "新規追加時に Joined が通知されるべき"
+        );
+        assert!(
+            events.try_recv().is_err(),
+            "既存クライアントの更新ではイベントが通知されないはず"
+        );
+    }
+
+    // テスト: 明示的な削除時の Left イベント
+    // 目的: remove_client が削除したクライアントを返し、Left を通知することを確認する
+    #[tokio::test]
+    async fn test_remove_client_publishes_left_event() {
+        let manager = ClientManager::new(Duration::from_secs(10));
+        let client = ClientInfo {
+            user_name: "alice".to_string(),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            last_message_time: Instant::now(),
+            role: Role::Member,
+            ingress: None,
+        };
+        manager.upsert_client(client.clone());
+        let mut events = manager.subscribe();
+
+        // 削除されたクライアントが返ることを検証
+        let removed = manager.remove_client("alice");
+        assert_eq!(
+            removed,
+            Some(client.clone()),
+            "削除したクライアントが返るべき"
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ClientEvent::Left(client),
+            "削除時に Left が通知されるべき"
+        );
+
+        // 存在しないクライアントの削除は None を返し、通知もしない
+        assert!(manager.remove_client("alice").is_none());
+        assert!(events.try_recv().is_err());
+    }
+
+    // テスト: クリーンアップ時の TimedOut イベント
+    // 目的: cleanup_inactive_clients が削除したクライアントを返し、TimedOut を通知することを確認する
+    #[tokio::test]
+    async fn test_cleanup_reports_evicted_clients() {
+        let manager = ClientManager::new(Duration::from_secs(10));
+
+        // タイムアウト済みのクライアントとアクティブなクライアントを用意
+        let stale = ClientInfo {
+            user_name: "alice".to_string(),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            last_message_time: Instant::now() - Duration::from_secs(20),
+            role: Role::Member,
+            ingress: None,
+        };
+        let fresh = ClientInfo {
+            user_name: "bob".to_string(),
+            socket_addr: "127.0.0.1:8081".parse().unwrap(),
+            last_message_time: Instant::now(),
+            role: Role::Member,
+            ingress: None,
+        };
+        manager.upsert_client(stale.clone());
+        manager.upsert_client(fresh);
+        let mut events = manager.subscribe();
+
+        let evicted = manager.cleanup_inactive_clients();
+
+        // タイムアウトしたクライアントだけが返されることを検証
+        assert_eq!(evicted, vec![stale.clone()], "alice だけが削除されるべき");
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ClientEvent::TimedOut(stale),
+            "削除時に TimedOut が通知されるべき"
+        );
+        assert!(events.try_recv().is_err(), "bob の通知はないはず");
+    }
+
+    // テスト: バックグラウンドクリーンアップの TimedOut イベント
+    // 目的: バックグラウンドタスクによる削除も購読者に通知されることを確認する
+    #[tokio::test(start_paused = true)]
+    async fn test_background_cleanup_publishes_timed_out_event() {
+        let manager = ClientManager::new_with_background_cleanup(Duration::from_secs(1));
+        let mut events = manager.subscribe();
+
+        let client = ClientInfo {
+            user_name: "alice".to_string(),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            last_message_time: Instant::now() - Duration::from_secs(5),
+            role: Role::Member,
+            ingress: None,
+        };
+        manager.upsert_client(client.clone());
+
+        // Joined の後に TimedOut が届くことを検証
+        assert_eq!(
+            events.recv().await.unwrap(),
+            ClientEvent::Joined(client.clone())
+        );
+        let event = tokio::time::timeout(Duration::from_secs(3), events.recv())
+            .await
+            .expect("TimedOut イベントが届くべき")
+            .unwrap();
+        assert_eq!(event, ClientEvent::TimedOut(client));
+    }
+
+    // テスト: 活動更新後のクリーンアップ
+    // 目的: 古い期限エントリが残っていても、更新済みのクライアントは削除されないことを確認する
+    #[tokio::test]
+    async fn test_cleanup_skips_clients_with_refreshed_activity() {
+        let manager = ClientManager::new(Duration::from_secs(10));
+
+        // タイムアウト済みの時刻で登録した後、活動を更新する
+        let client = ClientInfo {
+            user_name: "alice".to_string(),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            last_message_time: Instant::now() - Duration::from_secs(20),
+            role: Role::Member,
+            ingress: None,
+        };
+        manager.upsert_client(client);
+        manager.update_client_activity("alice").unwrap();
+
+        let evicted = manager.cleanup_inactive_clients();
+
+        // 最新の活動時刻で判定されるため削除されないことを検証
+        assert!(
+            evicted.is_empty(),
+            "活動を更新したクライアントは削除されないはず"
+        );
+        assert!(
+            manager.clients_table.contains_key("alice"),
+            "alice はテーブルに残っているべき"
+        );
+    }
+
+    // 止めた時計の上でバックグラウンドタスクに順番を回す（時計は進めない）
+    async fn settle() {
+        for _ in 0..10 {
+            yield_now().await;
+        }
+    }
+
+    fn client_at(user_name: &str, last_message_time: Instant) -> ClientInfo {
+        ClientInfo {
+            user_name: user_name.to_string(),
+            socket_addr: "127.0.0.1:8080".parse().unwrap(),
+            last_message_time,
+            role: Role::Member,
+            ingress: None,
+        }
+    }
+
+    // テスト: タイムアウトの境界
+    // 目的: タイムアウトちょうどで削除され、その 1ms 前には残ることを確認する
+    #[tokio::test(start_paused = true)]
+    async fn test_cleanup_boundary_is_exact() {
+        let manager = ClientManager::new(Duration::from_secs(10));
+        manager.upsert_client(client_at("alice", Instant::now()));
+
+        advance(Duration::from_secs(10) - Duration::from_millis(1)).await;
+        assert!(
+            manager.cleanup_inactive_clients().is_empty(),
+            "タイムアウトの 1ms 前には削除されないはず"
+        );
+
+        advance(Duration::from_millis(1)).await;
+        assert_eq!(
+            manager.cleanup_inactive_clients().len(),
+            1,
+            "タイムアウトちょうどで削除されるべき"
+        );
+    }
+
+    // テスト: バックグラウンドクリーンアップの時刻
+    // 目的: 定期実行を待たず、期限ちょうどに削除されることを確認する
+    #[tokio::test(start_paused = true)]
+    async fn test_background_cleanup_evicts_at_deadline() {
+        let manager = ClientManager::new_with_background_cleanup(Duration::from_secs(10));
+        let mut events = manager.subscribe();
+        // 秒の途中で参加させ、1 秒ごとの見回りでは期限ちょうどにならないようにする
+        advance(Duration::from_millis(500)).await;
+        let client = client_at("alice", Instant::now());
+        manager.upsert_client(client.clone());
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ClientEvent::Joined(client.clone())
+        );
+
+        advance(Duration::from_secs(10) - Duration::from_millis(1)).await;
+        settle().await;
+        assert!(events.try_recv().is_err(), "期限前には通知されないはず");
+        assert_eq!(manager.active_client_count(), 1);
+
+        advance(Duration::from_millis(1)).await;
+        settle().await;
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ClientEvent::TimedOut(client),
+            "期限ちょうどに TimedOut が通知されるべき"
+        );
+        assert_eq!(manager.active_client_count(), 0);
+    }
+
+    // テスト: 活動更新とタイムアウト変更への追従
+    // 目的: 活動があれば期限が延び、タイムアウトを縮めればすぐに反映されることを確認する
+    #[tokio::test(start_paused = true)]
+    async fn test_background_cleanup_follows_activity_and_timeout() {
+        let manager = ClientManager::new_with_background_cleanup(Duration::from_secs(10));
+        manager.upsert_client(client_at("alice", Instant::now()));
+
+        // 参加から 12 秒経っても、6 秒前に活動していれば残る
+        advance(Duration::from_secs(6)).await;
+        manager.update_client_activity("alice").unwrap();
+        advance(Duration::from_secs(6)).await;
+        settle().await;
+        assert_eq!(manager.active_client_count(), 1, "活動で期限が延びるべき");
+
+        // タイムアウトを 5 秒に縮めると、次の定期実行を待たずに削除される
+        manager.set_timeout_duration(Duration::from_secs(5));
+        settle().await;
+        assert_eq!(
+            manager.active_client_count(),
+            0,
+            "縮めたタイムアウトがすぐに反映されるべき"
+        );
+    }
 }
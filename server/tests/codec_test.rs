@@ -0,0 +1,186 @@
+//! Codec negotiation integration test.
+//!
+//! 参加済みのクライアントだけが方式を切り替えられ、確認は wire 形式で返ること、
+//! 切り替えた後は送受信ともその方式になり、他のクライアントには影響しない
+//! ことを確認する。
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::{
+    MessageKind, MessageProtocol, ProtocolError,
+    codec::{Codec, CodecId, JsonCodec, WireCodec},
+};
+use server::{BUFFER_SIZE, client_manager::ClientManager, process_datagram, state::ServerState};
+use tokio::{net::UdpSocket, time::timeout};
+
+async fn recv_frame(sock: &UdpSocket) -> Vec<u8> {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    buf[..len].to_vec()
+}
+
+async fn assert_silent(sock: &UdpSocket) {
+    let mut buf = [0u8; BUFFER_SIZE];
+    assert!(
+        timeout(Duration::from_millis(100), sock.recv_from(&mut buf))
+            .await
+            .is_err()
+    );
+}
+
+async fn send(
+    state: &Arc<ServerState>,
+    server: &UdpSocket,
+    from: &UdpSocket,
+    codec: &dyn Codec,
+    message: MessageProtocol,
+) {
+    let frame = codec.encode(&message).unwrap();
+    process_datagram(server, &frame, from.local_addr().unwrap(), state)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn joined_clients_switch_codecs() {
+    let state = Arc::new(ServerState::new(Arc::new(ClientManager::new(
+        Duration::from_secs(30),
+    ))));
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bob = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let carol = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    for (sock, name) in [(&alice, "alice"), (&bob, "bob")] {
+        send(
+            &state,
+            &server,
+            sock,
+            &WireCodec,
+            MessageProtocol::new(name, "joined"),
+        )
+        .await;
+    }
+    for sock in [&alice, &alice, &bob] {
+        recv_frame(sock).await;
+    }
+
+    // ❶ 未参加のアドレスからの切り替えは無視する
+    send(
+        &state,
+        &server,
+        &carol,
+        &WireCodec,
+        MessageProtocol::codec("carol", CodecId::Json),
+    )
+    .await;
+    assert_silent(&carol).await;
+    assert!(state.codecs.is_empty());
+
+    // ❷ 確認は wire 形式で届き、以後は JSON になる
+    send(
+        &state,
+        &server,
+        &bob,
+        &WireCodec,
+        MessageProtocol::codec("bob", CodecId::Json),
+    )
+    .await;
+    let reply = WireCodec.decode(&recv_frame(&bob).await).unwrap();
+    assert_eq!(reply.kind, MessageKind::Codec);
+    assert_eq!(reply.codec_id(), Some(CodecId::Json));
+    assert_eq!(
+        state.codecs.codec_for(bob.local_addr().unwrap()),
+        CodecId::Json
+    );
+
+    // ❸ JSON の発言は wire のクライアントには wire で、本人には JSON で届く
+    send(
+        &state,
+        &server,
+        &bob,
+        &JsonCodec,
+        MessageProtocol::new("bob", "hi"),
+    )
+    .await;
+    let to_alice = WireCodec.decode(&recv_frame(&alice).await).unwrap();
+    assert_eq!(
+        (to_alice.user_name.as_str(), to_alice.body.as_str()),
+        ("bob", "hi")
+    );
+    let to_bob = JsonCodec.decode(&recv_frame(&bob).await).unwrap();
+    assert_eq!(to_bob, to_alice);
+
+    // ❹ 知らない名前では切り替わらず、いまの方式が返る
+    send(
+        &state,
+        &server,
+        &bob,
+        &JsonCodec,
+        MessageProtocol {
+            body: "xml".into(),
+            ..MessageProtocol::codec("bob", CodecId::Json)
+        },
+    )
+    .await;
+    let reply = WireCodec.decode(&recv_frame(&bob).await).unwrap();
+    assert_eq!(reply.codec_id(), Some(CodecId::Json));
+
+    // ❺ wire に戻すと覚えていた方式を捨てる
+    send(
+        &state,
+        &server,
+        &bob,
+        &WireCodec,
+        MessageProtocol::codec("bob", CodecId::Wire),
+    )
+    .await;
+    let reply = WireCodec.decode(&recv_frame(&bob).await).unwrap();
+    assert_eq!(reply.codec_id(), Some(CodecId::Wire));
+    assert!(state.codecs.is_empty());
+}
+
+#[tokio::test]
+async fn negotiated_codecs_keep_wire_limits() {
+    let state = Arc::new(ServerState::new(Arc::new(ClientManager::new(
+        Duration::from_secs(30),
+    ))));
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bob = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    for (sock, name) in [(&alice, "alice"), (&bob, "bob")] {
+        let message = MessageProtocol::new(name, "joined");
+        send(&state, &server, sock, &WireCodec, message).await;
+    }
+    for sock in [&alice, &alice, &bob] {
+        recv_frame(sock).await;
+    }
+    let codec = MessageProtocol::codec("bob", CodecId::Json);
+    send(&state, &server, &bob, &WireCodec, codec).await;
+    recv_frame(&bob).await;
+
+    // ❶ 255 バイトを超える名前の JSON は解析の時点で断る
+    let frame = JsonCodec
+        .encode(&MessageProtocol::new("bob", "hi"))
+        .unwrap();
+    let long_name = "b".repeat(300);
+    let frame = String::from_utf8(frame)
+        .unwrap()
+        .replace(r#""bob""#, &format!("{long_name:?}"));
+    let bob_addr = bob.local_addr().unwrap();
+    assert_eq!(
+        state.codecs.decode(frame.as_bytes(), bob_addr),
+        Err(ProtocolError::UsernameTooLong(300))
+    );
+
+    // ❷ 送っても参加者にも中継にも現れない
+    process_datagram(&server, frame.as_bytes(), bob_addr, &state)
+        .await
+        .unwrap();
+    assert!(!state.client_manager.clients_table.contains_key(&long_name));
+    assert_eq!(state.client_manager.active_client_count(), 2);
+    assert_silent(&alice).await;
+}
@@ -0,0 +1,73 @@
+//! Compressed body relay integration test.
+//!
+//! 受信バッファより長い貼り付けが圧縮されたまま届き、サーバが展開して
+//! 記録したうえで、再び圧縮して中継することを確認する。
+//! 圧縮の効かない本文も、フレームの上限までは実際のソケット越しに届く。
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::{COMPRESSED_FLAG, MAX_BUFFER_SIZE, MessageProtocol};
+use server::{BUFFER_SIZE, client_manager::ClientManager, process_datagram, state::ServerState};
+use test_support::harness::TestServer;
+use tokio::{net::UdpSocket, time::timeout};
+
+// 圧縮してもほとんど縮まない本文
+fn incompressible(len: usize) -> String {
+    let mut seed = 0x2545_f491_u32;
+    (0..len)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            char::from(b'!' + (seed >> 16) as u8 % 90)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn long_pastes_are_relayed_compressed() {
+    let state = Arc::new(ServerState::new(Arc::new(ClientManager::new(
+        Duration::from_secs(30),
+    ))));
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    // ❶ 展開すると受信バッファに収まらない長さの本文
+    let paste = MessageProtocol::new("alice", "let x = 1;\n".repeat(600));
+    assert!(paste.body.len() > BUFFER_SIZE);
+    let frame = paste.serialize().unwrap();
+    process_datagram(&server, &frame, alice.local_addr().unwrap(), &state)
+        .await
+        .unwrap();
+
+    // ❷ 中継されたフレームも圧縮されており、元の本文に戻る
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), alice.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    assert_ne!(buf[16] & COMPRESSED_FLAG, 0);
+    let relayed = MessageProtocol::deserialize(&buf[..len]).unwrap();
+    assert_eq!(relayed.body, paste.body);
+    assert_eq!(state.history.get(relayed.id).unwrap().body, paste.body);
+}
+
+#[tokio::test]
+async fn incompressible_bodies_fit_the_receive_buffer() {
+    let server = TestServer::start().await.unwrap();
+    let [alice, bob] = server.join(["alice", "bob"]).await.unwrap();
+
+    // ❶ 圧縮しても 1 KB を大きく超えるが、フレームの上限には収まる
+    let body = incompressible(3_000);
+    let frame = MessageProtocol::new("alice", body.as_str())
+        .serialize()
+        .unwrap();
+    assert!(
+        (2_048..=MAX_BUFFER_SIZE).contains(&frame.len()),
+        "{} bytes",
+        frame.len()
+    );
+
+    // ❷ サーバを往復しても欠けずに届く
+    alice.say(&body).await;
+    alice.expect_from("alice", &body).await;
+    bob.expect_from("alice", &body).await;
+}
@@ -0,0 +1,211 @@
+//! Direct message / offline queue integration test.
+//!
+//! オフラインの宛先への DM がキューに入り、宛先が戻ったときに配送され、
+//! 送信者に queued → delivered の順で状況が届くこと、キューが宛先の数でも
+//! 期限でも上限を守ることを確認する。
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::{DeliveryStatus, MessageKind, MessageProtocol};
+use server::{
+    BUFFER_SIZE,
+    client_manager::ClientManager,
+    direct::{DirectQueue, QueueFull},
+    process_datagram,
+    state::ServerState,
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+async fn recv_message(sock: &UdpSocket) -> MessageProtocol {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    MessageProtocol::deserialize(&buf[..len]).unwrap()
+}
+
+async fn assert_silent(sock: &UdpSocket) {
+    let mut buf = [0u8; BUFFER_SIZE];
+    assert!(
+        timeout(Duration::from_millis(100), sock.recv_from(&mut buf))
+            .await
+            .is_err()
+    );
+}
+
+async fn send(
+    state: &Arc<ServerState>,
+    server: &UdpSocket,
+    from: &UdpSocket,
+    message: MessageProtocol,
+) {
+    let frame = message.serialize().unwrap();
+    process_datagram(server, &frame, from.local_addr().unwrap(), state)
+        .await
+        .unwrap();
+}
+
+fn queued(id: u64, recipient: &str) -> MessageProtocol {
+    MessageProtocol {
+        id,
+        ..MessageProtocol::direct("alice", recipient, format!("message {id}"))
+    }
+}
+
+#[test]
+fn queue_is_bounded_per_recipient() {
+    let queue = DirectQueue::new(2, Duration::from_secs(60));
+    queue.enqueue(queued(1, "bob")).unwrap();
+    queue.enqueue(queued(2, "bob")).unwrap();
+    assert_eq!(
+        queue.enqueue(queued(3, "bob")),
+        Err(QueueFull::Recipient("bob".into()))
+    );
+    // 宛先ごとに別々に数える
+    queue.enqueue(queued(4, "carol")).unwrap();
+    assert_eq!(queue.len(), 3);
+
+    let drained = queue.drain("bob");
+    let ids: Vec<u64> = drained.pending.iter().map(|m| m.id).collect();
+    assert_eq!(ids, vec![1, 2], "届いた順に配送する");
+    assert!(drained.expired.is_empty());
+    assert!(queue.drain("bob").pending.is_empty());
+}
+
+#[test]
+fn expired_messages_are_not_delivered() {
+    let queue = DirectQueue::new(10, Duration::ZERO);
+    queue.enqueue(queued(1, "bob")).unwrap();
+    // 次に触れたときに期限切れとして取り除かれる
+    let expired = queue.enqueue(queued(2, "bob")).unwrap();
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].id, 1);
+
+    let drained = queue.drain("bob");
+    assert!(drained.pending.is_empty());
+    assert_eq!(drained.expired.len(), 1);
+    assert!(queue.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn sweep_forgets_expired_recipients() {
+    let queue = DirectQueue::new(10, Duration::from_secs(60));
+    for (id, recipient) in [(1, "bob"), (2, "carol"), (3, "carol")] {
+        queue.enqueue(queued(id, recipient)).unwrap();
+    }
+    assert_eq!(queue.recipient_count(), 2);
+
+    assert!(queue.sweep().is_empty());
+
+    // 誰も触れない宛先も掃除で消える
+    tokio::time::advance(Duration::from_secs(60)).await;
+    let mut ids: Vec<u64> = queue.sweep().iter().map(|m| m.id).collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert_eq!(queue.recipient_count(), 0);
+}
+
+#[tokio::test]
+async fn many_recipients_stay_within_the_bound() {
+    let mut state = ServerState::new(Arc::new(ClientManager::new(Duration::from_secs(30))));
+    state.direct_queue = DirectQueue::new(10, Duration::from_secs(60)).with_max_recipients(5);
+    let state = Arc::new(state);
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    send(&state, &server, &alice, MessageProtocol::new("alice", "hi")).await;
+    recv_message(&alice).await;
+
+    // ❶ 上限までは宛先ごとにキューに入る
+    for n in 0..5 {
+        let message = MessageProtocol::direct("alice", format!("ghost{n}"), "hello?");
+        send(&state, &server, &alice, message).await;
+        recv_message(&alice).await;
+        let status = recv_message(&alice).await;
+        assert_eq!(status.delivery_status(), Some(DeliveryStatus::Queued));
+    }
+
+    // ❷ それ以上の宛先は断られ、キューは増えない
+    for n in 5..50 {
+        let message = MessageProtocol::direct("alice", format!("ghost{n}"), "hello?");
+        send(&state, &server, &alice, message).await;
+        recv_message(&alice).await;
+        let notice = recv_message(&alice).await;
+        assert_eq!(notice.body, "too many users have undelivered messages");
+    }
+    assert_eq!(state.direct_queue.recipient_count(), 5);
+    assert_eq!(state.direct_queue.len(), 5);
+
+    // ❸ 既にキューのある宛先にはまだ送れる
+    let message = MessageProtocol::direct("alice", "ghost0", "still there?");
+    send(&state, &server, &alice, message).await;
+    recv_message(&alice).await;
+    let status = recv_message(&alice).await;
+    assert_eq!(status.delivery_status(), Some(DeliveryStatus::Queued));
+    assert_eq!(state.direct_queue.len(), 6);
+}
+
+#[tokio::test]
+async fn direct_messages_wait_for_offline_recipients() {
+    let state = Arc::new(ServerState::new(Arc::new(ClientManager::new(
+        Duration::from_secs(30),
+    ))));
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bob = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let carol = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    for (sock, name) in [(&alice, "alice"), (&bob, "bob"), (&carol, "carol")] {
+        send(&state, &server, sock, MessageProtocol::new(name, "joined")).await;
+    }
+    for sock in [&alice, &alice, &alice, &bob, &bob, &carol] {
+        recv_message(sock).await;
+    }
+
+    // ❶ 接続中の宛先にはすぐ届き、他の参加者には届かない
+    send(
+        &state,
+        &server,
+        &alice,
+        MessageProtocol::direct("alice", "bob", "psst"),
+    )
+    .await;
+    let direct = recv_message(&bob).await;
+    assert_eq!(direct.kind, MessageKind::Direct);
+    assert_eq!(direct.body, "psst");
+    assert_ne!(direct.id, 0);
+    assert_eq!(recv_message(&alice).await, direct, "送信者にはエコーが届く");
+    let status = recv_message(&alice).await;
+    assert_eq!(status.target_id, direct.id);
+    assert_eq!(status.delivery_status(), Some(DeliveryStatus::Delivered));
+    assert_silent(&carol).await;
+    assert!(
+        state.history.get(direct.id).is_none(),
+        "DM は部屋の履歴に残さない"
+    );
+
+    // ❷ タイムアウトした宛先への DM はキューに入る
+    state.client_manager.remove_client("bob");
+    send(
+        &state,
+        &server,
+        &alice,
+        MessageProtocol::direct("alice", "bob", "call me"),
+    )
+    .await;
+    let queued = recv_message(&alice).await;
+    let status = recv_message(&alice).await;
+    assert_eq!(status.target_id, queued.id);
+    assert_eq!(status.delivery_status(), Some(DeliveryStatus::Queued));
+    assert_eq!(state.direct_queue.len(), 1);
+    assert_silent(&bob).await;
+
+    // ❸ 宛先が戻ると配送され、送信者に delivered が届く
+    send(&state, &server, &bob, MessageProtocol::new("bob", "back")).await;
+    let delivered = recv_message(&bob).await;
+    assert_eq!(delivered, queued);
+    let status = recv_message(&alice).await;
+    assert_eq!(status.target_id, queued.id);
+    assert_eq!(status.delivery_status(), Some(DeliveryStatus::Delivered));
+    assert!(state.direct_queue.is_empty());
+}
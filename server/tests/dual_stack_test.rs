@@ -0,0 +1,151 @@
+//! Dual-stack integration test.
+//!
+//! `[::]` にバインドしたサーバに IPv4 と IPv6 のループバックから接続し、
+//! 相互に中継されることと、クライアント表のアドレス表現を確認する。
+//! 複数のアドレスにバインドしたときは、各クライアントに参加したソケットから
+//! 送ることも確認する。
+
+use std::{
+    net::{Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use protocol::MessageProtocol;
+use server::{
+    BUFFER_SIZE,
+    addr::{addr_for_socket, canonical_addr},
+    client_manager::ClientManager,
+    set_up_server_on,
+    state::ServerState,
+    worker::run_workers,
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+async fn recv_message(sock: &UdpSocket) -> MessageProtocol {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    MessageProtocol::deserialize(&buf[..len]).unwrap()
+}
+
+async fn send_message(sock: &UdpSocket, server: SocketAddr, user: &str, body: &str) {
+    let frame = MessageProtocol::new(user, body).serialize().unwrap();
+    sock.send_to(&frame, server).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn relays_between_v4_and_v6_peers() {
+    // ❶ デュアルスタックでサーバを起動
+    let sock = set_up_server_on(&["[::]:0".parse().unwrap()])
+        .unwrap()
+        .remove(0);
+    let port = sock.local_addr().unwrap().port();
+    let manager = Arc::new(ClientManager::new(Duration::from_secs(30)));
+    tokio::spawn(run_workers(
+        Arc::new(sock),
+        Arc::new(ServerState::new(Arc::clone(&manager))),
+        2,
+    ));
+
+    let v4 = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let v6 = UdpSocket::bind("[::1]:0").await.unwrap();
+
+    // ❷ IPv4 クライアントを登録
+    send_message(&v4, SocketAddr::from(([127, 0, 0, 1], port)), "alice", "v4").await;
+    assert_eq!(recv_message(&v4).await.body, "v4");
+
+    // ❸ IPv6 クライアントの発言が IPv4 クライアントにも届くことを検証
+    send_message(
+        &v6,
+        SocketAddr::from((Ipv6Addr::LOCALHOST, port)),
+        "bob",
+        "v6",
+    )
+    .await;
+    assert_eq!(recv_message(&v6).await.body, "v6");
+    let relayed = recv_message(&v4).await;
+    assert_eq!(
+        (relayed.user_name.as_str(), relayed.body.as_str()),
+        ("bob", "v6")
+    );
+
+    // ❹ IPv4 クライアントはマップ形式ではなく通常の IPv4 アドレスで記録される
+    let alice = manager.clients_table.get("alice").unwrap().socket_addr;
+    assert_eq!(alice, v4.local_addr().unwrap());
+    let bob = manager.clients_table.get("bob").unwrap().socket_addr;
+    assert_eq!(bob, v6.local_addr().unwrap());
+}
+
+// 受信したデータグラムと送信元のアドレス
+async fn recv_from(sock: &UdpSocket) -> (MessageProtocol, SocketAddr) {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, from) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    (MessageProtocol::deserialize(&buf[..len]).unwrap(), from)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn answers_each_client_from_the_socket_it_joined() {
+    // ❶ IPv4 と IPv6 専用の 2 つのアドレスにバインドし、状態を共有する
+    let socks =
+        set_up_server_on(&["127.0.0.1:0".parse().unwrap(), "[::1]:0".parse().unwrap()]).unwrap();
+    let [v4_server, v6_server] = [0, 1].map(|i| socks[i].local_addr().unwrap());
+    let state = Arc::new(ServerState::new(Arc::new(ClientManager::new(
+        Duration::from_secs(30),
+    ))));
+    for sock in socks {
+        tokio::spawn(run_workers(Arc::new(sock), Arc::clone(&state), 2));
+    }
+
+    let v4 = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let v6 = UdpSocket::bind("[::1]:0").await.unwrap();
+    send_message(&v4, v4_server, "alice", "v4").await;
+    assert_eq!(recv_from(&v4).await.1, v4_server);
+    send_message(&v6, v6_server, "bob", "v6").await;
+    assert_eq!(recv_from(&v6).await.1, v6_server);
+
+    // ❷ IPv6 ソケットで受けた発言も、IPv4 クライアントには IPv4 ソケットから届く
+    let (relayed, from) = recv_from(&v4).await;
+    assert_eq!(
+        (relayed.user_name.as_str(), relayed.body.as_str()),
+        ("bob", "v6")
+    );
+    assert_eq!(from, v4_server);
+
+    // ❸ 逆向きも同じ
+    send_message(&v4, v4_server, "alice", "hello v6").await;
+    assert_eq!(recv_from(&v4).await.1, v4_server);
+    let (relayed, from) = recv_from(&v6).await;
+    assert_eq!(relayed.body, "hello v6");
+    assert_eq!(from, v6_server);
+    assert_eq!(
+        state
+            .client_manager
+            .clients_table
+            .get("bob")
+            .unwrap()
+            .ingress,
+        Some(v6_server)
+    );
+}
+
+#[test]
+fn maps_addresses_between_families() {
+    let v4: SocketAddr = "127.0.0.1:9050".parse().unwrap();
+    let mapped: SocketAddr = "[::ffff:127.0.0.1]:9050".parse().unwrap();
+    let v6: SocketAddr = "[::1]:9050".parse().unwrap();
+
+    // マップ形式は IPv4 に正規化し、純粋な IPv6 はそのまま
+    assert_eq!(canonical_addr(mapped), v4);
+    assert_eq!(canonical_addr(v6), v6);
+
+    // IPv6 ソケットから IPv4 宛に送るときだけマップ形式に戻す
+    assert_eq!(addr_for_socket("[::]:9001".parse().unwrap(), v4), mapped);
+    assert_eq!(addr_for_socket("0.0.0.0:9001".parse().unwrap(), v4), v4);
+    assert_eq!(addr_for_socket("[::]:9001".parse().unwrap(), v6), v6);
+}
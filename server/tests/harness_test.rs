@@ -0,0 +1,70 @@
+//! Multi-user scenarios on the test harness.
+//!
+//! エフェメラルポートのサーバに複数のクライアントをつなぎ、部屋全体への中継と
+//! ダイレクトメッセージを数行で書けること、停止後は何も返らないことを確認する。
+
+use protocol::{DeliveryStatus, MessageKind, MessageProtocol};
+use test_support::harness::TestServer;
+
+#[tokio::test]
+async fn relays_between_three_users() {
+    let server = TestServer::start().await.unwrap();
+    let [alice, bob, carol] = server.join(["alice", "bob", "carol"]).await.unwrap();
+
+    // ❶ 発言は本人を含む全員に届く
+    alice.say("hello all").await;
+    for client in [&alice, &bob, &carol] {
+        client.expect_from("alice", "hello all").await;
+    }
+
+    // ❷ ダイレクトメッセージは宛先と本人にだけ届く
+    bob.whisper("carol", "psst").await;
+    let direct = carol.expect_from("bob", "psst").await;
+    assert_eq!(direct.kind, MessageKind::Direct);
+    bob.expect_from("bob", "psst").await;
+    let status = bob.recv().await;
+    assert_eq!(status.delivery_status(), Some(DeliveryStatus::Delivered));
+    alice.expect_silence().await;
+    assert_eq!(server.state().client_manager.active_client_count(), 3);
+}
+
+#[tokio::test]
+async fn shutdown_stops_the_server() {
+    let server = TestServer::start().await.unwrap();
+    let [alice] = server.join(["alice"]).await.unwrap();
+    let addr = server.addr();
+    assert_ne!(addr.port(), server::SERVER_PORT);
+
+    // ❶ 停止後の送信には何も返らない
+    server.shutdown().await.unwrap();
+    alice.say("anyone?").await;
+    alice.expect_silence().await;
+
+    // ❷ 別のタスクからも停止できる
+    let server = TestServer::start().await.unwrap();
+    let handle = server.shutdown_handle();
+    tokio::spawn(async move { handle.shutdown() })
+        .await
+        .unwrap();
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn heartbeat_rejoins_without_relaying() {
+    let server = TestServer::start().await.unwrap();
+    let [alice] = server.join(["alice"]).await.unwrap();
+    let bob = server.client("bob").await.unwrap();
+    let manager = &server.state().client_manager;
+
+    // ❶ 生存通知で参加するが、部屋には何も流れない
+    bob.send(MessageProtocol::heartbeat("bob")).await;
+    bob.expect_silence().await;
+    alice.expect_silence().await;
+    assert!(manager.clients_table.contains_key("bob"));
+
+    // ❷ 外された後も、次の生存通知で戻る
+    manager.remove_client("bob");
+    bob.send(MessageProtocol::heartbeat("bob")).await;
+    bob.expect_silence().await;
+    assert!(manager.clients_table.contains_key("bob"));
+}
@@ -0,0 +1,283 @@
+//! Message edit / delete / reaction integration test.
+//!
+//! 実際の UDP ソケットから編集・削除を送り、投稿者とオペレーターだけが
+//! 履歴を変更でき、変更が全員に中継されることを確認する。
+//! リアクションは履歴で集計され、集計結果が中継される。
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::{MessageKind, MessageProtocol};
+use server::{
+    BUFFER_SIZE,
+    client_manager::{ClientInfo, ClientManager, Role},
+    history::{History, HistoryError},
+    process_datagram,
+    state::{SYSTEM_USER_NAME, ServerState},
+};
+use tokio::{
+    net::UdpSocket,
+    time::{Instant, timeout},
+};
+
+async fn recv_message(sock: &UdpSocket) -> MessageProtocol {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    MessageProtocol::deserialize(&buf[..len]).unwrap()
+}
+
+async fn send(
+    state: &Arc<ServerState>,
+    server: &UdpSocket,
+    from: &UdpSocket,
+    message: MessageProtocol,
+) {
+    let frame = message.serialize().unwrap();
+    process_datagram(server, &frame, from.local_addr().unwrap(), state)
+        .await
+        .unwrap();
+}
+
+// 登録済みのクライアントとしての送信者
+fn client(user_name: &str, role: Role) -> ClientInfo {
+    ClientInfo {
+        user_name: user_name.into(),
+        socket_addr: "127.0.0.1:40000".parse().unwrap(),
+        last_message_time: Instant::now(),
+        role,
+        ingress: None,
+    }
+}
+
+#[test]
+fn history_is_bounded() {
+    let history = History::new(2);
+    for id in 1..=3 {
+        history.record(&MessageProtocol {
+            id,
+            ..MessageProtocol::new("alice", format!("message {id}"))
+        });
+    }
+    // 未スタンプのメッセージと編集要求は記録しない
+    history.record(&MessageProtocol::new("alice", "unstamped"));
+    history.record(&MessageProtocol {
+        id: 4,
+        ..MessageProtocol::edit("alice", 3, "edited")
+    });
+
+    assert_eq!(history.len(), 2);
+    assert!(history.get(1).is_none(), "最も古いメッセージから忘れる");
+    assert_eq!(
+        history.apply(
+            &MessageProtocol::delete("alice", 1),
+            &client("alice", Role::Host)
+        ),
+        Err(HistoryError::NotFound(1))
+    );
+}
+
+#[tokio::test]
+async fn author_and_operators_can_change_messages() {
+    let state = Arc::new(ServerState::new(Arc::new(ClientManager::new(
+        Duration::from_secs(30),
+    ))));
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bob = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    // ❶ alice（ホスト）と bob（メンバー）が発言する
+    send(&state, &server, &alice, MessageProtocol::new("alice", "hi")).await;
+    let alice_message = recv_message(&alice).await;
+    send(&state, &server, &bob, MessageProtocol::new("bob", "helo")).await;
+    let bob_message = recv_message(&bob).await;
+    recv_message(&alice).await;
+    assert_eq!(state.history.len(), 2);
+
+    // ❷ 投稿者は自分のメッセージを編集でき、変更が全員に中継される
+    send(
+        &state,
+        &server,
+        &bob,
+        MessageProtocol::edit("bob", bob_message.id, "hello"),
+    )
+    .await;
+    let edit = recv_message(&alice).await;
+    assert_eq!(edit.kind, MessageKind::Edit);
+    assert_eq!(edit.target_id, bob_message.id);
+    assert!(edit.id > bob_message.id, "編集にも新しい ID が付く");
+    recv_message(&bob).await;
+    assert_eq!(state.history.get(bob_message.id).unwrap().body, "hello");
+
+    // ❸ メンバーは他人のメッセージを変更できず、本人にだけエラーが届く
+    send(
+        &state,
+        &server,
+        &bob,
+        MessageProtocol::delete("bob", alice_message.id),
+    )
+    .await;
+    let error = recv_message(&bob).await;
+    assert_eq!(error.user_name, SYSTEM_USER_NAME);
+    assert!(error.body.contains("only the author"), "{}", error.body);
+    assert!(state.history.get(alice_message.id).is_some());
+
+    // 別のアドレスから投稿者を名乗っても編集できない
+    let mallory = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    send(
+        &state,
+        &server,
+        &mallory,
+        MessageProtocol::edit("alice", alice_message.id, "pwned"),
+    )
+    .await;
+    assert!(recv_message(&mallory).await.body.contains("already in use"));
+    assert_eq!(state.history.get(alice_message.id).unwrap().body, "hi");
+
+    // ❹ ホストは他人のメッセージを削除できる
+    send(
+        &state,
+        &server,
+        &alice,
+        MessageProtocol::delete("alice", bob_message.id),
+    )
+    .await;
+    assert_eq!(recv_message(&bob).await.kind, MessageKind::Delete);
+    recv_message(&alice).await;
+    assert!(state.history.get(bob_message.id).is_none());
+
+    // 削除済みのメッセージは編集できない
+    send(
+        &state,
+        &server,
+        &bob,
+        MessageProtocol::edit("bob", bob_message.id, "again"),
+    )
+    .await;
+    assert!(recv_message(&bob).await.body.contains("not found"));
+}
+
+#[test]
+fn authorship_comes_from_the_sender_not_the_frame() {
+    let history = History::default();
+    history.record(&MessageProtocol {
+        id: 1,
+        ..MessageProtocol::new("alice", "mine")
+    });
+
+    // 名前だけ投稿者を騙っても、送信者が別人なら拒否する
+    let forged = MessageProtocol::edit("alice", 1, "forged");
+    assert_eq!(
+        history.apply(&forged, &client("bob", Role::Member)),
+        Err(HistoryError::NotAuthor(1))
+    );
+    assert!(
+        history
+            .apply(&forged, &client("alice", Role::Member))
+            .is_ok()
+    );
+    assert_eq!(history.get(1).unwrap().body, "forged");
+}
+
+#[test]
+fn reactions_are_counted_per_user_and_toggle() {
+    let history = History::default();
+    history.record(&MessageProtocol {
+        id: 1,
+        ..MessageProtocol::new("alice", "ship it?")
+    });
+
+    for user in ["bob", "carol"] {
+        history
+            .apply(
+                &MessageProtocol::react(user, 1, "👍"),
+                &client(user, Role::Member),
+            )
+            .unwrap();
+    }
+    let summary = history
+        .apply(
+            &MessageProtocol::react("bob", 1, "🎉"),
+            &client("bob", Role::Member),
+        )
+        .unwrap();
+    assert_eq!(summary.kind, MessageKind::Reactions);
+    assert_eq!(summary.target_id, 1);
+    assert_eq!(
+        summary.tally(),
+        vec![("🎉".to_string(), 1), ("👍".to_string(), 2)]
+    );
+
+    // 同じリアクションをもう一度送ると取り消しになる
+    let summary = history
+        .apply(
+            &MessageProtocol::react("bob", 1, "🎉"),
+            &client("bob", Role::Member),
+        )
+        .unwrap();
+    assert_eq!(summary.tally(), vec![("👍".to_string(), 2)]);
+    assert_eq!(history.reactions(1), summary.tally());
+
+    assert_eq!(
+        history.apply(
+            &MessageProtocol::react("bob", 1, "two words"),
+            &client("bob", Role::Member)
+        ),
+        Err(HistoryError::InvalidReaction("two words".into()))
+    );
+}
+
+#[tokio::test]
+async fn reactions_and_replies_are_relayed() {
+    let state = Arc::new(ServerState::new(Arc::new(ClientManager::new(
+        Duration::from_secs(30),
+    ))));
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bob = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    send(&state, &server, &alice, MessageProtocol::new("alice", "hi")).await;
+    let original = recv_message(&alice).await;
+    send(
+        &state,
+        &server,
+        &bob,
+        MessageProtocol::reply("bob", original.id, "hey"),
+    )
+    .await;
+
+    // ❶ 返信は reply_to を保ったまま中継される
+    let reply = recv_message(&alice).await;
+    assert_eq!(reply.reply_to, original.id);
+    recv_message(&bob).await;
+
+    // ❷ リアクションは集計結果として全員に届く
+    send(
+        &state,
+        &server,
+        &bob,
+        MessageProtocol::react("bob", original.id, "❤"),
+    )
+    .await;
+    for sock in [&alice, &bob] {
+        let summary = recv_message(sock).await;
+        assert_eq!(summary.kind, MessageKind::Reactions);
+        assert_eq!(summary.user_name, "bob");
+        assert_eq!(summary.tally(), vec![("❤".to_string(), 1)]);
+    }
+
+    // ❸ クライアントは集計フレームを送れない
+    send(
+        &state,
+        &server,
+        &bob,
+        MessageProtocol::reactions("bob", original.id, &[("❤".into(), 99)]),
+    )
+    .await;
+    assert!(recv_message(&bob).await.body.contains("may not send"));
+    assert_eq!(
+        state.history.reactions(original.id),
+        vec![("❤".to_string(), 1)]
+    );
+}
@@ -0,0 +1,244 @@
+//! Message hook pipeline integration test.
+//!
+//! 言葉の置き換え・コマンドへの返信・送信者単位の破棄・監査をフックとして登録し、
+//! 登録順に連鎖すること、破棄や返信で後続のフックと中継が止まること、
+//! 入退室がフックに届くこと、DM とファイルの申し出も中継前のフックを通る
+//! こと、モデレーション以外の `/` コマンドと送信者名の書き換えがフックに
+//! 任されることを確認する。
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use protocol::{MessageKind, MessageProtocol, transfer::FileOffer};
+use server::{
+    client_manager::ClientInfo,
+    hooks::{HookAction, MessageHook},
+};
+use test_support::harness::TestServer;
+use tokio::time::{sleep, timeout};
+
+struct WordFilter;
+
+impl MessageHook for WordFilter {
+    fn on_relay(&self, message: &mut MessageProtocol, _sender: &ClientInfo) -> HookAction {
+        message.body = message.body.replace("darn", "****");
+        HookAction::Pass
+    }
+}
+
+struct PingBot;
+
+impl MessageHook for PingBot {
+    fn on_relay(&self, message: &mut MessageProtocol, _sender: &ClientInfo) -> HookAction {
+        match message.body.as_str() {
+            "!ping" => HookAction::Reply(MessageProtocol::new("bot", "pong")),
+            _ => HookAction::Pass,
+        }
+    }
+}
+
+struct NoFiles;
+
+impl MessageHook for NoFiles {
+    fn on_relay(&self, message: &mut MessageProtocol, _sender: &ClientInfo) -> HookAction {
+        match message.kind {
+            MessageKind::FileOffer => {
+                HookAction::Reply(MessageProtocol::new("bot", "files are disabled"))
+            }
+            _ => HookAction::Pass,
+        }
+    }
+}
+
+struct SlashRoll;
+
+impl MessageHook for SlashRoll {
+    fn on_relay(&self, message: &mut MessageProtocol, _sender: &ClientInfo) -> HookAction {
+        match message.body.as_str() {
+            "/roll" => HookAction::Reply(MessageProtocol::new("bot", "4")),
+            _ => HookAction::Pass,
+        }
+    }
+}
+
+struct Rename(&'static str, &'static str);
+
+impl MessageHook for Rename {
+    fn on_receive(&self, message: &mut MessageProtocol, _from: SocketAddr) -> HookAction {
+        if message.user_name == self.0 {
+            message.user_name = self.1.to_string();
+        }
+        HookAction::Pass
+    }
+}
+
+struct Block(&'static str);
+
+impl MessageHook for Block {
+    fn on_receive(&self, message: &mut MessageProtocol, _from: SocketAddr) -> HookAction {
+        if message.user_name == self.0 {
+            HookAction::Drop
+        } else {
+            HookAction::Pass
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Audit(Arc<Mutex<Vec<String>>>);
+
+impl Audit {
+    fn entries(&self) -> Vec<String> {
+        self.0.lock().unwrap().clone()
+    }
+
+    async fn wait_for(&self, entry: &str) {
+        timeout(Duration::from_secs(1), async {
+            while !self.entries().iter().any(|e| e == entry) {
+                sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("no {entry:?} in {:?}", self.entries()));
+    }
+}
+
+impl MessageHook for Audit {
+    fn on_relay(&self, message: &mut MessageProtocol, sender: &ClientInfo) -> HookAction {
+        let entry = format!("relay {}: {}", sender.user_name, message.body);
+        self.0.lock().unwrap().push(entry);
+        HookAction::Pass
+    }
+
+    fn on_join(&self, client: &ClientInfo) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("join {}", client.user_name));
+    }
+
+    fn on_leave(&self, client: &ClientInfo) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("leave {}", client.user_name));
+    }
+}
+
+#[tokio::test]
+async fn hooks_filter_answer_drop_and_audit() {
+    let server = TestServer::start().await.unwrap();
+    let audit = Audit::default();
+    let hooks = &server.state().hooks;
+    hooks.register(Block("spammer"));
+    hooks.register(WordFilter);
+    hooks.register(PingBot);
+    hooks.register(audit.clone());
+    assert_eq!(hooks.len(), 4);
+    let [alice, bob] = server.join(["alice", "bob"]).await.unwrap();
+    audit.wait_for("join bob").await;
+
+    // ❶ 置き換えは中継にも、後続のフックにも反映される
+    alice.say("oh darn").await;
+    alice.expect_from("alice", "oh ****").await;
+    bob.expect_from("alice", "oh ****").await;
+    assert!(
+        audit
+            .entries()
+            .contains(&"relay alice: oh ****".to_string())
+    );
+
+    // ❷ 返信したフックで連鎖は止まり、送信者にだけ返る
+    bob.say("!ping").await;
+    let reply = bob.expect_from("bot", "pong").await;
+    assert!(reply.id > 0);
+    alice.expect_silence().await;
+    assert!(!audit.entries().iter().any(|e| e.ends_with("!ping")));
+
+    // ❸ 破棄されたフレームは参加にも数えられない
+    let spammer = server.client("spammer").await.unwrap();
+    spammer.say("buy now").await;
+    alice.expect_silence().await;
+    spammer.expect_silence().await;
+    assert_eq!(server.state().client_manager.active_client_count(), 2);
+
+    // ❹ 退室もフックに届く
+    server.state().client_manager.remove_client("bob");
+    audit.wait_for("leave bob").await;
+    assert_eq!(
+        audit
+            .entries()
+            .iter()
+            .filter(|e| e.starts_with("join"))
+            .count(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn empty_chain_passes_everything() {
+    let server = TestServer::start().await.unwrap();
+    assert!(server.state().hooks.is_empty());
+    let [alice, bob] = server.join(["alice", "bob"]).await.unwrap();
+    alice.say("darn").await;
+    bob.expect_from("alice", "darn").await;
+}
+
+#[tokio::test]
+async fn hooks_see_direct_messages_and_file_offers() {
+    let server = TestServer::start().await.unwrap();
+    let audit = Audit::default();
+    let hooks = &server.state().hooks;
+    hooks.register(WordFilter);
+    hooks.register(NoFiles);
+    hooks.register(audit.clone());
+    let [alice, bob, carol] = server.join(["alice", "bob", "carol"]).await.unwrap();
+
+    // ❶ DM も置き換えられてから宛先に届く
+    alice.whisper("bob", "darn it").await;
+    let direct = bob.expect_from("alice", "**** it").await;
+    assert_eq!(direct.kind, MessageKind::Direct);
+    assert_eq!(alice.expect_from("alice", "**** it").await, direct);
+    assert_eq!(alice.recv().await.kind, MessageKind::DirectStatus);
+    assert!(
+        audit
+            .entries()
+            .contains(&"relay alice: **** it".to_string())
+    );
+    carol.expect_silence().await;
+
+    // ❷ ファイルの申し出もフックで止められる
+    let offer = FileOffer::for_data("notes.txt", b"hello");
+    bob.send(MessageProtocol::file_offer("bob", &offer)).await;
+    bob.expect_from("bot", "files are disabled").await;
+    alice.expect_silence().await;
+    carol.expect_silence().await;
+    assert!(server.state().transfers.is_empty());
+}
+
+#[tokio::test]
+async fn hooks_see_slash_commands_and_renamed_senders() {
+    let server = TestServer::start().await.unwrap();
+    let hooks = &server.state().hooks;
+    hooks.register(Rename("guest", "visitor"));
+    hooks.register(SlashRoll);
+    let [alice, bob] = server.join(["alice", "bob"]).await.unwrap();
+
+    // ❶ モデレーション以外の `/` コマンドは中継前のフックに届き、残りは部屋に流れる
+    alice.say("/roll").await;
+    alice.expect_from("bot", "4").await;
+    bob.expect_silence().await;
+    alice.say("/shrug").await;
+    bob.expect_from("alice", "/shrug").await;
+
+    // ❷ 受信時のフックが書き換えた名前で参加する
+    let guest = server.client("guest").await.unwrap();
+    guest.say("hi").await;
+    bob.expect_from("visitor", "hi").await;
+    let clients = &server.state().client_manager.clients_table;
+    assert!(clients.contains_key("visitor"));
+    assert!(!clients.contains_key("guest"));
+}
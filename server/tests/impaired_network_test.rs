@@ -0,0 +1,175 @@
+//! Integration test over an impaired network.
+//!
+//! サーバとクライアントの間に劣化させるプロキシを挟み、損失・重複・並べ替え・
+//! 遅延があってもファイル転送が再送で完了すること、切り詰められたフレームを
+//! 受けてもサーバが動き続けることを確認する。
+
+use std::{net::SocketAddr, time::Duration};
+
+use protocol::{
+    MessageKind, MessageProtocol,
+    transfer::{Download, FILE_CHUNK_SIZE, Upload},
+};
+use server::BUFFER_SIZE;
+use test_support::{
+    harness::TestServer,
+    proxy::{ImpairedProxy, Impairments},
+};
+use tokio::{
+    net::UdpSocket,
+    time::{Instant, interval, sleep, timeout},
+};
+
+async fn recv_message(sock: &UdpSocket) -> MessageProtocol {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    MessageProtocol::deserialize(&buf[..len]).unwrap()
+}
+
+async fn send(sock: &UdpSocket, to: SocketAddr, message: MessageProtocol) {
+    sock.send_to(&message.serialize().unwrap(), to)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn file_transfer_survives_a_lossy_network() {
+    let server = TestServer::start().await.unwrap();
+    let proxy = ImpairedProxy::start(server.addr(), Impairments::NONE, 2024)
+        .await
+        .unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bob = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    // ❶ 参加と申し出は劣化なしで済ませる
+    send(
+        &alice,
+        proxy.addr(),
+        MessageProtocol::new("alice", "joined"),
+    )
+    .await;
+    recv_message(&alice).await;
+    send(&bob, proxy.addr(), MessageProtocol::new("bob", "joined")).await;
+    recv_message(&alice).await;
+    recv_message(&bob).await;
+    let data: Vec<u8> = (0..FILE_CHUNK_SIZE * 12 + 7).map(|i| i as u8).collect();
+    let mut upload = Upload::new("photo.png", data.clone());
+    send(
+        &alice,
+        proxy.addr(),
+        MessageProtocol::file_offer("alice", upload.offer()),
+    )
+    .await;
+    let id = recv_message(&bob).await.id;
+    recv_message(&alice).await;
+
+    // ❷ 以後は両方向とも劣化させる（切り詰めは本文を壊すので別のテストで見る）
+    proxy.set_impairments(Impairments {
+        loss: 0.2,
+        duplicate: 0.1,
+        reorder: 0.1,
+        delay: 0.1,
+        ..Impairments::NONE
+    });
+
+    // ❸ 送信者は確認応答が進まなければ巻き戻し、受信者は欠けた位置を要求し続ける
+    let mut download = Download::new(upload.offer().clone());
+    let mut tick = interval(Duration::from_millis(30));
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut progressed = true;
+    let (mut alice_buf, mut bob_buf) = ([0u8; BUFFER_SIZE], [0u8; BUFFER_SIZE]);
+    while !download.is_complete() {
+        assert!(
+            Instant::now() < deadline,
+            "stalled at chunk {}",
+            download.next_index()
+        );
+        tokio::select! {
+            _ = tick.tick() => {
+                if !progressed {
+                    upload.rewind();
+                }
+                progressed = false;
+                for (index, chunk) in upload.due_chunks() {
+                    let frame = MessageProtocol::file_chunk("alice", id, index, chunk);
+                    send(&alice, proxy.addr(), frame).await;
+                }
+                let ack = MessageProtocol::file_ack("bob", id, Some(download.next_index()));
+                send(&bob, proxy.addr(), ack).await;
+            }
+            Ok((len, _)) = alice.recv_from(&mut alice_buf) => {
+                if let Ok(frame) = MessageProtocol::deserialize(&alice_buf[..len])
+                    && frame.kind == MessageKind::FileAck
+                    && let Some(next) = frame.ack_index()
+                {
+                    progressed |= upload.on_ack(next);
+                }
+            }
+            Ok((len, _)) = bob.recv_from(&mut bob_buf) => {
+                if let Ok(frame) = MessageProtocol::deserialize(&bob_buf[..len])
+                    && frame.kind == MessageKind::FileChunk
+                    && let Some((index, chunk)) = frame.chunk()
+                {
+                    let _ = download.receive(index, &chunk);
+                }
+            }
+        }
+    }
+
+    assert_eq!(download.verified().unwrap(), data.as_slice());
+    let stats = proxy.stats();
+    assert!(stats.dropped > 0, "{stats:?}");
+}
+
+#[tokio::test]
+async fn server_keeps_running_after_truncated_frames() {
+    let server = TestServer::start().await.unwrap();
+    let proxy = ImpairedProxy::start(
+        server.addr(),
+        Impairments {
+            truncate: 1.0,
+            ..Impairments::NONE
+        },
+        7,
+    )
+    .await
+    .unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    // ❶ 切り詰められたフレームを何度も送る
+    for i in 0..20 {
+        let body = format!("message {i}");
+        send(&alice, proxy.addr(), MessageProtocol::new("alice", body)).await;
+    }
+    // プロキシが処理し終えるまで待つ（サーバからの返りも切り詰めて数える）
+    timeout(Duration::from_secs(1), async {
+        while proxy.stats().truncated < 20 {
+            sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("proxy did not forward the frames");
+
+    // ❷ 劣化を止めれば、サーバはまだ応答する
+    proxy.set_impairments(Impairments::NONE);
+    send(
+        &alice,
+        proxy.addr(),
+        MessageProtocol::new("alice", "still there?"),
+    )
+    .await;
+    let mut buf = [0u8; BUFFER_SIZE];
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        let (len, _) = timeout(deadline - Instant::now(), alice.recv_from(&mut buf))
+            .await
+            .expect("server stopped answering")
+            .unwrap();
+        if MessageProtocol::deserialize(&buf[..len]).is_ok_and(|m| m.body == "still there?") {
+            break;
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Metrics endpoint integration test.
+//!
+//! 数件のデータグラムを処理した後に `/metrics` を HTTP で取得し、
+//! カウンタが Prometheus 形式で出力されることを確認する。
+#![cfg(feature = "metrics")]
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::MessageProtocol;
+use server::{
+    client_manager::ClientManager,
+    metrics::{REQUEST_TIMEOUT, serve_metrics},
+    process_datagram,
+    state::ServerState,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+};
+
+async fn http_get(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn exposes_counters_in_prometheus_format() {
+    let manager = Arc::new(ClientManager::new(Duration::from_secs(30)));
+    let state = Arc::new(ServerState::new(manager));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let metrics_addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_metrics(listener, Arc::clone(&state)));
+
+    // ❶ 正常なフレーム 1 件、解析できないフレーム 1 件、システム名を騙るフレーム 1 件
+    let sock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let from = client.local_addr().unwrap();
+    let frame = MessageProtocol::new("alice", "hi").serialize().unwrap();
+    process_datagram(&sock, &frame, from, &state).await.unwrap();
+    process_datagram(&sock, b"bob hello", from, &state)
+        .await
+        .unwrap();
+    let spoofed = MessageProtocol::new("system", "fake").serialize().unwrap();
+    process_datagram(&sock, &spoofed, from, &state)
+        .await
+        .unwrap();
+
+    // ❷ メトリクスを取得して検証
+    let response = http_get(metrics_addr, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    assert!(
+        response.contains("chat_datagrams_received_total 3\n"),
+        "{response}"
+    );
+    assert!(
+        response.contains("chat_decode_failures_total{error=\"truncated\"} 1\n"),
+        "{response}"
+    );
+    assert!(
+        response.contains("chat_dropped_datagrams_total{reason=\"reserved_name\"} 1\n"),
+        "{response}"
+    );
+    // alice と bob の 2 クライアントが登録されている
+    assert!(response.contains("chat_active_clients 2\n"), "{response}");
+    assert!(
+        response.contains("chat_relay_duration_seconds_count 2\n"),
+        "{response}"
+    );
+    // 2 回の中継で 1 + 2 宛先に送信
+    assert!(
+        response.contains("chat_datagrams_sent_total 3\n"),
+        "{response}"
+    );
+
+    let not_found = http_get(metrics_addr, "/").await;
+    assert!(not_found.starts_with("HTTP/1.1 404"), "{not_found}");
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_connections_are_closed() {
+    let manager = Arc::new(ClientManager::new(Duration::from_secs(30)));
+    let state = Arc::new(ServerState::new(manager));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let metrics_addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_metrics(listener, state));
+
+    // 何も送らない接続は、期限が来るとサーバ側から閉じられる
+    let mut idle = TcpStream::connect(metrics_addr).await.unwrap();
+    let started = tokio::time::Instant::now();
+    let mut buf = [0u8; 16];
+    assert_eq!(idle.read(&mut buf).await.unwrap(), 0);
+    assert!(started.elapsed() >= REQUEST_TIMEOUT);
+
+    // その後の要求には普通に答える
+    let response = http_get(metrics_addr, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+}
@@ -0,0 +1,335 @@
+//! Moderation integration test.
+//!
+//! 実際の UDP ソケットからチャットコマンドを送り、役割ごとの権限、
+//! キック・ミュート・BAN の動作と BAN リストの永続化を確認する。
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::MessageProtocol;
+use server::{
+    BUFFER_SIZE,
+    client_manager::{ClientManager, Role},
+    moderation::{ModCommand, Moderation, ModerationError, authorize},
+    process_datagram,
+    state::{SYSTEM_USER_NAME, ServerState},
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+async fn recv_message(sock: &UdpSocket) -> MessageProtocol {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    MessageProtocol::deserialize(&buf[..len]).unwrap()
+}
+
+async fn assert_silent(sock: &UdpSocket) {
+    let mut buf = [0u8; BUFFER_SIZE];
+    assert!(
+        timeout(Duration::from_millis(100), sock.recv_from(&mut buf))
+            .await
+            .is_err()
+    );
+}
+
+struct Room {
+    state: Arc<ServerState>,
+    server: UdpSocket,
+}
+
+impl Room {
+    async fn new() -> Self {
+        let manager = Arc::new(ClientManager::new(Duration::from_secs(30)));
+        Self {
+            state: Arc::new(ServerState::new(manager)),
+            server: UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+        }
+    }
+
+    async fn send(&self, from: &UdpSocket, user_name: &str, body: &str) {
+        let frame = MessageProtocol::new(user_name, body).serialize().unwrap();
+        process_datagram(
+            &self.server,
+            &frame,
+            from.local_addr().unwrap(),
+            &self.state,
+        )
+        .await
+        .unwrap();
+    }
+
+    // 参加して自分のエコーを読み捨てる
+    async fn join(&self, user_name: &str, others: &[&UdpSocket]) -> UdpSocket {
+        self.join_from("127.0.0.1", user_name, others).await
+    }
+
+    async fn join_from(&self, ip: &str, user_name: &str, others: &[&UdpSocket]) -> UdpSocket {
+        let sock = UdpSocket::bind((ip, 0)).await.unwrap();
+        self.send(&sock, user_name, "hello").await;
+        recv_message(&sock).await;
+        for other in others {
+            recv_message(other).await;
+        }
+        sock
+    }
+
+    fn role(&self, user_name: &str) -> Role {
+        self.state
+            .client_manager
+            .clients_table
+            .get(user_name)
+            .unwrap()
+            .role
+    }
+}
+
+#[test]
+fn parses_commands() {
+    assert_eq!(ModCommand::parse("hello"), None);
+    assert_eq!(
+        ModCommand::parse("/kick bob"),
+        Some(Ok(ModCommand::Kick("bob".into())))
+    );
+    assert_eq!(
+        ModCommand::parse("/mute bob 30"),
+        Some(Ok(ModCommand::Mute("bob".into(), Duration::from_secs(30))))
+    );
+    assert!(matches!(
+        ModCommand::parse("/mute bob soon"),
+        Some(Err(ModerationError::Invalid(_)))
+    ));
+    assert!(matches!(
+        ModCommand::parse("/kick"),
+        Some(Err(ModerationError::Invalid(_)))
+    ));
+    // 知らないコマンドは普通の発言としてフックと部屋に渡す
+    assert_eq!(ModCommand::parse("/dance"), None);
+}
+
+#[test]
+fn authorization_follows_role_order() {
+    let kick = ModCommand::Kick("bob".into());
+    let op = ModCommand::Op("bob".into());
+
+    // メンバーは何もできない
+    assert_eq!(
+        authorize(&kick, Role::Member, Some(("bob", Role::Member))),
+        Err(ModerationError::NotAuthorized {
+            command: "kick",
+            required: Role::Operator,
+        })
+    );
+    // オペレーターはメンバーだけを対象にできる
+    assert!(authorize(&kick, Role::Operator, Some(("bob", Role::Member))).is_ok());
+    assert!(matches!(
+        authorize(&kick, Role::Operator, Some(("bob", Role::Operator))),
+        Err(ModerationError::Outranked { .. })
+    ));
+    // 役割の付与はホストのみ
+    assert!(matches!(
+        authorize(&op, Role::Operator, Some(("bob", Role::Member))),
+        Err(ModerationError::NotAuthorized { .. })
+    ));
+    assert!(authorize(&op, Role::Host, Some(("bob", Role::Member))).is_ok());
+}
+
+#[tokio::test]
+async fn first_client_hosts_and_members_cannot_moderate() {
+    let room = Room::new().await;
+    let alice = room.join("alice", &[]).await;
+    let bob = room.join("bob", &[&alice]).await;
+    assert_eq!(room.role("alice"), Role::Host);
+    assert_eq!(room.role("bob"), Role::Member);
+
+    // ❶ メンバーのコマンドは拒否され、本人にだけエラーが届く
+    room.send(&bob, "bob", "/kick alice").await;
+    let error = recv_message(&bob).await;
+    assert_eq!(error.user_name, SYSTEM_USER_NAME);
+    assert!(error.body.contains("Operator"), "{}", error.body);
+    assert_silent(&alice).await;
+    assert!(
+        room.state
+            .client_manager
+            .clients_table
+            .contains_key("alice")
+    );
+
+    // ❷ ホストがオペレーターに任命し、全員に告知される
+    room.send(&alice, "alice", "/op bob").await;
+    assert!(recv_message(&alice).await.body.contains("operator"));
+    assert!(recv_message(&bob).await.body.contains("operator"));
+    assert_eq!(room.role("bob"), Role::Operator);
+
+    // ❸ 発言しても役割は保たれるが、ホストは対象にできない
+    room.send(&bob, "bob", "/kick alice").await;
+    assert!(recv_message(&bob).await.body.contains("outrank"));
+    assert_eq!(room.role("bob"), Role::Operator);
+}
+
+#[tokio::test]
+async fn impersonating_the_host_from_another_address_fails() {
+    let room = Room::new().await;
+    let alice = room.join("alice", &[]).await;
+    let bob = room.join("bob", &[&alice]).await;
+    let host_addr = alice.local_addr().unwrap();
+
+    // ❶ 別のアドレスからホストの名前でコマンドを送っても実行されない
+    let mallory = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    room.send(&mallory, "alice", "/kick bob").await;
+    let notice = recv_message(&mallory).await;
+    assert_eq!(notice.user_name, SYSTEM_USER_NAME);
+    assert!(notice.body.contains("already in use"), "{}", notice.body);
+    assert!(room.state.client_manager.clients_table.contains_key("bob"));
+    assert_silent(&bob).await;
+
+    // ❷ 登録もアドレスも役割も元のまま
+    let entry = room
+        .state
+        .client_manager
+        .clients_table
+        .get("alice")
+        .unwrap()
+        .clone();
+    assert_eq!((entry.socket_addr, entry.role), (host_addr, Role::Host));
+    room.send(&mallory, "alice", "/op mallory").await;
+    recv_message(&mallory).await;
+    assert_silent(&alice).await;
+}
+
+#[tokio::test]
+async fn kick_and_mute() {
+    let room = Room::new().await;
+    let alice = room.join("alice", &[]).await;
+    let bob = room.join("bob", &[&alice]).await;
+
+    // ミュート中の発言は中継されず、本人に通知される
+    room.send(&alice, "alice", "/mute bob 60").await;
+    assert!(recv_message(&alice).await.body.contains("muted"));
+    assert!(recv_message(&bob).await.body.contains("muted"));
+    room.send(&bob, "bob", "can you hear me").await;
+    assert!(recv_message(&bob).await.body.contains("you are muted"));
+    assert_silent(&alice).await;
+
+    room.send(&alice, "alice", "/unmute bob").await;
+    recv_message(&alice).await;
+    recv_message(&bob).await;
+    room.send(&bob, "bob", "back").await;
+    assert_eq!(recv_message(&alice).await.body, "back");
+    recv_message(&bob).await;
+
+    // キックされたユーザーには理由が届き、残りの全員に告知される
+    room.send(&alice, "alice", "/kick bob").await;
+    assert!(recv_message(&bob).await.body.contains("kicked"));
+    assert!(recv_message(&alice).await.body.contains("bob was kicked"));
+    assert!(!room.state.client_manager.clients_table.contains_key("bob"));
+    assert_silent(&bob).await;
+}
+
+#[tokio::test]
+async fn banned_user_cannot_rejoin() {
+    let room = Room::new().await;
+    let alice = room.join("alice", &[]).await;
+    let bob = room.join("bob", &[&alice]).await;
+
+    room.send(&alice, "alice", "/ban bob").await;
+    assert!(recv_message(&bob).await.body.contains("banned"));
+    assert!(recv_message(&alice).await.body.contains("bob was banned"));
+
+    room.send(&bob, "bob", "let me back in").await;
+    assert!(!room.state.client_manager.clients_table.contains_key("bob"));
+    assert_silent(&alice).await;
+
+    // 存在しない BAN の解除は本人にエラーとして返る
+    room.send(&alice, "alice", "/unban carol").await;
+    assert!(recv_message(&alice).await.body.contains("not banned"));
+}
+
+#[tokio::test]
+async fn address_bans_respect_everyone_on_the_address() {
+    let room = Room::new().await;
+    let manager = &room.state.client_manager;
+    let alice = room.join_from("127.0.0.2", "alice", &[]).await;
+    let bob = room.join("bob", &[&alice]).await;
+    let carol = room.join_from("127.0.0.3", "carol", &[&alice, &bob]).await;
+    manager.set_role("bob", Role::Operator).unwrap();
+    manager.set_role("carol", Role::Operator).unwrap();
+
+    // ❶ ホストや同じ役割の相手がいるアドレスは、IP を直接書いても BAN できない
+    for target in ["127.0.0.2", "127.0.0.3"] {
+        room.send(&bob, "bob", &format!("/ban-ip {target}")).await;
+        let notice = recv_message(&bob).await;
+        assert!(notice.body.contains("outrank"), "{}", notice.body);
+    }
+    assert_eq!(room.state.moderation.ban_count(), 0);
+    assert_eq!(manager.active_client_count(), 3);
+    assert_silent(&alice).await;
+    assert_silent(&carol).await;
+
+    // ❷ 全員より上位なら BAN でき、そのアドレスの全員が退出する
+    manager.set_role("carol", Role::Member).unwrap();
+    room.send(&bob, "bob", "/ban-ip 127.0.0.3").await;
+    assert!(recv_message(&carol).await.body.contains("banned"));
+    assert!(
+        recv_message(&alice)
+            .await
+            .body
+            .contains("banned by address")
+    );
+    assert!(!manager.clients_table.contains_key("carol"));
+}
+
+#[tokio::test]
+async fn offline_moderators_keep_their_rank_for_bans() {
+    let room = Room::new().await;
+    let manager = &room.state.client_manager;
+    let alice = room.join("alice", &[]).await;
+    let bob = room.join("bob", &[&alice]).await;
+    let carol = room.join("carol", &[&alice, &bob]).await;
+    let dave = room.join("dave", &[&alice, &bob, &carol]).await;
+    manager.set_role("bob", Role::Operator).unwrap();
+    manager.set_role("carol", Role::Operator).unwrap();
+    for user_name in ["alice", "carol", "dave"] {
+        manager.remove_client(user_name);
+    }
+    assert_eq!(manager.known_role("alice"), Some(Role::Host));
+    assert_eq!(manager.known_role("dave"), None);
+
+    // ❶ 退出中のホストやオペレーターは、最後の役割のまま守られる
+    for target in ["alice", "carol"] {
+        room.send(&bob, "bob", &format!("/ban {target}")).await;
+        let notice = recv_message(&bob).await;
+        assert!(notice.body.contains("outrank"), "{}", notice.body);
+    }
+    assert_eq!(room.state.moderation.ban_count(), 0);
+
+    // ❷ 退出中のメンバーは BAN できる
+    room.send(&bob, "bob", "/ban dave").await;
+    assert!(recv_message(&bob).await.body.contains("dave was banned"));
+    assert_silent(&dave).await;
+}
+
+#[test]
+fn bans_persist_across_restarts() {
+    let path = std::env::temp_dir().join(format!("online-chat-bans-{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let ip = "203.0.113.7".parse().unwrap();
+    let other_ip = "198.51.100.1".parse().unwrap();
+
+    let moderation = Moderation::load(&path).unwrap();
+    assert_eq!(moderation.ban_count(), 0);
+    moderation.ban_user("mallory").unwrap();
+    moderation.ban_ip(ip).unwrap();
+
+    // 読み直しても BAN が残っている
+    let reloaded = Moderation::load(&path).unwrap();
+    assert_eq!(reloaded.ban_count(), 2);
+    assert!(reloaded.is_banned("mallory", other_ip));
+    assert!(reloaded.is_banned("anyone", ip));
+    assert!(!reloaded.is_banned("anyone", other_ip));
+
+    assert!(reloaded.unban("203.0.113.7").unwrap());
+    assert!(!reloaded.unban("203.0.113.7").unwrap());
+    assert_eq!(Moderation::load(&path).unwrap().ban_count(), 1);
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,155 @@
+//! Delivery / read receipt integration test.
+//!
+//! 受信確認と既読位置を送り、メッセージごとの受信状況が照会できること、
+//! 確認フレームが中継されないことを確認する。
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::{MessageKind, MessageProtocol, ReceiptStatus};
+use server::{
+    BUFFER_SIZE, client_manager::ClientManager, history::History, process_datagram,
+    state::ServerState,
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+async fn recv_message(sock: &UdpSocket) -> MessageProtocol {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    MessageProtocol::deserialize(&buf[..len]).unwrap()
+}
+
+async fn assert_silent(sock: &UdpSocket) {
+    let mut buf = [0u8; BUFFER_SIZE];
+    assert!(
+        timeout(Duration::from_millis(100), sock.recv_from(&mut buf))
+            .await
+            .is_err()
+    );
+}
+
+async fn send(
+    state: &Arc<ServerState>,
+    server: &UdpSocket,
+    from: &UdpSocket,
+    message: MessageProtocol,
+) {
+    let frame = message.serialize().unwrap();
+    process_datagram(server, &frame, from.local_addr().unwrap(), state)
+        .await
+        .unwrap();
+}
+
+#[test]
+fn read_markers_imply_delivery_and_only_move_forward() {
+    let history = History::default();
+    for id in [1, 2] {
+        history.record(&MessageProtocol {
+            id,
+            ..MessageProtocol::new("alice", "hi")
+        });
+        history.track_delivery(id, ["bob".to_string(), "carol".to_string()]);
+    }
+
+    assert!(history.acknowledge("bob", 1));
+    assert!(!history.acknowledge("alice", 1), "送信者は受信者ではない");
+    assert!(!history.acknowledge("bob", 99));
+    history.mark_read("carol", 2);
+    history.mark_read("carol", 1);
+
+    assert_eq!(
+        history.receipts(1).unwrap(),
+        vec![
+            ("bob".to_string(), ReceiptStatus::Delivered),
+            ("carol".to_string(), ReceiptStatus::Read),
+        ]
+    );
+    assert_eq!(history.receipts(2).unwrap()[1].1, ReceiptStatus::Read);
+
+    // 未来のメッセージを先に既読にはできない
+    history.mark_read("bob", 100);
+    history.record(&MessageProtocol {
+        id: 3,
+        ..MessageProtocol::new("alice", "later")
+    });
+    history.track_delivery(3, ["bob".to_string()]);
+    assert_eq!(
+        history.receipts(3).unwrap(),
+        vec![("bob".to_string(), ReceiptStatus::Pending)]
+    );
+    assert!(history.receipts(99).is_none());
+}
+
+#[tokio::test]
+async fn receipts_are_tracked_per_recipient() {
+    let state = Arc::new(ServerState::new(Arc::new(ClientManager::new(
+        Duration::from_secs(30),
+    ))));
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bob = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let carol = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    // ❶ 3人が参加し、alice の発言が bob と carol に届く
+    for (sock, name) in [(&alice, "alice"), (&bob, "bob"), (&carol, "carol")] {
+        send(&state, &server, sock, MessageProtocol::new(name, "joined")).await;
+    }
+    for sock in [&alice, &alice, &alice, &bob, &bob, &carol] {
+        recv_message(sock).await;
+    }
+    send(
+        &state,
+        &server,
+        &alice,
+        MessageProtocol::new("alice", "ping"),
+    )
+    .await;
+    let ping = recv_message(&bob).await;
+    recv_message(&alice).await;
+    recv_message(&carol).await;
+
+    // ❷ bob は受信確認、carol は既読位置を送る。どちらも中継されない
+    send(&state, &server, &bob, MessageProtocol::ack("bob", ping.id)).await;
+    send(
+        &state,
+        &server,
+        &carol,
+        MessageProtocol::read_up_to("carol", ping.id),
+    )
+    .await;
+    assert_silent(&alice).await;
+
+    // ❸ 照会の応答は照会した本人にだけ届く
+    send(
+        &state,
+        &server,
+        &alice,
+        MessageProtocol::receipts("alice", ping.id, &[]),
+    )
+    .await;
+    let report = recv_message(&alice).await;
+    assert_eq!(report.kind, MessageKind::Receipts);
+    assert_eq!(report.target_id, ping.id);
+    assert_eq!(
+        report.receipt_states(),
+        vec![
+            ("bob".to_string(), ReceiptStatus::Delivered),
+            ("carol".to_string(), ReceiptStatus::Read),
+        ]
+    );
+    assert_silent(&bob).await;
+
+    // 未参加のクライアントからの照会は無視される
+    let stranger = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    send(
+        &state,
+        &server,
+        &stranger,
+        MessageProtocol::receipts("mallory", ping.id, &[]),
+    )
+    .await;
+    assert_silent(&stranger).await;
+    assert!(!state.client_manager.clients_table.contains_key("mallory"));
+}
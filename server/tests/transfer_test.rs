@@ -0,0 +1,216 @@
+//! File transfer integration test.
+//!
+//! ファイルの申し出が大きさを確かめたうえで中継され、チャンクが順番どおりに
+//! 部屋へ中継されること、送信者に累積の確認応答が返り、受信者は欠けた位置から
+//! 再送を受けられることを確認する。
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::{
+    MessageKind, MessageProtocol,
+    transfer::{Download, FILE_CHUNK_SIZE, FileOffer},
+};
+use server::{
+    BUFFER_SIZE,
+    client_manager::ClientManager,
+    process_datagram,
+    state::ServerState,
+    transfer::{FileTransferError, MAX_FILE_SIZE, Transfers},
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+async fn recv_message(sock: &UdpSocket) -> MessageProtocol {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    MessageProtocol::deserialize(&buf[..len]).unwrap()
+}
+
+async fn assert_silent(sock: &UdpSocket) {
+    let mut buf = [0u8; BUFFER_SIZE];
+    assert!(
+        timeout(Duration::from_millis(100), sock.recv_from(&mut buf))
+            .await
+            .is_err()
+    );
+}
+
+async fn send(
+    state: &Arc<ServerState>,
+    server: &UdpSocket,
+    from: &UdpSocket,
+    message: MessageProtocol,
+) {
+    let frame = message.serialize().unwrap();
+    process_datagram(server, &frame, from.local_addr().unwrap(), state)
+        .await
+        .unwrap();
+}
+
+fn chunk(data: &[u8], index: u32) -> &[u8] {
+    let from = index as usize * FILE_CHUNK_SIZE;
+    &data[from..(from + FILE_CHUNK_SIZE).min(data.len())]
+}
+
+#[test]
+fn server_copy_is_bounded_and_verified() {
+    let data = vec![7u8; FILE_CHUNK_SIZE + 1];
+    let offer = FileOffer::for_data("f", &data);
+    let transfers = Transfers::new(1);
+    transfers.open(1, "alice", offer.clone());
+    assert_eq!(
+        transfers.receive(1, "bob", 0, chunk(&data, 0)),
+        Err(FileTransferError::NotSender(1))
+    );
+    let received = transfers.receive(1, "alice", 0, chunk(&data, 0)).unwrap();
+    assert!(received.accepted);
+    assert_eq!(received.next, 1);
+
+    // 中身が申し出と違えば、最後のチャンクで失敗して破棄する
+    assert!(matches!(
+        transfers.receive(1, "alice", 1, &[8u8]),
+        Err(FileTransferError::Failed(1, _))
+    ));
+    assert!(transfers.is_empty());
+
+    // 容量を超えると古い転送から捨てる
+    transfers.open(2, "alice", offer.clone());
+    transfers.open(3, "alice", offer);
+    assert_eq!(transfers.len(), 1);
+    assert!(transfers.progress(2).is_none());
+}
+
+#[tokio::test]
+async fn file_is_relayed_in_order_and_resumable() {
+    let state = Arc::new(ServerState::new(Arc::new(ClientManager::new(
+        Duration::from_secs(30),
+    ))));
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bob = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let carol = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    for (sock, name) in [(&alice, "alice"), (&bob, "bob"), (&carol, "carol")] {
+        send(&state, &server, sock, MessageProtocol::new(name, "joined")).await;
+    }
+    for sock in [&alice, &alice, &alice, &bob, &bob, &carol] {
+        recv_message(sock).await;
+    }
+
+    // ❶ 大きすぎるファイルは本人にだけ断られる
+    let huge = FileOffer {
+        size: MAX_FILE_SIZE + 1,
+        ..FileOffer::for_data("huge.bin", b"")
+    };
+    send(
+        &state,
+        &server,
+        &alice,
+        MessageProtocol::file_offer("alice", &huge),
+    )
+    .await;
+    let notice = recv_message(&alice).await;
+    assert!(notice.body.contains("too large"), "{}", notice.body);
+    assert_silent(&bob).await;
+
+    // ❷ 申し出は全員に届き、その ID が転送 ID になる
+    let data: Vec<u8> = (0..FILE_CHUNK_SIZE * 2 + 100).map(|i| i as u8).collect();
+    let offer = FileOffer::for_data("photo.png", &data);
+    send(
+        &state,
+        &server,
+        &alice,
+        MessageProtocol::file_offer("alice", &offer),
+    )
+    .await;
+    let announced = recv_message(&bob).await;
+    assert_eq!(announced.kind, MessageKind::FileOffer);
+    assert_eq!(announced.offer(), Some(offer.clone()));
+    let id = announced.id;
+    assert_eq!(recv_message(&alice).await.id, id);
+    recv_message(&carol).await;
+
+    // ❸ 順番どおりのチャンクは他の全員に中継され、送信者には次の位置が返る
+    send(
+        &state,
+        &server,
+        &alice,
+        MessageProtocol::file_chunk("alice", id, 0, chunk(&data, 0)),
+    )
+    .await;
+    let ack = recv_message(&alice).await;
+    assert_eq!(ack.kind, MessageKind::FileAck);
+    assert_eq!((ack.target_id, ack.ack_index()), (id, Some(1)));
+    let mut download = Download::new(offer.clone());
+    let relayed = recv_message(&bob).await;
+    assert_eq!(relayed.user_name, "alice");
+    let (index, bytes) = relayed.chunk().unwrap();
+    assert!(download.receive(index, &bytes).unwrap());
+    recv_message(&carol).await;
+
+    // ❹ 欠けの後ろのチャンクは中継せず、同じ位置を確認応答する
+    send(
+        &state,
+        &server,
+        &alice,
+        MessageProtocol::file_chunk("alice", id, 2, chunk(&data, 2)),
+    )
+    .await;
+    assert_eq!(recv_message(&alice).await.ack_index(), Some(1));
+    assert_silent(&bob).await;
+
+    // ❺ 送信者は照会で再開位置を知り、そこから送り直す
+    send(
+        &state,
+        &server,
+        &alice,
+        MessageProtocol::file_ack("alice", id, None),
+    )
+    .await;
+    assert_eq!(recv_message(&alice).await.ack_index(), Some(1));
+    for index in [1, 2] {
+        send(
+            &state,
+            &server,
+            &alice,
+            MessageProtocol::file_chunk("alice", id, index, chunk(&data, index)),
+        )
+        .await;
+        assert_eq!(recv_message(&alice).await.ack_index(), Some(index + 1));
+        let (index, bytes) = recv_message(&bob).await.chunk().unwrap();
+        assert!(download.receive(index, &bytes).unwrap());
+    }
+    assert_eq!(download.verified().unwrap(), data.as_slice());
+
+    // ❻ 取りこぼした受信者は、要求した位置からサーバの控えを受け取る
+    for _ in 0..2 {
+        recv_message(&carol).await;
+    }
+    send(
+        &state,
+        &server,
+        &carol,
+        MessageProtocol::file_ack("carol", id, Some(0)),
+    )
+    .await;
+    let mut resumed = Download::new(offer);
+    for _ in 0..3 {
+        let (index, bytes) = recv_message(&carol).await.chunk().unwrap();
+        assert!(resumed.receive(index, &bytes).unwrap());
+    }
+    assert_eq!(resumed.verified().unwrap(), data.as_slice());
+    assert_silent(&alice).await;
+
+    // ❼ 他人の転送にはチャンクを送れない
+    send(
+        &state,
+        &server,
+        &bob,
+        MessageProtocol::file_chunk("bob", id, 0, chunk(&data, 0)),
+    )
+    .await;
+    let notice = recv_message(&bob).await;
+    assert!(notice.body.contains("someone else"), "{}", notice.body);
+}
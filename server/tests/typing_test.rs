@@ -0,0 +1,130 @@
+//! Typing indicator integration test.
+//!
+//! 入力中通知が中継される一方で、履歴にも最終発言時刻にも影響しないこと、
+//! 連続した通知が間引かれることを確認する。
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::{MessageKind, MessageProtocol};
+use server::{
+    BUFFER_SIZE,
+    client_manager::ClientManager,
+    process_datagram,
+    state::ServerState,
+    typing::{TYPING_TTL, TypingTracker},
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+async fn recv_message(sock: &UdpSocket) -> MessageProtocol {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    MessageProtocol::deserialize(&buf[..len]).unwrap()
+}
+
+async fn assert_silent(sock: &UdpSocket) {
+    let mut buf = [0u8; BUFFER_SIZE];
+    assert!(
+        timeout(Duration::from_millis(100), sock.recv_from(&mut buf))
+            .await
+            .is_err()
+    );
+}
+
+async fn send(
+    state: &Arc<ServerState>,
+    server: &UdpSocket,
+    from: &UdpSocket,
+    message: MessageProtocol,
+) {
+    let frame = message.serialize().unwrap();
+    process_datagram(server, &frame, from.local_addr().unwrap(), state)
+        .await
+        .unwrap();
+}
+
+#[test]
+fn tracker_throttles_and_clears() {
+    let tracker = TypingTracker::new();
+    assert!(tracker.signal("alice"));
+    assert!(!tracker.signal("alice"), "間隔内の通知は中継しない");
+    assert!(tracker.signal("bob"));
+    assert_eq!(tracker.active(), vec!["alice", "bob"]);
+
+    // 発言後は次の通知をすぐに中継する
+    tracker.clear("alice");
+    assert_eq!(tracker.active(), vec!["bob"]);
+    assert!(tracker.signal("alice"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn tracker_forgets_users_who_stopped_typing() {
+    let tracker = TypingTracker::new();
+    for n in 0..100 {
+        tracker.signal(&format!("user{n}"));
+    }
+    assert_eq!(tracker.len(), 100);
+
+    // 期限切れの記録は新しい利用者の通知で消え、溜まり続けない
+    tokio::time::advance(TYPING_TTL).await;
+    assert!(tracker.signal("alice"));
+    assert_eq!(tracker.len(), 1);
+}
+
+#[tokio::test]
+async fn typing_is_relayed_but_not_stored() {
+    let state = Arc::new(ServerState::new(Arc::new(ClientManager::new(
+        Duration::from_secs(30),
+    ))));
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bob = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    // ❶ 参加前の通知は中継されず、クライアント登録もされない
+    send(&state, &server, &bob, MessageProtocol::typing("bob")).await;
+    assert_eq!(state.client_manager.active_client_count(), 0);
+
+    send(&state, &server, &alice, MessageProtocol::new("alice", "hi")).await;
+    recv_message(&alice).await;
+    send(&state, &server, &bob, MessageProtocol::new("bob", "hey")).await;
+    recv_message(&alice).await;
+    recv_message(&bob).await;
+    let last_seen = state
+        .client_manager
+        .clients_table
+        .get("bob")
+        .unwrap()
+        .last_message_time;
+
+    // ❷ 通知は ID なしで全員に届き、履歴と最終発言時刻は変わらない
+    send(&state, &server, &bob, MessageProtocol::typing("bob")).await;
+    for sock in [&alice, &bob] {
+        let typing = recv_message(sock).await;
+        assert_eq!(typing.kind, MessageKind::Typing);
+        assert_eq!(typing.user_name, "bob");
+        assert_eq!(typing.id, 0);
+        assert_ne!(typing.timestamp_ms, 0);
+    }
+    assert_eq!(state.history.len(), 2);
+    assert_eq!(state.messages_stamped(), 2);
+    assert_eq!(
+        state
+            .client_manager
+            .clients_table
+            .get("bob")
+            .unwrap()
+            .last_message_time,
+        last_seen
+    );
+
+    // ❸ 直後の通知は間引かれる
+    send(&state, &server, &bob, MessageProtocol::typing("bob")).await;
+    assert_silent(&alice).await;
+    assert_eq!(state.typing.active(), vec!["bob"]);
+
+    // ❹ 発言すると入力中の状態は消える
+    send(&state, &server, &bob, MessageProtocol::new("bob", "sent")).await;
+    assert!(state.typing.active().is_empty());
+}
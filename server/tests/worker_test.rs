@@ -0,0 +1,63 @@
+//! Relay integration test for the worker pool.
+//!
+//! エフェメラルポートでサーバを起動し、2 クライアント間の中継と
+//! 送信元ごとの順序保証を確認する。
+
+use std::{sync::Arc, time::Duration};
+
+use protocol::MessageProtocol;
+use server::{BUFFER_SIZE, client_manager::ClientManager, state::ServerState, worker::run_workers};
+use tokio::{net::UdpSocket, time::timeout};
+
+async fn recv_message(sock: &UdpSocket) -> MessageProtocol {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let (len, _) = timeout(Duration::from_secs(1), sock.recv_from(&mut buf))
+        .await
+        .expect("client timed out")
+        .unwrap();
+    MessageProtocol::deserialize(&buf[..len]).unwrap()
+}
+
+async fn send_message(sock: &UdpSocket, server: std::net::SocketAddr, user: &str, body: &str) {
+    let frame = MessageProtocol::new(user, body).serialize().unwrap();
+    sock.send_to(&frame, server).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn relays_to_all_clients_in_sender_order() {
+    // ❶ ワーカー 4 つでサーバを起動
+    let server_sock = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let server_addr = server_sock.local_addr().unwrap();
+    let manager = Arc::new(ClientManager::new(Duration::from_secs(30)));
+    tokio::spawn(run_workers(
+        server_sock,
+        Arc::new(ServerState::new(Arc::clone(&manager))),
+        4,
+    ));
+
+    let alice = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bob = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    // ❷ bob を登録（自分自身へのエコーを受け取る）
+    send_message(&bob, server_addr, "bob", "hi").await;
+    assert_eq!(recv_message(&bob).await.body, "hi");
+
+    // ❸ alice が連続送信したメッセージが順序通りに bob へ届くことを検証
+    for i in 0..20 {
+        send_message(&alice, server_addr, "alice", &i.to_string()).await;
+    }
+    let mut last_id = 0;
+    for i in 0..20 {
+        let msg = recv_message(&bob).await;
+        assert_eq!(msg.user_name, "alice");
+        assert_eq!(msg.body, i.to_string(), "送信元ごとの順序が保たれるべき");
+        // サーバが単調増加の ID と受信時刻を付与している
+        assert!(msg.id > last_id, "メッセージ ID は単調増加するべき");
+        assert!(msg.timestamp_ms > 0, "サーバの受信時刻が付与されるべき");
+        last_id = msg.id;
+    }
+
+    // ❹ alice 自身にもエコーされ、両者が登録されている
+    assert_eq!(recv_message(&alice).await.body, "0");
+    assert_eq!(manager.active_client_count(), 2);
+}
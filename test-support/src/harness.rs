@@ -0,0 +1,225 @@
+//! A server on an ephemeral port plus scripted clients.
+//!
+//! ```no_run
+//! # async fn scenario() -> std::io::Result<()> {
+//! use test_support::harness::TestServer;
+//!
+//! let server = TestServer::start().await?;
+//! let [alice, bob] = server.join(["alice", "bob"]).await?;
+//! alice.say("hi").await;
+//! bob.expect_from("alice", "hi").await;
+//! server.shutdown().await
+//! # }
+//! ```
+
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use protocol::MessageProtocol;
+use server::{
+    BUFFER_SIZE, client_manager::ClientManager, follow_client_events, state::ServerState,
+    worker::run_workers_until,
+};
+use tokio::{
+    net::UdpSocket,
+    sync::Notify,
+    task::JoinHandle,
+    time::{Instant, timeout},
+};
+
+/// How long a client waits for an expected frame.
+pub const RECV_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long a client listens before concluding nothing is coming.
+pub const SILENCE: Duration = Duration::from_millis(100);
+/// Workers the test server processes datagrams on.
+pub const TEST_WORKERS: usize = 2;
+
+/// Stops a [`TestServer`] from receiving; cheap to clone into other tasks.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    notify: Arc<Notify>,
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        // 受信ループがまだ待っていなくても合図が残る
+        self.notify.notify_one();
+    }
+}
+
+/// A server bound to `127.0.0.1:0`; shut down when dropped.
+pub struct TestServer {
+    addr: SocketAddr,
+    state: Arc<ServerState>,
+    handle: ShutdownHandle,
+    task: Option<JoinHandle<io::Result<()>>>,
+    events: JoinHandle<()>,
+}
+
+impl TestServer {
+    /// Start a server with a 30 second client timeout.
+    pub async fn start() -> io::Result<Self> {
+        let manager = ClientManager::new(Duration::from_secs(30));
+        Self::start_with(Arc::new(ServerState::new(Arc::new(manager)))).await
+    }
+
+    /// Start a server over prepared state, e.g. with moderation loaded.
+    pub async fn start_with(state: Arc<ServerState>) -> io::Result<Self> {
+        let sock = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        // 入退室はサーバ本体と同じようにフックへ伝える
+        let events = tokio::spawn(follow_client_events(
+            Arc::clone(&state),
+            state.client_manager.subscribe(),
+        ));
+        let addr = sock.local_addr()?;
+        let handle = ShutdownHandle::default();
+        let notify = Arc::clone(&handle.notify);
+        let task = tokio::spawn(run_workers_until(
+            sock,
+            Arc::clone(&state),
+            TEST_WORKERS,
+            async move { notify.notified().await },
+        ));
+        Ok(Self {
+            addr,
+            state,
+            handle,
+            task: Some(task),
+            events,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn state(&self) -> &Arc<ServerState> {
+        &self.state
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.handle.clone()
+    }
+
+    /// A client bound to an ephemeral port that has not sent anything yet.
+    pub async fn client(&self, name: &str) -> io::Result<TestClient> {
+        TestClient::connect(self.addr, name).await
+    }
+
+    /// Clients that have each joined in turn, with every join echo consumed.
+    pub async fn join<const N: usize>(&self, names: [&str; N]) -> io::Result<[TestClient; N]> {
+        let mut clients: Vec<TestClient> = Vec::with_capacity(N);
+        for name in names {
+            let client = self.client(name).await?;
+            client.say("joined").await;
+            // 参加の知らせは本人を含む全員に届く
+            for other in clients.iter().chain([&client]) {
+                other.expect_from(name, "joined").await;
+            }
+            clients.push(client);
+        }
+        Ok(clients.try_into().ok().expect("one client per name"))
+    }
+
+    /// Stop receiving and wait for the receive loop to finish.
+    pub async fn shutdown(mut self) -> io::Result<()> {
+        self.handle.shutdown();
+        self.events.abort();
+        let task = self.task.take().expect("shut down twice");
+        task.await.map_err(io::Error::other)?
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.handle.shutdown();
+        self.events.abort();
+    }
+}
+
+/// One user talking to the server; every helper panics on failure.
+pub struct TestClient {
+    name: String,
+    sock: UdpSocket,
+    server: SocketAddr,
+}
+
+impl TestClient {
+    pub async fn connect(server: SocketAddr, name: &str) -> io::Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            sock: UdpSocket::bind("127.0.0.1:0").await?,
+            server,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.sock.local_addr().expect("bound socket")
+    }
+
+    /// Send a wire-encoded frame.
+    pub async fn send(&self, message: MessageProtocol) {
+        let frame = message.serialize().expect("frame fits");
+        self.sock
+            .send_to(&frame, self.server)
+            .await
+            .expect("send to server");
+    }
+
+    /// Send a chat message as this client.
+    pub async fn say(&self, body: &str) {
+        self.send(MessageProtocol::new(self.name.as_str(), body))
+            .await;
+    }
+
+    /// Send a direct message as this client.
+    pub async fn whisper(&self, to: &str, body: &str) {
+        self.send(MessageProtocol::direct(self.name.as_str(), to, body))
+            .await;
+    }
+
+    /// The next frame, or `None` if nothing arrives within `wait`.
+    pub async fn try_recv(&self, wait: Duration) -> Option<MessageProtocol> {
+        let mut buf = [0u8; BUFFER_SIZE];
+        let deadline = Instant::now() + wait;
+        loop {
+            let (len, _) = timeout(deadline - Instant::now(), self.sock.recv_from(&mut buf))
+                .await
+                .ok()?
+                .expect("receive from server");
+            // 解析できないものは読み飛ばす
+            if let Ok(message) = MessageProtocol::deserialize(&buf[..len]) {
+                return Some(message);
+            }
+        }
+    }
+
+    /// The next frame; panics after [`RECV_TIMEOUT`].
+    pub async fn recv(&self) -> MessageProtocol {
+        self.try_recv(RECV_TIMEOUT)
+            .await
+            .unwrap_or_else(|| panic!("{} received nothing", self.name))
+    }
+
+    /// Expect the next frame to be `body` from `from`.
+    pub async fn expect_from(&self, from: &str, body: &str) -> MessageProtocol {
+        let message = self.recv().await;
+        assert_eq!(
+            (message.user_name.as_str(), message.body.as_str()),
+            (from, body),
+            "{} got an unexpected frame: {message:?}",
+            self.name
+        );
+        message
+    }
+
+    /// Expect nothing to arrive for [`SILENCE`].
+    pub async fn expect_silence(&self) {
+        if let Some(message) = self.try_recv(SILENCE).await {
+            panic!("{} expected silence, got {message:?}", self.name);
+        }
+    }
+}
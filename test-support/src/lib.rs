@@ -0,0 +1,10 @@
+//! Helpers shared by the integration tests of the workspace.
+//!
+//! [`harness::TestServer`] runs the server on an ephemeral port and hands out
+//! scripted [`harness::TestClient`]s, so tests can run in parallel.
+//! [`proxy::ImpairedProxy`] sits between the server and its clients and
+//! drops, duplicates, reorders, delays or truncates datagrams with
+//! configured probabilities, reproducibly for a given seed.
+
+pub mod harness;
+pub mod proxy;
@@ -0,0 +1,321 @@
+//! In-process UDP proxy that impairs the traffic passing through it.
+//!
+//! Clients send to [`ImpairedProxy::addr`] instead of the server. Each client
+//! gets its own upstream socket, so the server sees one address per client
+//! and its replies find their way back. Both directions of every client are
+//! impaired independently, each with a random generator seeded from the
+//! proxy's seed and the order in which clients first appeared.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use tokio::{
+    net::UdpSocket,
+    sync::mpsc,
+    task::{JoinHandle, JoinSet},
+    time::{sleep, timeout},
+};
+
+// 転送途中のデータグラムを溜めておける数
+const QUEUE_DEPTH: usize = 1024;
+// 受信バッファ（プロトコルの上限より大きければよい）
+const DATAGRAM_SIZE: usize = 64 * 1024;
+
+/// Probability of each impairment, applied to every datagram in both directions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Impairments {
+    /// Drop the datagram.
+    pub loss: f64,
+    /// Send the datagram twice.
+    pub duplicate: f64,
+    /// Hold the datagram back until the next one has been sent.
+    pub reorder: f64,
+    /// Send the datagram after [`Impairments::delay_by`].
+    pub delay: f64,
+    /// Cut the datagram to a random shorter length.
+    pub truncate: f64,
+    /// Added latency of a delayed datagram, and the longest a reordered one
+    /// waits for a successor.
+    pub delay_by: Duration,
+}
+
+impl Impairments {
+    /// A perfect network.
+    pub const NONE: Self = Self {
+        loss: 0.0,
+        duplicate: 0.0,
+        reorder: 0.0,
+        delay: 0.0,
+        truncate: 0.0,
+        delay_by: Duration::from_millis(20),
+    };
+}
+
+impl Default for Impairments {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// What the proxy has done so far, over both directions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProxyStats {
+    pub received: u64,
+    pub sent: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+    pub delayed: u64,
+    pub truncated: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    received: AtomicU64,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    duplicated: AtomicU64,
+    reordered: AtomicU64,
+    delayed: AtomicU64,
+    truncated: AtomicU64,
+}
+
+impl Counters {
+    fn snapshot(&self) -> ProxyStats {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        ProxyStats {
+            received: get(&self.received),
+            sent: get(&self.sent),
+            dropped: get(&self.dropped),
+            duplicated: get(&self.duplicated),
+            reordered: get(&self.reordered),
+            delayed: get(&self.delayed),
+            truncated: get(&self.truncated),
+        }
+    }
+}
+
+fn bump(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Seeded decisions for one direction of one path.
+///
+/// The same seed, configuration and input always give the same output.
+pub struct Impairer {
+    rng: StdRng,
+    held: Option<Vec<u8>>,
+    counters: Arc<Counters>,
+}
+
+impl Impairer {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            held: None,
+            counters: Arc::default(),
+        }
+    }
+
+    /// Impair one datagram. Returns what to send now, each with its delay.
+    pub fn apply(&mut self, config: &Impairments, datagram: &[u8]) -> Vec<(Duration, Vec<u8>)> {
+        bump(&self.counters.received);
+        if self.rng.random_bool(config.loss) {
+            bump(&self.counters.dropped);
+            return Vec::new();
+        }
+        let mut datagram = datagram.to_vec();
+        if !datagram.is_empty() && self.rng.random_bool(config.truncate) {
+            datagram.truncate(self.rng.random_range(0..datagram.len()));
+            bump(&self.counters.truncated);
+        }
+        // 一度に抱えるのは 1 つだけで、次のデータグラムに追い越させる
+        if self.held.is_none() && self.rng.random_bool(config.reorder) {
+            bump(&self.counters.reordered);
+            self.held = Some(datagram);
+            return Vec::new();
+        }
+        let copies = if self.rng.random_bool(config.duplicate) {
+            bump(&self.counters.duplicated);
+            2
+        } else {
+            1
+        };
+        let delay = if self.rng.random_bool(config.delay) {
+            bump(&self.counters.delayed);
+            config.delay_by
+        } else {
+            Duration::ZERO
+        };
+        let mut out = vec![(delay, datagram); copies];
+        out.extend(self.held.take().map(|held| (Duration::ZERO, held)));
+        out
+    }
+
+    /// Release a datagram still held for reordering.
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        self.held.take()
+    }
+
+    pub fn is_holding(&self) -> bool {
+        self.held.is_some()
+    }
+}
+
+/// A running proxy; stops when dropped.
+pub struct ImpairedProxy {
+    addr: SocketAddr,
+    config: Arc<Mutex<Impairments>>,
+    counters: Arc<Counters>,
+    task: JoinHandle<()>,
+}
+
+impl ImpairedProxy {
+    /// Listen on an ephemeral loopback port and forward to `upstream`.
+    pub async fn start(upstream: SocketAddr, config: Impairments, seed: u64) -> io::Result<Self> {
+        let front = Arc::new(UdpSocket::bind(SocketAddr::new(loopback(upstream), 0)).await?);
+        let addr = front.local_addr()?;
+        let config = Arc::new(Mutex::new(config));
+        let counters = Arc::new(Counters::default());
+        let task = tokio::spawn(run(front, upstream, seed, config.clone(), counters.clone()));
+        Ok(Self {
+            addr,
+            config,
+            counters,
+            task,
+        })
+    }
+
+    /// Where clients should send instead of the server.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Change the impairments from the next datagram on, e.g. to heal a partition.
+    pub fn set_impairments(&self, config: Impairments) {
+        *self.config.lock().unwrap() = config;
+    }
+
+    pub fn stats(&self) -> ProxyStats {
+        self.counters.snapshot()
+    }
+}
+
+impl Drop for ImpairedProxy {
+    fn drop(&mut self) {
+        // 経路ごとのタスクは JoinSet ごと破棄される
+        self.task.abort();
+    }
+}
+
+fn loopback(upstream: SocketAddr) -> IpAddr {
+    match upstream {
+        SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+        SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+    }
+}
+
+async fn run(
+    front: Arc<UdpSocket>,
+    upstream: SocketAddr,
+    seed: u64,
+    config: Arc<Mutex<Impairments>>,
+    counters: Arc<Counters>,
+) {
+    let mut tasks = JoinSet::new();
+    let mut paths: HashMap<SocketAddr, mpsc::Sender<Vec<u8>>> = HashMap::new();
+    let mut buf = vec![0u8; DATAGRAM_SIZE];
+    while let Ok((len, client)) = front.recv_from(&mut buf).await {
+        if !paths.contains_key(&client) {
+            let Ok(back) = UdpSocket::bind(SocketAddr::new(loopback(upstream), 0)).await else {
+                continue;
+            };
+            let back = Arc::new(back);
+            // 経路ごと・向きごとに別の乱数列を使う
+            let path_seed = seed.wrapping_add(2 * paths.len() as u64);
+            let impairer = |offset| Impairer {
+                counters: counters.clone(),
+                ..Impairer::new(path_seed.wrapping_add(offset))
+            };
+
+            let (up_tx, up_rx) = mpsc::channel(QUEUE_DEPTH);
+            tasks.spawn(forward(
+                up_rx,
+                back.clone(),
+                upstream,
+                impairer(0),
+                config.clone(),
+            ));
+            let (down_tx, down_rx) = mpsc::channel(QUEUE_DEPTH);
+            tasks.spawn(forward(
+                down_rx,
+                front.clone(),
+                client,
+                impairer(1),
+                config.clone(),
+            ));
+            tasks.spawn(read_into(back, down_tx));
+            paths.insert(client, up_tx);
+        }
+        // 詰まった経路は落とす（実際のネットワークと同じ）
+        let _ = paths[&client].try_send(buf[..len].to_vec());
+    }
+}
+
+async fn read_into(sock: Arc<UdpSocket>, tx: mpsc::Sender<Vec<u8>>) {
+    let mut buf = vec![0u8; DATAGRAM_SIZE];
+    while let Ok((len, _)) = sock.recv_from(&mut buf).await {
+        let _ = tx.try_send(buf[..len].to_vec());
+    }
+}
+
+async fn forward(
+    mut rx: mpsc::Receiver<Vec<u8>>,
+    sock: Arc<UdpSocket>,
+    to: SocketAddr,
+    mut impairer: Impairer,
+    config: Arc<Mutex<Impairments>>,
+) {
+    let mut delayed = JoinSet::new();
+    loop {
+        let current = || *config.lock().unwrap();
+        let next = if impairer.is_holding() {
+            timeout(current().delay_by, rx.recv()).await
+        } else {
+            Ok(rx.recv().await)
+        };
+        // 設定は受け取った時点のものを使う
+        let out = match next {
+            Ok(Some(datagram)) => impairer.apply(&current(), &datagram),
+            Ok(None) => break,
+            // 後続が来ないまま待ちすぎたものは、そのまま送る
+            Err(_) => impairer
+                .flush()
+                .map(|held| (Duration::ZERO, held))
+                .into_iter()
+                .collect(),
+        };
+        for (delay, datagram) in out {
+            bump(&impairer.counters.sent);
+            if delay.is_zero() {
+                let _ = sock.send_to(&datagram, to).await;
+            } else {
+                let sock = sock.clone();
+                delayed.spawn(async move {
+                    sleep(delay).await;
+                    let _ = sock.send_to(&datagram, to).await;
+                });
+            }
+        }
+        while delayed.try_join_next().is_some() {}
+    }
+}
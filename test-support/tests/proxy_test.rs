@@ -0,0 +1,115 @@
+//! Impaired proxy integration test.
+//!
+//! 同じシードなら同じ劣化が起きること、各劣化が設定どおりに働くこと、
+//! プロキシ越しに往復でき、途中で設定を変えられることを確認する。
+
+use std::{net::SocketAddr, time::Duration};
+
+use test_support::proxy::{ImpairedProxy, Impairer, Impairments};
+use tokio::{net::UdpSocket, time::timeout};
+
+fn run(seed: u64, config: &Impairments) -> Vec<(Duration, Vec<u8>)> {
+    let mut impairer = Impairer::new(seed);
+    (0..200u8)
+        .flat_map(|i| impairer.apply(config, &[i; 16]))
+        .collect()
+}
+
+#[test]
+fn impairments_are_reproducible() {
+    let config = Impairments {
+        loss: 0.2,
+        duplicate: 0.2,
+        reorder: 0.2,
+        delay: 0.2,
+        truncate: 0.2,
+        ..Impairments::NONE
+    };
+    assert_eq!(run(7, &config), run(7, &config));
+    assert_ne!(run(7, &config), run(8, &config));
+    assert_eq!(run(7, &Impairments::NONE).len(), 200);
+}
+
+#[test]
+fn each_impairment_applies() {
+    let always = |f: fn(&mut Impairments)| {
+        let mut config = Impairments::NONE;
+        f(&mut config);
+        config
+    };
+    let mut impairer = Impairer::new(1);
+
+    // ❶ 損失・重複・遅延・切り詰め
+    assert!(impairer.apply(&always(|c| c.loss = 1.0), b"a").is_empty());
+    assert_eq!(
+        impairer.apply(&always(|c| c.duplicate = 1.0), b"a"),
+        vec![(Duration::ZERO, b"a".to_vec()); 2]
+    );
+    let delayed = always(|c| c.delay = 1.0);
+    assert_eq!(
+        impairer.apply(&delayed, b"a"),
+        vec![(delayed.delay_by, b"a".to_vec())]
+    );
+    let out = impairer.apply(&always(|c| c.truncate = 1.0), b"abcdef");
+    assert!(out[0].1.len() < 6 && b"abcdef".starts_with(&out[0].1));
+
+    // ❷ 並べ替えは 1 つ抱え、次のものに追い越させる
+    let reorder = always(|c| c.reorder = 1.0);
+    assert!(impairer.apply(&reorder, b"1").is_empty());
+    assert!(impairer.is_holding());
+    let sent: Vec<_> = impairer
+        .apply(&reorder, b"2")
+        .into_iter()
+        .map(|(_, d)| d)
+        .collect();
+    assert_eq!(sent, [b"2".to_vec(), b"1".to_vec()]);
+    assert!(impairer.apply(&reorder, b"3").is_empty());
+    assert_eq!(impairer.flush(), Some(b"3".to_vec()));
+}
+
+async fn recv(sock: &UdpSocket, wait: Duration) -> Option<Vec<u8>> {
+    let mut buf = [0u8; 64];
+    let (len, _) = timeout(wait, sock.recv_from(&mut buf)).await.ok()?.unwrap();
+    Some(buf[..len].to_vec())
+}
+
+#[tokio::test]
+async fn proxy_forwards_both_ways() {
+    // ❶ 受け取ったものをそのまま返す上流
+    let echo = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let upstream: SocketAddr = echo.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 64];
+        while let Ok((len, from)) = echo.recv_from(&mut buf).await {
+            echo.send_to(&buf[..len], from).await.unwrap();
+        }
+    });
+    let proxy = ImpairedProxy::start(upstream, Impairments::NONE, 42)
+        .await
+        .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    // ❷ 劣化なしなら往復する
+    client.send_to(b"ping", proxy.addr()).await.unwrap();
+    let wait = Duration::from_secs(1);
+    assert_eq!(recv(&client, wait).await.as_deref(), Some(&b"ping"[..]));
+
+    // ❸ 分断すると届かず、直すとまた届く
+    proxy.set_impairments(Impairments {
+        loss: 1.0,
+        ..Impairments::NONE
+    });
+    client.send_to(b"lost", proxy.addr()).await.unwrap();
+    assert_eq!(recv(&client, Duration::from_millis(100)).await, None);
+    proxy.set_impairments(Impairments {
+        delay: 1.0,
+        ..Impairments::NONE
+    });
+    client.send_to(b"late", proxy.addr()).await.unwrap();
+    assert_eq!(recv(&client, wait).await.as_deref(), Some(&b"late"[..]));
+
+    let stats = proxy.stats();
+    assert_eq!((stats.dropped, stats.delayed), (1, 2));
+    assert_eq!(stats.received, 5);
+    assert_eq!(stats.sent, 4);
+}